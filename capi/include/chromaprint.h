@@ -0,0 +1,61 @@
+/* C API of rusty-chromaprint, compatible with chromaprint.h from libchromaprint. */
+#ifndef CHROMAPRINT_CHROMAPRINT_H_
+#define CHROMAPRINT_CHROMAPRINT_H_
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#include <stdint.h>
+
+#define CHROMAPRINT_VERSION_MAJOR 1
+#define CHROMAPRINT_VERSION_MINOR 5
+#define CHROMAPRINT_VERSION_PATCH 1
+
+struct ChromaprintContextPrivate;
+typedef struct ChromaprintContextPrivate ChromaprintContext;
+
+enum ChromaprintAlgorithm {
+	CHROMAPRINT_ALGORITHM_TEST1 = 0,
+	CHROMAPRINT_ALGORITHM_TEST2,
+	CHROMAPRINT_ALGORITHM_TEST3,
+	CHROMAPRINT_ALGORITHM_TEST4,
+	CHROMAPRINT_ALGORITHM_TEST5,
+	CHROMAPRINT_ALGORITHM_DEFAULT = CHROMAPRINT_ALGORITHM_TEST2,
+};
+
+const char *chromaprint_get_version(void);
+
+ChromaprintContext *chromaprint_new(int algorithm);
+void chromaprint_free(ChromaprintContext *ctx);
+int chromaprint_get_algorithm(ChromaprintContext *ctx);
+int chromaprint_set_option(ChromaprintContext *ctx, const char *name, int value);
+
+int chromaprint_get_num_channels(ChromaprintContext *ctx);
+int chromaprint_get_sample_rate(ChromaprintContext *ctx);
+int chromaprint_get_item_duration(ChromaprintContext *ctx);
+int chromaprint_get_item_duration_ms(ChromaprintContext *ctx);
+int chromaprint_get_delay(ChromaprintContext *ctx);
+int chromaprint_get_delay_ms(ChromaprintContext *ctx);
+
+int chromaprint_start(ChromaprintContext *ctx, int sample_rate, int num_channels);
+int chromaprint_feed(ChromaprintContext *ctx, const int16_t *data, int size);
+int chromaprint_finish(ChromaprintContext *ctx);
+
+int chromaprint_get_fingerprint(ChromaprintContext *ctx, char **fingerprint);
+int chromaprint_get_raw_fingerprint(ChromaprintContext *ctx, uint32_t **fingerprint, int *size);
+int chromaprint_get_raw_fingerprint_size(ChromaprintContext *ctx, int *size);
+int chromaprint_get_fingerprint_hash(ChromaprintContext *ctx, uint32_t *hash);
+int chromaprint_clear_fingerprint(ChromaprintContext *ctx);
+
+int chromaprint_encode_fingerprint(const uint32_t *fp, int size, int algorithm, char **encoded_fp, int *encoded_size, int base64);
+int chromaprint_decode_fingerprint(const char *encoded_fp, int encoded_size, uint32_t **fp, int *size, int *algorithm, int base64);
+int chromaprint_hash_fingerprint(const uint32_t *fp, int size, uint32_t *hash);
+
+void chromaprint_dealloc(void *ptr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
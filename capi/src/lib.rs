@@ -0,0 +1,660 @@
+//! C API compatible with `chromaprint.h` from libchromaprint.
+//!
+//! Functions returning `int` return 1 on success and 0 on failure. A panic inside a function
+//! is caught and reported as a failure, so it never unwinds into the caller.
+//! Memory returned through output pointers must be released with [chromaprint_dealloc].
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use rusty_chromaprint::{Configuration, Fingerprint, Fingerprinter, ResamplerKind};
+
+/// Version of libchromaprint whose API is implemented.
+const VERSION: &CStr = c"1.5.1";
+
+/// Fingerprinting context created by [chromaprint_new].
+pub struct ChromaprintContext {
+    config: Configuration,
+    printer: Fingerprinter,
+}
+
+/// Returns the version of the implemented API.
+#[no_mangle]
+pub extern "C" fn chromaprint_get_version() -> *const c_char {
+    VERSION.as_ptr()
+}
+
+/// Creates a new context using the given algorithm or returns NULL if there is no such algorithm
+/// or its preset cannot be used for fingerprinting.
+///
+/// Audio is resampled the same way as in libchromaprint, so the fingerprints are identical.
+#[no_mangle]
+pub extern "C" fn chromaprint_new(algorithm: c_int) -> *mut ChromaprintContext {
+    catch_panic(ptr::null_mut(), || {
+        let Some(config) = u8::try_from(algorithm).ok().and_then(Configuration::preset) else {
+            return ptr::null_mut();
+        };
+        let config = config.with_resampler(ResamplerKind::Chromaprint);
+        if config.validate().is_err() {
+            return ptr::null_mut();
+        }
+        Box::into_raw(Box::new(ChromaprintContext {
+            printer: Fingerprinter::new(&config),
+            config,
+        }))
+    })
+}
+
+/// Deallocates the context.
+///
+/// # Safety
+/// `ctx` must be NULL or a pointer returned by [chromaprint_new] that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_free(ctx: *mut ChromaprintContext) {
+    catch_panic((), || {
+        if !ctx.is_null() {
+            drop(Box::from_raw(ctx));
+        }
+    })
+}
+
+/// Returns the algorithm used by the context.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_algorithm(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(-1, || match ctx.as_ref() {
+        Some(ctx) => c_int::from(ctx.config.id()),
+        None => -1,
+    })
+}
+
+/// Sets an option of the fingerprinting algorithm, taking effect with the next [chromaprint_start].
+///
+/// The only option is `silence_threshold`, supported by algorithms removing the silence.
+///
+/// # Safety
+/// `ctx` must be a valid context and `name` NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_set_option(
+    ctx: *mut ChromaprintContext,
+    name: *const c_char,
+    value: c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), false, Ok(value)) = (ctx.as_mut(), name.is_null(), u32::try_from(value))
+        else {
+            return 0;
+        };
+        if CStr::from_ptr(name) != c"silence_threshold" || ctx.config.silence_threshold().is_none()
+        {
+            return 0;
+        }
+        // Changing the threshold keeps the algorithm, like in libchromaprint.
+        let algorithm = ctx.config.algorithm();
+        ctx.config = ctx
+            .config
+            .clone()
+            .with_removed_silence(value)
+            .with_algorithm(algorithm);
+        ctx.printer = Fingerprinter::new(&ctx.config);
+        1
+    })
+}
+
+/// Returns the number of channels of the audio processed internally.
+#[no_mangle]
+pub extern "C" fn chromaprint_get_num_channels(_ctx: *mut ChromaprintContext) -> c_int {
+    1
+}
+
+/// Returns the sample rate of the audio processed internally.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_sample_rate(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || match ctx.as_ref() {
+        Some(ctx) => ctx.config.sample_rate() as c_int,
+        None => 0,
+    })
+}
+
+/// Returns the duration of a single fingerprint item in samples.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_item_duration(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || match ctx.as_ref() {
+        Some(ctx) => (ctx.config.item_duration_in_seconds() * ctx.config.sample_rate() as f32)
+            .round() as c_int,
+        None => 0,
+    })
+}
+
+/// Returns the duration of a single fingerprint item in milliseconds.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_item_duration_ms(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || samples_to_ms(ctx, chromaprint_get_item_duration(ctx)))
+}
+
+/// Returns the delay of the fingerprint relative to the audio in samples.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_delay(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || match ctx.as_ref() {
+        Some(ctx) => ctx.config.delay() as c_int,
+        None => 0,
+    })
+}
+
+/// Returns the delay of the fingerprint relative to the audio in milliseconds.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_delay_ms(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || samples_to_ms(ctx, chromaprint_get_delay(ctx)))
+}
+
+unsafe fn samples_to_ms(ctx: *mut ChromaprintContext, samples: c_int) -> c_int {
+    match chromaprint_get_sample_rate(ctx) {
+        0 => 0,
+        sample_rate => (i64::from(samples) * 1000 / i64::from(sample_rate)) as c_int,
+    }
+}
+
+/// Restarts the computation of a fingerprint with a new audio stream.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_start(
+    ctx: *mut ChromaprintContext,
+    sample_rate: c_int,
+    num_channels: c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Ok(sample_rate), Ok(num_channels)) = (
+            ctx.as_mut(),
+            u32::try_from(sample_rate),
+            u32::try_from(num_channels),
+        ) else {
+            return 0;
+        };
+        ctx.printer.start(sample_rate, num_channels).is_ok() as c_int
+    })
+}
+
+/// Sends `size` interleaved samples (of all channels) to the fingerprint calculator.
+///
+/// # Safety
+/// `ctx` must be a valid context and `data` must point to at least `size` samples.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_feed(
+    ctx: *mut ChromaprintContext,
+    data: *const i16,
+    size: c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Ok(size)) = (ctx.as_mut(), usize::try_from(size)) else {
+            return 0;
+        };
+        if size == 0 {
+            return 1;
+        }
+        if data.is_null() {
+            return 0;
+        }
+        let data = std::slice::from_raw_parts(data, size);
+        ctx.printer.consume(data).is_ok() as c_int
+    })
+}
+
+/// Processes any remaining buffered audio and calculates the fingerprint.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_finish(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || {
+        let Some(ctx) = ctx.as_mut() else {
+            return 0;
+        };
+        ctx.printer.finish();
+        1
+    })
+}
+
+/// Returns the calculated fingerprint as a compressed, base64-encoded string.
+///
+/// # Safety
+/// `ctx` must be a valid context and `fingerprint` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_fingerprint(
+    ctx: *mut ChromaprintContext,
+    fingerprint: *mut *mut c_char,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Some(fingerprint)) = (ctx.as_ref(), fingerprint.as_mut()) else {
+            return 0;
+        };
+        let encoded = Fingerprint::new(ctx.config.id(), ctx.printer.fingerprint().to_vec());
+        *fingerprint = alloc_string(encoded.to_string().as_bytes());
+        (!fingerprint.is_null()) as c_int
+    })
+}
+
+/// Returns the calculated fingerprint as an array of 32-bit integers.
+///
+/// # Safety
+/// `ctx` must be a valid context, `fingerprint` and `size` valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_raw_fingerprint(
+    ctx: *mut ChromaprintContext,
+    fingerprint: *mut *mut u32,
+    size: *mut c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Some(fingerprint), Some(size)) =
+            (ctx.as_ref(), fingerprint.as_mut(), size.as_mut())
+        else {
+            return 0;
+        };
+        let items = ctx.printer.fingerprint();
+        *fingerprint = alloc_copy(items);
+        *size = items.len() as c_int;
+        (!fingerprint.is_null()) as c_int
+    })
+}
+
+/// Returns the number of items in the calculated fingerprint.
+///
+/// # Safety
+/// `ctx` must be a valid context and `size` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_raw_fingerprint_size(
+    ctx: *mut ChromaprintContext,
+    size: *mut c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Some(size)) = (ctx.as_ref(), size.as_mut()) else {
+            return 0;
+        };
+        *size = ctx.printer.fingerprint().len() as c_int;
+        1
+    })
+}
+
+/// Returns a 32-bit SimHash of the calculated fingerprint.
+///
+/// # Safety
+/// `ctx` must be a valid context and `hash` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_get_fingerprint_hash(
+    ctx: *mut ChromaprintContext,
+    hash: *mut u32,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(ctx), Some(hash)) = (ctx.as_ref(), hash.as_mut()) else {
+            return 0;
+        };
+        *hash = simhash(ctx.printer.fingerprint());
+        1
+    })
+}
+
+/// Clears the calculated fingerprint, but allows to continue feeding the audio.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_clear_fingerprint(ctx: *mut ChromaprintContext) -> c_int {
+    catch_panic(0, || {
+        let Some(ctx) = ctx.as_mut() else {
+            return 0;
+        };
+        ctx.printer.clear_fingerprint();
+        1
+    })
+}
+
+/// Compresses a raw fingerprint and optionally encodes it using base64.
+///
+/// # Safety
+/// `fp` must point to at least `size` items, `encoded_fp` and `encoded_size` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_encode_fingerprint(
+    fp: *const u32,
+    size: c_int,
+    algorithm: c_int,
+    encoded_fp: *mut *mut c_char,
+    encoded_size: *mut c_int,
+    base64: c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(encoded_fp), Some(encoded_size), Ok(algorithm)) = (
+            encoded_fp.as_mut(),
+            encoded_size.as_mut(),
+            u8::try_from(algorithm),
+        ) else {
+            return 0;
+        };
+        let Some(items) = slice_from_raw(fp, size) else {
+            return 0;
+        };
+        let fingerprint = Fingerprint::new(algorithm, items.to_vec());
+        let encoded = if base64 != 0 {
+            fingerprint.to_string().into_bytes()
+        } else {
+            fingerprint.compress()
+        };
+        *encoded_fp = alloc_string(&encoded);
+        *encoded_size = encoded.len() as c_int;
+        (!encoded_fp.is_null()) as c_int
+    })
+}
+
+/// Decodes and uncompresses a fingerprint compressed by [chromaprint_encode_fingerprint].
+///
+/// # Safety
+/// `encoded_fp` must point to at least `encoded_size` bytes, `fp`, `size` and `algorithm`
+/// must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_decode_fingerprint(
+    encoded_fp: *const c_char,
+    encoded_size: c_int,
+    fp: *mut *mut u32,
+    size: *mut c_int,
+    algorithm: *mut c_int,
+    base64: c_int,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(fp), Some(size)) = (fp.as_mut(), size.as_mut()) else {
+            return 0;
+        };
+        let Some(encoded) = slice_from_raw(encoded_fp as *const u8, encoded_size) else {
+            return 0;
+        };
+        let fingerprint = if base64 != 0 {
+            std::str::from_utf8(encoded)
+                .ok()
+                .and_then(|encoded| encoded.parse::<Fingerprint>().ok())
+        } else {
+            Fingerprint::decompress(encoded).ok()
+        };
+        let Some(fingerprint) = fingerprint else {
+            return 0;
+        };
+
+        *fp = alloc_copy(fingerprint.items());
+        *size = fingerprint.items().len() as c_int;
+        if let Some(algorithm) = algorithm.as_mut() {
+            *algorithm = c_int::from(fingerprint.algorithm());
+        }
+        (!fp.is_null()) as c_int
+    })
+}
+
+/// Calculates a 32-bit SimHash of a raw fingerprint.
+///
+/// # Safety
+/// `fp` must point to at least `size` items and `hash` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_hash_fingerprint(
+    fp: *const u32,
+    size: c_int,
+    hash: *mut u32,
+) -> c_int {
+    catch_panic(0, || {
+        let (Some(items), Some(hash)) = (slice_from_raw(fp, size), hash.as_mut()) else {
+            return 0;
+        };
+        *hash = simhash(items);
+        1
+    })
+}
+
+/// Frees memory allocated by any function from the library.
+///
+/// # Safety
+/// `ptr` must be NULL or a pointer returned by the library that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn chromaprint_dealloc(ptr: *mut c_void) {
+    libc::free(ptr);
+}
+
+/// Runs the body of an exported function, returning `default` instead of unwinding into C on a panic.
+fn catch_panic<T>(default: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+/// Each bit of the hash is set if it's set in the majority of items.
+fn simhash(fp: &[u32]) -> u32 {
+    let mut counts = [0i32; 32];
+    for item in fp {
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if item & (1 << bit) != 0 {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit))
+}
+
+unsafe fn slice_from_raw<'a, T>(data: *const T, size: c_int) -> Option<&'a [T]> {
+    let size = usize::try_from(size).ok()?;
+    if size == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(std::slice::from_raw_parts(data, size))
+    }
+}
+
+/// Copies the items into memory allocated with `malloc`.
+unsafe fn alloc_copy<T: Copy>(items: &[T]) -> *mut T {
+    // Allocate at least one item, so an empty slice doesn't look like an allocation failure.
+    let ptr = libc::malloc(std::mem::size_of_val(items).max(std::mem::size_of::<T>())) as *mut T;
+    if !ptr.is_null() {
+        ptr::copy_nonoverlapping(items.as_ptr(), ptr, items.len());
+    }
+    ptr
+}
+
+/// Copies the bytes into a NUL-terminated string allocated with `malloc`.
+unsafe fn alloc_string(bytes: &[u8]) -> *mut c_char {
+    let ptr = libc::malloc(bytes.len() + 1) as *mut u8;
+    if !ptr.is_null() {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+        *ptr.add(bytes.len()) = 0;
+    }
+    ptr as *mut c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, c_int, CStr};
+    use std::ptr;
+
+    use rusty_chromaprint::io::{read_pcm_file, PcmFormat};
+    use rusty_chromaprint::{Configuration, Fingerprinter};
+
+    use crate::*;
+
+    #[test]
+    fn fingerprint() {
+        let data =
+            read_pcm_file("../chromaprint/data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let mut printer = Fingerprinter::new(&Configuration::preset_test2());
+        printer.start(11025, 1).unwrap();
+        printer.consume(&data).unwrap();
+        printer.finish();
+
+        unsafe {
+            assert!(chromaprint_new(5).is_null());
+            let silent = chromaprint_new(3);
+            assert_eq!(
+                chromaprint_set_option(silent, c"silence_threshold".as_ptr(), 100),
+                1
+            );
+            assert_eq!(chromaprint_set_option(silent, c"other".as_ptr(), 100), 0);
+            assert_eq!(chromaprint_get_algorithm(silent), 3);
+            chromaprint_free(silent);
+            let ctx = chromaprint_new(1);
+            assert_eq!(chromaprint_get_algorithm(ctx), 1);
+            assert_eq!(chromaprint_get_sample_rate(ctx), 11025);
+            assert_eq!(chromaprint_get_item_duration(ctx), 1365);
+            assert_eq!(chromaprint_get_item_duration_ms(ctx), 123);
+            assert_eq!(
+                chromaprint_set_option(ctx, c"silence_threshold".as_ptr(), 50),
+                0
+            );
+            assert_eq!(chromaprint_feed(ctx, data.as_ptr(), data.len() as c_int), 0);
+
+            assert_eq!(chromaprint_start(ctx, 11025, 1), 1);
+            for chunk in data.chunks(1000) {
+                assert_eq!(
+                    chromaprint_feed(ctx, chunk.as_ptr(), chunk.len() as c_int),
+                    1
+                );
+            }
+            assert_eq!(chromaprint_finish(ctx), 1);
+
+            let mut raw = ptr::null_mut();
+            let mut size = 0;
+            assert_eq!(chromaprint_get_raw_fingerprint(ctx, &mut raw, &mut size), 1);
+            assert_eq!(
+                std::slice::from_raw_parts(raw, size as usize),
+                printer.fingerprint()
+            );
+
+            let mut encoded: *mut c_char = ptr::null_mut();
+            assert_eq!(chromaprint_get_fingerprint(ctx, &mut encoded), 1);
+            let encoded_len = CStr::from_ptr(encoded).to_bytes().len() as c_int;
+
+            let mut decoded = ptr::null_mut();
+            let mut decoded_size = 0;
+            let mut algorithm = -1;
+            assert_eq!(
+                chromaprint_decode_fingerprint(
+                    encoded,
+                    encoded_len,
+                    &mut decoded,
+                    &mut decoded_size,
+                    &mut algorithm,
+                    1
+                ),
+                1
+            );
+            assert_eq!(algorithm, 1);
+            assert_eq!(
+                std::slice::from_raw_parts(decoded, decoded_size as usize),
+                printer.fingerprint()
+            );
+
+            let mut hash = 0;
+            assert_eq!(chromaprint_get_fingerprint_hash(ctx, &mut hash), 1);
+            let mut expected_hash = 0;
+            assert_eq!(
+                chromaprint_hash_fingerprint(raw, size, &mut expected_hash),
+                1
+            );
+            assert_eq!(hash, expected_hash);
+
+            assert_eq!(chromaprint_clear_fingerprint(ctx), 1);
+            assert_eq!(chromaprint_get_raw_fingerprint_size(ctx, &mut size), 1);
+            assert_eq!(size, 0);
+
+            chromaprint_dealloc(raw as *mut _);
+            chromaprint_dealloc(encoded as *mut _);
+            chromaprint_dealloc(decoded as *mut _);
+            chromaprint_free(ctx);
+        }
+    }
+
+    #[test]
+    fn invalid_presets() {
+        for config in Configuration::all_presets() {
+            let ctx = chromaprint_new(c_int::from(config.id()));
+            assert_eq!(ctx.is_null(), config.validate().is_err());
+            unsafe { chromaprint_free(ctx) };
+        }
+        assert_eq!(catch_panic(0, || panic!("unexpected")), 0);
+    }
+
+    #[test]
+    fn libchromaprint_compatibility() {
+        let stereo = read_pcm_file(
+            "../chromaprint/data/test_stereo_44100.raw",
+            PcmFormat::S16Le,
+        )
+        .unwrap();
+        let mono =
+            read_pcm_file("../chromaprint/data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+
+        let fingerprint = |algorithm, data: &[i16], sample_rate, channels| unsafe {
+            let ctx = chromaprint_new(algorithm);
+            assert_eq!(chromaprint_start(ctx, sample_rate, channels), 1);
+            assert_eq!(chromaprint_feed(ctx, data.as_ptr(), data.len() as c_int), 1);
+            assert_eq!(chromaprint_finish(ctx), 1);
+            let mut raw = ptr::null_mut();
+            let mut size = 0;
+            assert_eq!(chromaprint_get_raw_fingerprint(ctx, &mut raw, &mut size), 1);
+            let items = std::slice::from_raw_parts(raw, size as usize).to_vec();
+            chromaprint_dealloc(raw as *mut _);
+            chromaprint_free(ctx);
+            items
+        };
+
+        // Fingerprint calculated by libchromaprint with the first algorithm.
+        assert_eq!(
+            fingerprint(0, &stereo, 11025, 2),
+            [
+                3086176501, 3077772469, 3077638581, 3052408789, 3048228821, 3046201301, 3042148311,
+                3037102035, 2969993073, 3041294129, 3045483313, 3046514967, 3050712326, 3040164098,
+                3040163847, 3073719559, 3073733965, 3212169693, 3212169693, 3220542455, 3220542399,
+                3212152503, 3077933717, 3086327509, 3080034295, 4120237047, 4119197543, 4119295527,
+                4123424293, 1975934501, 2110152245, 2111233559, 2144501255, 1005778439, 1001636359,
+                1005683463, 1005682948, 1005686104, 991003132, 991031785, 995223531, 995190635,
+                1003562858
+            ]
+        );
+
+        // Resampling gives the same results as in libchromaprint only with its own resampler.
+        // Treated as 22050 Hz to get a fingerprint long enough after resampling.
+        let expected = |resampler| {
+            let config = Configuration::preset_test2().with_resampler(resampler);
+            let mut printer = Fingerprinter::new(&config);
+            printer.start(22050, 1).unwrap();
+            printer.consume(&mono).unwrap();
+            printer.finish();
+            printer.fingerprint().to_vec()
+        };
+        let resampled = fingerprint(1, &mono, 22050, 1);
+        assert!(!resampled.is_empty());
+        assert_eq!(resampled, expected(ResamplerKind::Chromaprint));
+        assert_ne!(resampled, expected(ResamplerKind::default()));
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(simhash(&[]), 0);
+        assert_eq!(simhash(&[0b1011, 0b0011, 0b0100]), 0b0011);
+    }
+}
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rusty_chromaprint::io::{read_pcm_file, PcmFormat};
+use rusty_chromaprint::{Configuration, Fingerprinter, Pipeline, StaticPipeline};
+
+fn fingerprint<P: Pipeline>(mut printer: Fingerprinter<P>, data: &[i16]) -> usize {
+    printer.start(11025, 1).unwrap();
+    printer.consume(data).unwrap();
+    printer.finish();
+    printer.fingerprint().len()
+}
+
+fn pipeline(c: &mut Criterion) {
+    // About a minute of audio at 11025 Hz, so no resampling is involved.
+    let data = read_pcm_file(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/data/test_mono_44100.raw"),
+        PcmFormat::S16Le,
+    )
+    .unwrap()
+    .repeat(8);
+    let config = Configuration::preset_test2();
+
+    let mut group = c.benchmark_group("pipeline");
+    group.bench_function("dyn", |b| {
+        b.iter(|| fingerprint(Fingerprinter::new(&config), &data))
+    });
+    group.bench_function("static", |b| {
+        b.iter(|| {
+            fingerprint(
+                Fingerprinter::<StaticPipeline>::with_pipeline(&config),
+                &data,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rusty_chromaprint::io::{read_pcm_file, PcmFormat};
+use rusty_chromaprint::{Configuration, Fingerprinter, ResamplerKind};
+
+fn resampler(c: &mut Criterion) {
+    // About one minute of audio, so the setup of the resampler doesn't dominate.
+    let data = read_pcm_file(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/data/test_stereo_44100.raw"),
+        PcmFormat::S16Le,
+    )
+    .unwrap()
+    .repeat(30);
+
+    let mut group = c.benchmark_group("resampler");
+    for kind in [ResamplerKind::Sinc, ResamplerKind::Fft] {
+        let config = Configuration::preset_test2().with_resampler(kind);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{kind:?}")),
+            &kind,
+            |b, _| {
+                b.iter(|| {
+                    let mut printer = Fingerprinter::new(&config);
+                    printer.start(44100, 2).unwrap();
+                    printer.consume(&data).unwrap();
+                    printer.finish();
+                    printer.fingerprint().len()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, resampler);
+criterion_main!(benches);
@@ -1,19 +1,12 @@
-use std::path::Path;
-
+use rusty_chromaprint::io::{read_pcm_file, PcmFormat};
 use rusty_chromaprint::{Configuration, Fingerprinter};
 
-fn read_s16le(path: impl AsRef<Path>) -> Vec<i16> {
-    std::fs::read(path)
-        .unwrap()
-        .chunks_exact(2)
-        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-        .collect::<Vec<_>>()
-}
-
 fn main() {
     let mut printer = Fingerprinter::new(&Configuration::preset_test1());
     printer.start(11025, 2).unwrap();
-    printer.consume(&read_s16le("data/test_stereo_44100.raw"));
+    printer
+        .consume(&read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap())
+        .unwrap();
     printer.finish();
 
     assert_eq!(
@@ -0,0 +1,198 @@
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+use crate::fingerprint::Fingerprint;
+
+const DEFAULT_URL: &str = "https://api.acoustid.org/v2/lookup";
+
+/// Client of the [AcoustID](https://acoustid.org/webservice) lookup service.
+#[derive(Debug, Clone)]
+pub struct AcoustIdClient {
+    api_key: String,
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl AcoustIdClient {
+    /// Creates a client using the given application API key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            url: DEFAULT_URL.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    /// Sends the requests to a different URL (e.g. a mirror of the service).
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Looks up the fingerprint of the audio lasting `duration_secs` seconds.
+    ///
+    /// Results are sorted by their score, from the best one.
+    pub fn lookup(
+        &self,
+        fingerprint: &Fingerprint,
+        duration_secs: f64,
+    ) -> Result<Vec<LookupResult>, LookupError> {
+        let duration = (duration_secs.round() as u64).to_string();
+        let fingerprint = fingerprint.to_string();
+        let response = self.agent.post(&self.url).send_form(&[
+            ("format", "json"),
+            ("client", &self.api_key),
+            ("duration", &duration),
+            ("fingerprint", &fingerprint),
+            ("meta", "recordingids releaseids"),
+        ]);
+        let response = match response {
+            Ok(response) => response,
+            // The service describes its errors in the body.
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(LookupError::Http(Box::new(e))),
+        };
+        parse_response(response.into_reader())
+    }
+}
+
+/// Single match found by the AcoustID service.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LookupResult {
+    /// AcoustID of the matched track.
+    pub id: String,
+    /// Similarity of the fingerprints in range from 0 to 1.
+    pub score: f64,
+    /// Recordings linked to the track.
+    #[serde(default)]
+    pub recordings: Vec<Recording>,
+}
+
+/// MusicBrainz recording linked to an AcoustID track.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Recording {
+    /// MusicBrainz recording ID.
+    pub id: String,
+    /// MusicBrainz IDs of releases containing the recording.
+    #[serde(default)]
+    pub releases: Vec<Release>,
+}
+
+/// MusicBrainz release containing a recording.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Release {
+    /// MusicBrainz release ID.
+    pub id: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Response {
+    status: String,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+    error: Option<ServiceError>,
+}
+
+#[derive(serde::Deserialize)]
+struct ServiceError {
+    code: u32,
+    message: String,
+}
+
+fn parse_response(reader: impl Read) -> Result<Vec<LookupResult>, LookupError> {
+    let response: Response =
+        serde_json::from_reader(reader).map_err(LookupError::InvalidResponse)?;
+    if response.status == "ok" {
+        let mut results = response.results;
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        return Ok(results);
+    }
+
+    let error = response.error.unwrap_or(ServiceError {
+        code: 0,
+        message: format!("unexpected status {}", response.status),
+    });
+    Err(LookupError::Service {
+        code: error.code,
+        message: error.message,
+    })
+}
+
+/// Error returned when a fingerprint cannot be looked up.
+#[derive(Debug)]
+pub enum LookupError {
+    /// The request could not be sent.
+    Http(Box<ureq::Error>),
+    /// The response is not a valid JSON of the expected shape.
+    InvalidResponse(serde_json::Error),
+    /// The service rejected the request.
+    Service { code: u32, message: String },
+}
+
+impl Display for LookupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookupError::Http(e) => write!(f, "AcoustID request failed: {e}"),
+            LookupError::InvalidResponse(e) => write!(f, "Invalid AcoustID response: {e}"),
+            LookupError::Service { code, message } => {
+                write!(f, "AcoustID returned error {code}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LookupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LookupError::Http(e) => Some(e),
+            LookupError::InvalidResponse(e) => Some(e),
+            LookupError::Service { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::acoustid::{parse_response, LookupError, Recording, Release};
+
+    #[test]
+    fn results() {
+        let json = r#"{"status": "ok", "results": [
+            {"id": "a", "score": 0.5},
+            {"id": "b", "score": 0.9, "recordings": [
+                {"id": "r1", "releases": [{"id": "x"}, {"id": "y"}]},
+                {"id": "r2"}
+            ]}
+        ]}"#;
+        let results = parse_response(json.as_bytes()).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].id, "b");
+        assert_eq!(
+            results[0].recordings,
+            vec![
+                Recording {
+                    id: "r1".into(),
+                    releases: vec![Release { id: "x".into() }, Release { id: "y".into() }],
+                },
+                Recording {
+                    id: "r2".into(),
+                    releases: vec![],
+                },
+            ]
+        );
+        assert!(results[1].recordings.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let json = r#"{"status": "error", "error": {"code": 4, "message": "invalid API key"}}"#;
+        assert!(matches!(
+            parse_response(json.as_bytes()),
+            Err(LookupError::Service { code: 4, message }) if message == "invalid API key"
+        ));
+        assert!(matches!(
+            parse_response(&b"<html>"[..]),
+            Err(LookupError::InvalidResponse(_))
+        ));
+    }
+}
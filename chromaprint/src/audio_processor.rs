@@ -2,36 +2,140 @@ use std::fmt::{Display, Formatter};
 
 use rubato::Resampler;
 
-use crate::stages::{AudioConsumer, Stage};
+use crate::fingerprinter::PipelineStats;
+use crate::resampler::ChromaprintResampler;
+use crate::stages::{AudioConsumer, RawAudioConsumer, SpectrumSink, Stage};
 
 const MIN_SAMPLE_RATE: u32 = 1000;
 const MAX_BUFFER_SIZE: usize = 1024 * 32;
+/// Number of FFTs a single chunk of [MAX_BUFFER_SIZE] is split into by [ResamplerKind::Fft].
+const FFT_SUB_CHUNKS: usize = 32;
+
+/// Weights of channels in the WAV order (FL, FR, FC, LFE, BL, BR, SL, SR) for [DownmixMode::Weighted].
+const CHANNEL_WEIGHTS: [f64; 8] = [
+    1.0,
+    1.0,
+    std::f64::consts::FRAC_1_SQRT_2,
+    0.0,
+    std::f64::consts::FRAC_1_SQRT_2,
+    std::f64::consts::FRAC_1_SQRT_2,
+    std::f64::consts::FRAC_1_SQRT_2,
+    std::f64::consts::FRAC_1_SQRT_2,
+];
+
+/// Strategy of mixing multichannel audio down to a single channel.
+///
+/// Channels are expected in the WAV order: front left, front right, front center,
+/// low frequency, back left, back right, side left and side right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DownmixMode {
+    /// Averages all the channels.
+    #[default]
+    Average,
+    /// Weights the channels like ITU-R BS.775, skipping the low frequency channel.
+    Weighted,
+    /// Uses only the channel with a given index.
+    Channel(usize),
+    /// Averages only the front channels (left, right and center).
+    Front,
+}
+
+/// Algorithm used for converting the audio to the sample rate of the [Configuration](crate::Configuration).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResamplerKind {
+    /// Windowed sinc interpolation.
+    #[default]
+    Sinc,
+    /// FFT-based resampling with a steeper anti-aliasing filter.
+    ///
+    /// The fingerprints slightly differ from the ones calculated with the default resampler.
+    /// The `resampler` benchmark compares the speed of both algorithms.
+    Fft,
+    /// Port of the resampler used by chromaprint.
+    ///
+    /// It gives fingerprints identical to the ones calculated by the original implementation
+    /// (e.g. for submission to AcoustID).
+    Chromaprint,
+}
+
+enum AnyResampler {
+    Sinc(rubato::SincFixedIn<f64>),
+    Fft(rubato::FftFixedIn<f64>),
+    Chromaprint(ChromaprintResampler),
+}
 
-pub struct AudioProcessor<C: AudioConsumer<f64>> {
+impl AnyResampler {
+    fn output_frames_max(&self) -> usize {
+        match self {
+            AnyResampler::Sinc(resampler) => resampler.output_frames_max(),
+            AnyResampler::Fft(resampler) => resampler.output_frames_max(),
+            AnyResampler::Chromaprint(_) => MAX_BUFFER_SIZE,
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            AnyResampler::Sinc(resampler) => resampler.reset(),
+            AnyResampler::Fft(resampler) => resampler.reset(),
+            AnyResampler::Chromaprint(resampler) => resampler.reset(),
+        }
+    }
+}
+
+pub struct AudioProcessor<C: RawAudioConsumer> {
+    /// Downmixed samples for the [ChromaprintResampler], which works on integers.
     buffer: Box<[i16]>,
     buffer_offset: usize,
+    /// Samples of a frame split between two consumed slices.
+    partial_frame: Vec<i16>,
     output_buffer: Vec<f64>,
+    /// Downmixed and normalized samples for the other resamplers, converted as they are loaded.
     input: Vec<f64>,
     channels: u32,
+    downmix: DownmixMode,
     consumer: C,
     target_sample_rate: u32,
-    resampler: Option<rubato::SincFixedIn<f64>>,
+    resampler_kind: ResamplerKind,
+    resampler: Option<AnyResampler>,
+    /// Sample rate of the input the resampler was created for.
+    input_sample_rate: u32,
+    /// Set when the resampler rejected its input, the following samples are dropped until a reset.
+    failed: bool,
 }
 
-impl<C: AudioConsumer<f64>> AudioProcessor<C> {
+impl<C: RawAudioConsumer> AudioProcessor<C> {
     pub(crate) fn new(target_sample_rate: u32, consumer: C) -> Self {
         Self {
             buffer: vec![0; MAX_BUFFER_SIZE].into_boxed_slice(),
             buffer_offset: 0,
+            partial_frame: Vec::new(),
             output_buffer: Vec::new(),
             input: Vec::new(),
             channels: 0,
+            downmix: DownmixMode::Average,
             consumer,
             target_sample_rate,
+            resampler_kind: ResamplerKind::Sinc,
             resampler: None,
+            input_sample_rate: 0,
+            failed: false,
         }
     }
 
+    /// Sets the strategy of mixing multichannel audio down to a single channel.
+    pub(crate) fn with_downmix(mut self, downmix: DownmixMode) -> Self {
+        self.downmix = downmix;
+        self
+    }
+
+    /// Sets the algorithm used for resampling, applied by the following [reset](Self::reset).
+    pub(crate) fn with_resampler(mut self, kind: ResamplerKind) -> Self {
+        self.resampler_kind = kind;
+        self
+    }
+
     fn load(&mut self, input: &[i16], channels: usize) -> usize {
         assert!(self.buffer_offset <= self.buffer.len());
         assert_eq!(input.len() % channels, 0);
@@ -40,23 +144,43 @@ impl<C: AudioConsumer<f64>> AudioProcessor<C> {
         let consumed = available_samples.min(self.available_space());
         let input = &input[..consumed * channels];
 
-        match channels {
-            1 => {
+        match (self.downmix, channels) {
+            (_, 1) => {
                 for sample in input.iter().copied() {
                     self.push_sample(sample);
                 }
             }
-            2 => {
+            (DownmixMode::Channel(index), _) => {
+                for sample in input.chunks_exact(channels) {
+                    self.push_sample(sample[index]);
+                }
+            }
+            (_, 2) => {
                 for sample in input.chunks_exact(2) {
                     self.push_sample(((i32::from(sample[0]) + i32::from(sample[1])) / 2) as i16);
                 }
             }
-            _ => {
+            (DownmixMode::Average, _) => {
+                for sample in input.chunks_exact(channels) {
+                    self.push_sample(average(sample));
+                }
+            }
+            (DownmixMode::Front, _) => {
                 for sample in input.chunks_exact(channels) {
-                    let sum: i32 = sample.iter().copied().map(i32::from).sum();
-                    let samples: i32 = sample.len().try_into().unwrap();
-                    let average: i32 = sum / samples;
-                    self.push_sample(average.try_into().unwrap());
+                    self.push_sample(average(&sample[..3]));
+                }
+            }
+            (DownmixMode::Weighted, _) => {
+                let weights = (0..channels)
+                    .map(|i| CHANNEL_WEIGHTS.get(i).copied().unwrap_or(1.0))
+                    .collect::<Vec<_>>();
+                let total: f64 = weights.iter().sum();
+                for sample in input.chunks_exact(channels) {
+                    let sum: f64 = std::iter::zip(sample, &weights)
+                        .map(|(&s, &w)| f64::from(s) * w)
+                        .sum();
+                    // The weighted average of `i16` values always fits in `i16`.
+                    self.push_sample((sum / total).round() as i16);
                 }
             }
         }
@@ -64,60 +188,105 @@ impl<C: AudioConsumer<f64>> AudioProcessor<C> {
         consumed * channels
     }
 
+    fn consume_frames(&mut self, data: &[i16]) {
+        let mut index = 0;
+        while index < data.len() {
+            index += self.load(&data[index..], self.channels as usize);
+            if self.available_space() == 0 {
+                // Full buffer
+                self.resample(false);
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), fields(samples = self.buffer_offset + self.input.len())))]
     fn resample(&mut self, is_end: bool) {
-        for &sample in &self.buffer[..self.buffer_offset] {
-            self.input.push(f64::from(sample) / f64::from(i16::MAX));
+        if self.failed {
+            self.buffer_offset = 0;
+            self.input.clear();
+            return;
         }
-        self.buffer_offset = 0;
 
-        if let Some(resampler) = self.resampler.as_mut() {
-            let default_input_frames = resampler.input_frames_next();
-            while !self.input.is_empty() {
-                if self.input.len() < resampler.input_frames_next() {
-                    if is_end {
-                        // Update chunk size to accept the remaining samples
-                        resampler
-                            .set_chunk_size(self.input.len())
-                            .expect("cannot update chunk size for the resampler");
-                    } else {
-                        break;
-                    }
-                }
+        if let Some(AnyResampler::Chromaprint(resampler)) = self.resampler.as_mut() {
+            // Works directly on the buffered samples, keeping the unconsumed ones for later.
+            let consumed = resampler
+                .process(&self.buffer[..self.buffer_offset])
+                .min(self.buffer_offset);
+            let output = resampler.output();
+            for (converted, &sample) in self.output_buffer.iter_mut().zip(output) {
+                *converted = f64::from(sample) / f64::from(i16::MAX);
+            }
+            self.consumer.consume(&self.output_buffer[..output.len()]);
+            self.buffer.copy_within(consumed..self.buffer_offset, 0);
+            self.buffer_offset = if is_end {
+                0
+            } else {
+                self.buffer_offset - consumed
+            };
+            return;
+        }
 
-                let required_input = resampler.input_frames_next();
-                self.output_buffer
-                    .resize(resampler.output_frames_next(), 0.0);
-                let (read_samples, written_samples) = resampler
-                    .process_into_buffer(
-                        &[&self.input[..required_input]],
-                        std::slice::from_mut(&mut self.output_buffer),
-                        None,
-                    )
-                    .expect("invalid parameters for resampler");
-                self.input.drain(..read_samples);
-                self.consumer
-                    .consume(&self.output_buffer[..written_samples]);
-
-                if is_end {
-                    resampler
-                        .set_chunk_size(default_input_frames)
-                        .expect("cannot restore chunk size for the resampler");
-                }
+        let result = match self.resampler.as_mut() {
+            Some(AnyResampler::Sinc(resampler)) => resample_with(
+                resampler,
+                &mut self.input,
+                &mut self.output_buffer,
+                &mut self.consumer,
+                is_end,
+            ),
+            Some(AnyResampler::Fft(resampler)) => resample_with(
+                resampler,
+                &mut self.input,
+                &mut self.output_buffer,
+                &mut self.consumer,
+                is_end,
+            ),
+            Some(AnyResampler::Chromaprint(_)) => unreachable!(),
+            None => {
+                self.consumer.consume(&self.input);
+                self.input.clear();
+                Ok(())
             }
-        } else {
-            self.consumer.consume(&self.input);
+        };
+        if result.is_err() {
+            self.failed = true;
             self.input.clear();
         }
     }
 
+    /// Returns an error if resampling failed since the last reset.
+    pub(crate) fn error(&self) -> Option<ConsumeError> {
+        if self.failed {
+            Some(ConsumeError::CannotResample)
+        } else {
+            None
+        }
+    }
+
+    fn uses_integer_buffer(&self) -> bool {
+        matches!(self.resampler, Some(AnyResampler::Chromaprint(_)))
+    }
+
     fn available_space(&self) -> usize {
-        self.buffer.len() - self.buffer_offset
+        if self.uses_integer_buffer() {
+            self.buffer.len() - self.buffer_offset
+        } else {
+            MAX_BUFFER_SIZE.saturating_sub(self.input.len())
+        }
+    }
+
+    fn has_pending_samples(&self) -> bool {
+        self.buffer_offset > 0 || !self.input.is_empty()
     }
 
     #[inline]
     fn push_sample(&mut self, value: i16) {
-        self.buffer[self.buffer_offset] = value;
-        self.buffer_offset += 1;
+        if self.uses_integer_buffer() {
+            self.buffer[self.buffer_offset] = value;
+            self.buffer_offset += 1;
+        } else {
+            self.input.push(f64::from(value) / f64::from(i16::MAX));
+        }
     }
 
     pub(crate) fn reset(&mut self, sample_rate: u32, channels: u32) -> Result<(), ResetError> {
@@ -129,74 +298,207 @@ impl<C: AudioConsumer<f64>> AudioProcessor<C> {
             return Err(ResetError::SampleRateTooLow);
         }
 
+        if matches!(self.downmix, DownmixMode::Channel(index) if index >= channels as usize) {
+            return Err(ResetError::InvalidConfiguration(
+                "selected channel for downmix doesn't exist",
+            ));
+        }
+
         self.channels = channels;
-        self.buffer_offset = 0;
-        self.consumer.reset();
+        AudioConsumer::reset(self);
+        if self.resampler.is_some() && self.input_sample_rate == sample_rate {
+            // The resampler has just been reset, so it can be reused.
+            return Ok(());
+        }
 
+        self.resampler = None;
+        self.input_sample_rate = sample_rate;
         if self.target_sample_rate != sample_rate {
-            let resampler = rubato::SincFixedIn::new(
-                self.target_sample_rate as f64 / sample_rate as f64,
-                1.0,
-                rubato::SincInterpolationParameters {
-                    sinc_len: 16,
-                    f_cutoff: 0.8,
-                    oversampling_factor: 128,
-                    interpolation: rubato::SincInterpolationType::Nearest,
-                    window: rubato::WindowFunction::Blackman,
-                },
-                MAX_BUFFER_SIZE,
-                1,
-            )?;
+            let resampler = match self.resampler_kind {
+                ResamplerKind::Sinc => AnyResampler::Sinc(rubato::SincFixedIn::new(
+                    self.target_sample_rate as f64 / sample_rate as f64,
+                    1.0,
+                    rubato::SincInterpolationParameters {
+                        sinc_len: 16,
+                        f_cutoff: 0.8,
+                        oversampling_factor: 128,
+                        interpolation: rubato::SincInterpolationType::Nearest,
+                        window: rubato::WindowFunction::Blackman,
+                    },
+                    MAX_BUFFER_SIZE,
+                    1,
+                )?),
+                ResamplerKind::Fft => AnyResampler::Fft(rubato::FftFixedIn::new(
+                    sample_rate as usize,
+                    self.target_sample_rate as usize,
+                    MAX_BUFFER_SIZE,
+                    FFT_SUB_CHUNKS,
+                    1,
+                )?),
+                ResamplerKind::Chromaprint => AnyResampler::Chromaprint(ChromaprintResampler::new(
+                    sample_rate,
+                    self.target_sample_rate,
+                    MAX_BUFFER_SIZE,
+                )),
+            };
             self.output_buffer
                 .resize(resampler.output_frames_max(), 0.0);
             self.resampler = Some(resampler);
         }
+        if !self.uses_integer_buffer() {
+            self.input.reserve(MAX_BUFFER_SIZE);
+        }
 
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub(crate) fn stats(&self, stats: &mut PipelineStats) {
+        self.consumer.stats(stats);
+    }
+
+    pub(crate) fn set_spectrum_sink(&mut self, sink: Option<SpectrumSink>) {
+        self.consumer.set_spectrum_sink(sink);
+    }
+
     pub(crate) fn flush(&mut self) {
-        if self.buffer_offset > 0 {
+        if self.has_pending_samples() {
             self.resample(true);
         }
         self.consumer.flush();
     }
 }
 
-impl<C: AudioConsumer<f64>> Stage for AudioProcessor<C> {
+impl<C: RawAudioConsumer> Stage for AudioProcessor<C> {
     type Output = C::Output;
 
     fn output(&self) -> &Self::Output {
         self.consumer.output()
     }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
 }
 
-impl<C: AudioConsumer<f64>> AudioConsumer for AudioProcessor<C> {
+impl<C: RawAudioConsumer> AudioConsumer for AudioProcessor<C> {
+    /// Drops all the buffered samples and resets the following stages.
+    ///
+    /// The sample rate and the number of channels are preserved.
     fn reset(&mut self) {
-        todo!();
+        self.buffer_offset = 0;
+        self.failed = false;
+        self.partial_frame.clear();
+        self.input.clear();
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+        self.consumer.reset();
     }
 
+    /// Consumes interleaved samples.
+    ///
+    /// The slice doesn't need to contain whole frames, the samples of an incomplete frame
+    /// are kept until the rest of them is consumed.
     fn consume(&mut self, data: &[i16]) {
-        assert_eq!(data.len() % self.channels as usize, 0);
-
-        let mut index = 0;
-        while index < data.len() {
-            index += self.load(&data[index..], self.channels as usize);
-            if self.buffer.len() == self.buffer_offset {
-                // Full buffer
+        if self.channels == 1 && self.resampler.is_none() {
+            // Nothing to convert, so pass the samples directly to the consumer.
+            if self.has_pending_samples() {
                 self.resample(false);
             }
+            self.consumer.consume_raw(data);
+            return;
+        }
+
+        let channels = self.channels as usize;
+        let mut data = data;
+        if !self.partial_frame.is_empty() {
+            let missing = (channels - self.partial_frame.len()).min(data.len());
+            self.partial_frame.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.partial_frame.len() < channels {
+                return;
+            }
+            let frame = std::mem::take(&mut self.partial_frame);
+            self.consume_frames(&frame);
+            self.partial_frame = frame;
+            self.partial_frame.clear();
         }
+
+        let frames_len = data.len() - data.len() % channels;
+        self.consume_frames(&data[..frames_len]);
+        self.partial_frame.extend_from_slice(&data[frames_len..]);
     }
 
     fn flush(&mut self) {}
 }
 
+/// Resamples all the complete chunks of the input, or everything that's left at the end of the stream.
+///
+/// The output buffer must be already allocated for the longest output of the resampler.
+fn resample_with<R: Resampler<f64>, C: AudioConsumer<f64>>(
+    resampler: &mut R,
+    input: &mut Vec<f64>,
+    output_buffer: &mut [f64],
+    consumer: &mut C,
+    is_end: bool,
+) -> Result<(), rubato::ResampleError> {
+    let default_input_frames = resampler.input_frames_next();
+    while !input.is_empty() {
+        if input.len() < resampler.input_frames_next() {
+            if !is_end {
+                break;
+            }
+
+            // Update chunk size to accept the remaining samples
+            if resampler.set_chunk_size(input.len()).is_err() {
+                // The chunk size is fixed, so zero-pad the remaining samples instead
+                // and keep only the part of the output corresponding to them.
+                let expected_output =
+                    input.len() * resampler.output_frames_next() / resampler.input_frames_next();
+                let (_, written_samples) = resampler.process_partial_into_buffer(
+                    Some(&[&input[..]]),
+                    &mut [&mut *output_buffer],
+                    None,
+                )?;
+                consumer.consume(&output_buffer[..written_samples.min(expected_output)]);
+                input.clear();
+                break;
+            }
+        }
+
+        let required_input = resampler.input_frames_next();
+        let (read_samples, written_samples) = resampler.process_into_buffer(
+            &[&input[..required_input]],
+            &mut [&mut *output_buffer],
+            None,
+        )?;
+        input.drain(..read_samples);
+        consumer.consume(&output_buffer[..written_samples]);
+
+        if is_end && resampler.input_frames_next() != default_input_frames {
+            resampler.set_chunk_size(default_input_frames)?;
+        }
+    }
+    Ok(())
+}
+
+fn average(samples: &[i16]) -> i16 {
+    let sum: i64 = samples.iter().copied().map(i64::from).sum();
+    // The average of `i16` values always fits in `i16`.
+    (sum / samples.len() as i64) as i16
+}
+
 #[derive(Debug)]
 pub enum ResetError {
     SampleRateTooLow,
     NoChannels,
     CannotResample(rubato::ResamplerConstructionError),
+    InvalidConfiguration(&'static str),
 }
 
 impl From<rubato::ResamplerConstructionError> for ResetError {
@@ -215,17 +517,42 @@ impl Display for ResetError {
             ),
             ResetError::NoChannels => writeln!(f, "At least one channel is required"),
             ResetError::CannotResample(e) => writeln!(f, "Cannot resample: {}", e),
+            ResetError::InvalidConfiguration(reason) => {
+                writeln!(f, "Invalid configuration: {}", reason)
+            }
         }
     }
 }
 
 impl std::error::Error for ResetError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsumeError {
+    /// The calculation hasn't been successfully started.
+    NotStarted,
+    /// The resampler rejected the audio, the samples consumed since then were dropped.
+    CannotResample,
+}
+
+impl Display for ConsumeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsumeError::NotStarted => write!(f, "Fingerprint calculation is not started"),
+            ConsumeError::CannotResample => write!(f, "Cannot resample the audio"),
+        }
+    }
+}
+
+impl std::error::Error for ConsumeError {}
+
 #[cfg(test)]
 mod tests {
     use crate::assert_eq_float_slice;
-    use crate::audio_processor::{AudioConsumer, AudioProcessor, Stage};
-    use crate::utils::read_s16le;
+    use crate::audio_processor::{
+        AudioConsumer, AudioProcessor, ConsumeError, DownmixMode, RawAudioConsumer, ResamplerKind,
+        Stage,
+    };
+    use crate::io::{read_pcm_file, PcmFormat};
 
     fn i16_to_f64(s: &[i16]) -> Vec<f64> {
         s.iter()
@@ -236,7 +563,7 @@ mod tests {
 
     #[test]
     fn pass_through() {
-        let data = read_s16le("data/test_mono_44100.raw");
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
         let mut processor = AudioProcessor::new(44100, AudioBuffer::new());
         processor.reset(44100, 1).unwrap();
         processor.consume(&data);
@@ -245,25 +572,104 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
-    fn mono() {
-        let data1 = read_s16le("data/test_mono_44100.raw");
-        let data2 = read_s16le("data/test_mono_11025.raw");
+    fn reset() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let mut processor = AudioProcessor::new(11025, AudioBuffer::new());
+        processor.reset(44100, 1).unwrap();
+        processor.consume(&data);
+        processor.flush();
+        let expected = processor.output().to_vec();
 
+        processor.consume(&data[..5000]);
+        AudioConsumer::reset(&mut processor);
+        processor.consume(&data);
+        processor.flush();
+        assert_eq_float_slice!(processor.output(), expected);
+
+        processor.reset(11025, 1).unwrap();
+        processor.consume(&data);
+        processor.flush();
+        assert_eq_float_slice!(processor.output(), i16_to_f64(&data));
+    }
+
+    #[test]
+    fn resampling_failure() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
         let mut processor = AudioProcessor::new(11025, AudioBuffer::new());
         processor.reset(44100, 1).unwrap();
+        // The resampler rejects an output buffer that is too short.
+        processor.output_buffer.truncate(1);
+        processor.consume(&data);
+        processor.flush();
+        assert_eq!(Some(ConsumeError::CannotResample), processor.error());
+        assert!(processor.output().is_empty());
+
+        processor.reset(44100, 1).unwrap();
+        assert_eq!(None, processor.error());
+    }
+
+    #[test]
+    fn downmix() {
+        let frames = [100, 200, 400, 1000, 800, 800];
+        let downmixed = |downmix| {
+            let mut processor =
+                AudioProcessor::new(44100, AudioBuffer::new()).with_downmix(downmix);
+            processor.reset(44100, 6).unwrap();
+            processor.consume(&frames);
+            processor.flush();
+            processor.output().to_vec()
+        };
+        let scale = |x: f64| x / f64::from(i16::MAX);
+
+        assert_eq_float_slice!(downmixed(DownmixMode::Average), [scale(550.0)]);
+        assert_eq_float_slice!(downmixed(DownmixMode::Front), [scale(233.0)]);
+        assert_eq_float_slice!(downmixed(DownmixMode::Channel(2)), [scale(400.0)]);
+        let weight = std::f64::consts::FRAC_1_SQRT_2;
+        let weighted = (300.0 + weight * 2000.0) / (2.0 + 3.0 * weight);
+        assert_eq_float_slice!(downmixed(DownmixMode::Weighted), [scale(weighted.round())]);
+
+        let mut processor =
+            AudioProcessor::new(44100, AudioBuffer::new()).with_downmix(DownmixMode::Channel(2));
+        assert!(processor.reset(44100, 2).is_err());
+    }
+
+    #[test]
+    fn split_frames() {
+        let data = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
+        let mut processor = AudioProcessor::new(44100, AudioBuffer::new());
+        processor.reset(44100, 2).unwrap();
+        processor.consume(&data);
+        processor.flush();
+        let expected = processor.output().to_vec();
+
+        processor.reset(44100, 2).unwrap();
+        for chunk in data.chunks(1001) {
+            processor.consume(chunk);
+        }
+        processor.flush();
+        assert_eq_float_slice!(processor.output(), expected);
+    }
+
+    #[test]
+    fn mono() {
+        let data1 = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let data2 = read_pcm_file("data/test_mono_11025.raw", PcmFormat::S16Le).unwrap();
+
+        let mut processor = AudioProcessor::new(11025, AudioBuffer::new())
+            .with_resampler(ResamplerKind::Chromaprint);
+        processor.reset(44100, 1).unwrap();
         processor.consume(&data1);
         processor.flush();
         assert_eq_float_slice!(processor.output(), i16_to_f64(&data2));
     }
 
     #[test]
-    #[ignore]
     fn mono_non_integer() {
-        let data1 = read_s16le("data/test_mono_44100.raw");
-        let data2 = read_s16le("data/test_mono_8000.raw");
+        let data1 = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let data2 = read_pcm_file("data/test_mono_8000.raw", PcmFormat::S16Le).unwrap();
 
-        let mut processor = AudioProcessor::new(8000, AudioBuffer::new());
+        let mut processor = AudioProcessor::new(8000, AudioBuffer::new())
+            .with_resampler(ResamplerKind::Chromaprint);
         processor.reset(44100, 1).unwrap();
         processor.consume(&data1);
         processor.flush();
@@ -272,8 +678,8 @@ mod tests {
 
     #[test]
     fn stereo_to_mono() {
-        let data1 = read_s16le("data/test_mono_44100.raw");
-        let data2 = read_s16le("data/test_stereo_44100.raw");
+        let data1 = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let data2 = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
 
         let mut processor = AudioProcessor::new(44100, AudioBuffer::new());
         processor.reset(44100, 2).unwrap();
@@ -298,8 +704,14 @@ mod tests {
         fn output(&self) -> &Self::Output {
             self.data.as_slice()
         }
+
+        fn clear_output(&mut self) {
+            self.data.clear();
+        }
     }
 
+    impl RawAudioConsumer for AudioBuffer<f64> {}
+
     impl<T: Copy> AudioConsumer<T> for AudioBuffer<T> {
         fn reset(&mut self) {
             self.data.clear();
@@ -1,18 +1,22 @@
+use crate::float::Float;
 use crate::stages::{FeatureVectorConsumer, Stage};
 
-pub(crate) struct Chroma<C: FeatureVectorConsumer> {
+pub struct Chroma<C: FeatureVectorConsumer<T>, T: Float = f64> {
     interpolate: bool,
     notes: Box<[u8]>,
-    notes_frac: Box<[f64]>,
+    notes_frac: Box<[T]>,
     min_index: usize,
     max_index: usize,
-    features: [f64; NUM_BANDS],
+    sample_rate: u32,
+    features: Box<[T]>,
+    rotation: usize,
     consumer: C,
 }
 
-const NUM_BANDS: usize = 12;
+/// Default number of bands, one per semitone.
+pub(crate) const NUM_BANDS: usize = 12;
 
-impl<C: FeatureVectorConsumer> Chroma<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Chroma<C, T> {
     pub(crate) fn new(
         min_freq: u32,
         max_freq: u32,
@@ -23,40 +27,68 @@ impl<C: FeatureVectorConsumer> Chroma<C> {
         let mut chroma = Self {
             interpolate: false,
             notes: vec![0; frame_size].into_boxed_slice(),
-            notes_frac: vec![0.0; frame_size].into_boxed_slice(),
+            notes_frac: vec![T::zero(); frame_size].into_boxed_slice(),
             min_index: 0,
             max_index: 0,
-            features: [0.0; NUM_BANDS],
+            sample_rate,
+            features: vec![T::zero(); NUM_BANDS].into_boxed_slice(),
+            rotation: 0,
             consumer,
         };
-        chroma.prepare_notes(min_freq, max_freq, frame_size, sample_rate);
+        chroma.min_index = freq_to_index(min_freq, frame_size, sample_rate).max(1);
+        chroma.max_index = freq_to_index(max_freq, frame_size, sample_rate).min(frame_size / 2);
+        chroma.prepare_notes();
         chroma
     }
 
-    fn prepare_notes(&mut self, min_freq: u32, max_freq: u32, frame_size: usize, sample_rate: u32) {
-        self.min_index = freq_to_index(min_freq, frame_size, sample_rate).max(1);
-        self.max_index = freq_to_index(max_freq, frame_size, sample_rate).min(frame_size / 2);
+    /// Splits each octave into the given number of bands (a multiple of 12) instead of semitones.
+    ///
+    /// It must be set before the rotation.
+    pub(crate) fn with_bands(mut self, bands: usize) -> Self {
+        self.features = vec![T::zero(); bands].into_boxed_slice();
+        self.prepare_notes();
+        self
+    }
+
+    /// Shifts the bands down by the given number of semitones before passing them further.
+    pub(crate) fn with_rotation(mut self, rotation: usize) -> Self {
+        let bands = self.features.len();
+        self.rotation = rotation * bands / NUM_BANDS % bands;
+        self
+    }
+
+    fn prepare_notes(&mut self) {
+        let frame_size = self.notes.len();
+        let bands = self.features.len();
         for i in self.min_index..self.max_index {
-            let freq = index_to_freq(i, frame_size, sample_rate);
+            let freq = index_to_freq(i, frame_size, self.sample_rate);
             let octave = freq_to_octave(freq);
-            let note = NUM_BANDS as f64 * (octave - octave.floor());
+            let note = bands as f64 * (octave - octave.floor());
             self.notes[i] = note.floor() as u8;
-            self.notes_frac[i] = note - note.floor();
+            self.notes_frac[i] = T::cast(note - note.floor());
         }
     }
 }
 
-impl<C: FeatureVectorConsumer> Stage for Chroma<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Stage for Chroma<C, T> {
     type Output = C::Output;
 
     fn output(&self) -> &Self::Output {
         self.consumer.output()
     }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
 }
 
-impl<C: FeatureVectorConsumer> FeatureVectorConsumer for Chroma<C> {
-    fn consume(&mut self, frame: &[f64]) {
-        self.features.fill(0.0);
+impl<C: FeatureVectorConsumer<T>, T: Float> FeatureVectorConsumer<T> for Chroma<C, T> {
+    fn consume(&mut self, frame: &[T]) {
+        self.features.fill(T::zero());
         for (i, energy) in frame
             .iter()
             .enumerate()
@@ -66,22 +98,24 @@ impl<C: FeatureVectorConsumer> FeatureVectorConsumer for Chroma<C> {
             let note = self.notes[i] as usize;
             if self.interpolate {
                 let mut note2 = note;
-                let mut a = 1.0;
-                if self.notes_frac[i] < 0.5 {
-                    note2 = (note + NUM_BANDS - 1) % NUM_BANDS;
-                    a = 0.5 + self.notes_frac[i];
+                let mut a = T::one();
+                let half = T::cast(0.5);
+                if self.notes_frac[i] < half {
+                    note2 = (note + self.features.len() - 1) % self.features.len();
+                    a = half + self.notes_frac[i];
                 }
-                if self.notes_frac[i] > 0.5 {
-                    note2 = (note + 1) % NUM_BANDS;
-                    a = 1.5 - self.notes_frac[i];
+                if self.notes_frac[i] > half {
+                    note2 = (note + 1) % self.features.len();
+                    a = T::cast(1.5) - self.notes_frac[i];
                 }
-                self.features[note] += energy * a;
-                self.features[note2] += energy * (1.0 - a);
+                self.features[note] += *energy * a;
+                self.features[note2] += *energy * (T::one() - a);
             } else {
-                self.features[note] += energy;
+                self.features[note] += *energy;
             }
         }
 
+        self.features.rotate_left(self.rotation);
         self.consumer.consume(&self.features);
     }
 
@@ -125,6 +159,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rotated_a() {
+        let mut chroma =
+            Chroma::new(10, 510, 256, 1000, FeatureVectorBuffer::new()).with_rotation(1);
+        let mut frame = vec![0.0; 128];
+        frame[113] = 1.0;
+        chroma.consume(&frame);
+        let features = chroma.output();
+
+        assert_eq!(12, features.len());
+        let expected_features = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+        for i in 0..12 {
+            assert_eq_float!(expected_features[i], features[i], 0.0001);
+        }
+    }
+
     #[test]
     fn normal_gsharp() {
         let mut chroma = Chroma::new(10, 510, 256, 1000, FeatureVectorBuffer::new());
@@ -215,6 +266,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn more_bands() {
+        let mut chroma = Chroma::new(10, 510, 256, 1000, FeatureVectorBuffer::new())
+            .with_bands(24)
+            .with_rotation(1);
+        let mut frame = vec![0.0; 128];
+        frame[113] = 1.0;
+        chroma.consume(&frame);
+        let features = chroma.output();
+
+        assert_eq!(24, features.len());
+        for (i, &feature) in features.iter().enumerate() {
+            let expected = if i == 22 { 1.0 } else { 0.0 };
+            assert_eq_float!(expected, feature, 0.0001);
+        }
+    }
+
     struct FeatureVectorBuffer {
         features: Vec<f64>,
     }
@@ -231,6 +299,10 @@ mod tests {
         fn output(&self) -> &Self::Output {
             self.features.as_slice()
         }
+
+        fn clear_output(&mut self) {
+            self.features.clear();
+        }
     }
 
     impl FeatureVectorConsumer for FeatureVectorBuffer {
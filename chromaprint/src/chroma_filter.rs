@@ -1,47 +1,65 @@
+use crate::float::Float;
 use crate::stages::{FeatureVectorConsumer, Stage};
 
-pub struct ChromaFilter<C: FeatureVectorConsumer> {
-    coefficients: Box<[f64]>,
+pub struct ChromaFilter<C: FeatureVectorConsumer<T>, T: Float = f64> {
+    coefficients: Box<[T]>,
     consumer: C,
-    buffer: [[f64; 12]; 8],
-    result: [f64; 12],
+    /// Last rows of features, allocated for the number of bands of the first row.
+    buffer: Vec<T>,
+    result: Vec<T>,
     buffer_offset: usize,
     buffer_size: usize,
 }
 
-impl<C: FeatureVectorConsumer> ChromaFilter<C> {
-    pub(crate) fn new(coefficients: Box<[f64]>, consumer: C) -> Self {
+/// Maximum number of filter coefficients.
+pub(crate) const BUFFER_ROWS: usize = 8;
+
+impl<C: FeatureVectorConsumer<T>, T: Float> ChromaFilter<C, T> {
+    pub(crate) fn new(coefficients: &[f64], consumer: C) -> Self {
         Self {
-            coefficients,
+            coefficients: coefficients.iter().map(|&c| T::cast(c)).collect(),
             consumer,
-            buffer: std::array::from_fn(|_| [0.0; 12]),
-            result: [0.0; 12],
+            buffer: Vec::new(),
+            result: Vec::new(),
             buffer_offset: 0,
             buffer_size: 1,
         }
     }
 }
 
-impl<C: FeatureVectorConsumer> Stage for ChromaFilter<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Stage for ChromaFilter<C, T> {
     type Output = C::Output;
 
     fn output(&self) -> &Self::Output {
         self.consumer.output()
     }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
 }
 
-impl<C: FeatureVectorConsumer> FeatureVectorConsumer for ChromaFilter<C> {
-    fn consume(&mut self, features: &[f64]) {
-        self.buffer[self.buffer_offset].copy_from_slice(features);
-        self.buffer_offset = (self.buffer_offset + 1) % self.buffer.len();
+impl<C: FeatureVectorConsumer<T>, T: Float> FeatureVectorConsumer<T> for ChromaFilter<C, T> {
+    fn consume(&mut self, features: &[T]) {
+        let bands = features.len();
+        if self.result.len() != bands {
+            self.result = vec![T::zero(); bands];
+            self.buffer = vec![T::zero(); BUFFER_ROWS * bands];
+        }
+
+        self.buffer[self.buffer_offset * bands..][..bands].copy_from_slice(features);
+        self.buffer_offset = (self.buffer_offset + 1) % BUFFER_ROWS;
         if self.buffer_size >= self.coefficients.len() {
-            let offset = (self.buffer_offset + self.buffer.len() - self.coefficients.len())
-                % self.buffer.len();
-            self.result.fill(0.0);
-            for i in 0..self.result.len() {
+            let offset = (self.buffer_offset + BUFFER_ROWS - self.coefficients.len()) % BUFFER_ROWS;
+            self.result.fill(T::zero());
+            for i in 0..bands {
                 for j in 0..self.coefficients.len() {
-                    self.result[i] +=
-                        self.buffer[(offset + j) % self.buffer.len()][i] * self.coefficients[j];
+                    let row = (offset + j) % BUFFER_ROWS;
+                    self.result[i] += self.buffer[row * bands + i] * self.coefficients[j];
                 }
             }
 
@@ -54,6 +72,7 @@ impl<C: FeatureVectorConsumer> FeatureVectorConsumer for ChromaFilter<C> {
     fn reset(&mut self) {
         self.buffer_size = 1;
         self.buffer_offset = 0;
+        self.consumer.reset();
     }
 }
 
@@ -67,7 +86,7 @@ mod tests {
     fn blur2() {
         let coefficients = [0.5, 0.5];
         let mut image = Image::new(12);
-        let mut filter = ChromaFilter::new(coefficients.into(), &mut image);
+        let mut filter = ChromaFilter::new(&coefficients, &mut image);
         let d1 = [0.0, 5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d2 = [1.0, 6.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d3 = [2.0, 7.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
@@ -85,7 +104,7 @@ mod tests {
     fn blur3() {
         let coefficients = [0.5, 0.7, 0.5];
         let mut image = Image::new(12);
-        let mut filter = ChromaFilter::new(coefficients.into(), &mut image);
+        let mut filter = ChromaFilter::new(&coefficients, &mut image);
         let d1 = [0.0, 5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d2 = [1.0, 6.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d3 = [2.0, 7.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
@@ -96,16 +115,16 @@ mod tests {
         filter.consume(&d4);
         assert_eq!(2, image.rows());
         assert_eq_float!(1.7, image.get(0, 0));
-        assert_eq_float!(3.399999999999999, image.get(1, 0));
-        assert_eq_float!(10.199999999999999, image.get(0, 1));
-        assert_eq_float!(11.899999999999999, image.get(1, 1));
+        assert_eq_float!(3.4, image.get(1, 0));
+        assert_eq_float!(10.2, image.get(0, 1));
+        assert_eq_float!(11.9, image.get(1, 1));
     }
 
     #[test]
     fn diff() {
         let coefficients = [1.0, -1.0];
         let mut image = Image::new(12);
-        let mut filter = ChromaFilter::new(coefficients.into(), &mut image);
+        let mut filter = ChromaFilter::new(&coefficients, &mut image);
         let d1 = [0.0, 5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d2 = [1.0, 6.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
         let d3 = [2.0, 7.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
@@ -147,6 +166,10 @@ mod tests {
         fn output(&self) -> &Self::Output {
             self.data.as_slice()
         }
+
+        fn clear_output(&mut self) {
+            self.data.clear();
+        }
     }
 
     impl FeatureVectorConsumer for Image {
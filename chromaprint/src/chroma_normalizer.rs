@@ -1,28 +1,87 @@
+use std::marker::PhantomData;
+
+use crate::float::Float;
 use crate::stages::{FeatureVectorConsumer, Stage};
 
-pub struct ChromaNormalizer<C: FeatureVectorConsumer> {
+/// Norm used for normalizing the chroma features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaNorm {
+    /// Sum of absolute values.
+    L1,
+    /// Euclidean norm, used by Chromaprint.
+    #[default]
+    L2,
+    /// Maximum absolute value.
+    Max,
+}
+
+impl ChromaNorm {
+    fn of<T: Float>(self, values: &[T]) -> T {
+        match self {
+            ChromaNorm::L1 => values.iter().fold(T::zero(), |acc, &x| acc + x.abs()),
+            ChromaNorm::L2 => values
+                .iter()
+                .fold(T::zero(), |acc, &x| acc + x.powi(2))
+                .sqrt(),
+            ChromaNorm::Max => values.iter().fold(T::zero(), |acc, &x| acc.max(x.abs())),
+        }
+    }
+}
+
+/// Features with a norm below this are zeroed by default.
+pub(crate) const DEFAULT_NORM_EPSILON: f64 = 0.01;
+
+pub struct ChromaNormalizer<C: FeatureVectorConsumer<T>, T: Float = f64> {
     consumer: C,
+    norm: ChromaNorm,
+    eps: T,
+    /// Scratch copy of the features, reused between frames.
+    buffer: Vec<T>,
+    marker: PhantomData<T>,
 }
 
-impl<C: FeatureVectorConsumer> ChromaNormalizer<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> ChromaNormalizer<C, T> {
     pub(crate) fn new(consumer: C) -> Self {
-        Self { consumer }
+        Self {
+            consumer,
+            norm: ChromaNorm::default(),
+            eps: T::cast(DEFAULT_NORM_EPSILON),
+            buffer: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Uses the given norm, zeroing the features whose norm is below `eps`.
+    pub(crate) fn with_norm(mut self, norm: ChromaNorm, eps: f64) -> Self {
+        self.norm = norm;
+        self.eps = T::cast(eps);
+        self
     }
 }
 
-impl<C: FeatureVectorConsumer> Stage for ChromaNormalizer<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Stage for ChromaNormalizer<C, T> {
     type Output = C::Output;
 
     fn output(&self) -> &Self::Output {
         self.consumer.output()
     }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
 }
 
-impl<C: FeatureVectorConsumer> FeatureVectorConsumer for ChromaNormalizer<C> {
-    fn consume(&mut self, features: &[f64]) {
-        let mut features = features.to_vec();
-        normalize(&mut features, 0.01);
-        self.consumer.consume(&features);
+impl<C: FeatureVectorConsumer<T>, T: Float> FeatureVectorConsumer<T> for ChromaNormalizer<C, T> {
+    fn consume(&mut self, features: &[T]) {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(features);
+        normalize(&mut self.buffer, self.norm, self.eps);
+        self.consumer.consume(&self.buffer);
     }
 
     fn reset(&mut self) {
@@ -30,10 +89,10 @@ impl<C: FeatureVectorConsumer> FeatureVectorConsumer for ChromaNormalizer<C> {
     }
 }
 
-fn normalize(values: &mut [f64], eps: f64) {
-    let norm = values.iter().fold(0.0, |acc, &x| acc + x.powi(2)).sqrt();
+fn normalize<T: Float>(values: &mut [T], norm: ChromaNorm, eps: T) {
+    let norm = norm.of(values);
     if norm < eps {
-        values.fill(0.0);
+        values.fill(T::zero());
     } else {
         for x in values {
             *x /= norm;
@@ -44,39 +103,56 @@ fn normalize(values: &mut [f64], eps: f64) {
 #[cfg(test)]
 mod tests {
     use crate::assert_eq_float;
-    use crate::chroma_normalizer::normalize;
+    use crate::chroma_normalizer::{normalize, ChromaNorm};
 
     #[test]
     fn normalize_vector() {
-        let data = [0.1, 0.2, 0.4, 1.0];
+        let data: [f64; 4] = [0.1, 0.2, 0.4, 1.0];
         let normalized = [0.090909, 0.181818, 0.363636, 0.909091];
         let mut normalized_data = data;
-        normalize(&mut normalized_data, 0.01);
+        normalize(&mut normalized_data, ChromaNorm::L2, 0.01);
 
         for i in 0..4 {
             assert_eq_float!(normalized_data[i], normalized[i], 1e-5);
         }
     }
 
+    #[test]
+    fn normalize_vector_other_norms() {
+        let mut data: [f64; 4] = [0.1, 0.2, 0.4, 1.0];
+        normalize(&mut data, ChromaNorm::L1, 0.01);
+        assert_eq_float!(1.0, data.iter().sum::<f64>(), 1e-5);
+        assert_eq_float!(0.588235, data[3], 1e-5);
+
+        let mut data: [f64; 4] = [0.1, 0.2, 0.4, 1.0];
+        normalize(&mut data, ChromaNorm::Max, 0.01);
+        assert_eq_float!(1.0, data[3], 1e-5);
+        assert_eq_float!(0.4, data[2], 1e-5);
+
+        let mut data: [f64; 4] = [0.0, 0.001, 0.002, 0.003];
+        normalize(&mut data, ChromaNorm::L1, 0.001);
+        assert_eq_float!(0.5, data[3], 1e-5);
+    }
+
     #[test]
     fn normalize_vector_near_zero() {
-        let data = [0.0, 0.001, 0.002, 0.003];
+        let data: [f64; 4] = [0.0, 0.001, 0.002, 0.003];
         let mut normalized_data = data;
-        normalize(&mut normalized_data, 0.01);
+        normalize(&mut normalized_data, ChromaNorm::L2, 0.01);
 
-        for i in 0..4 {
-            assert_eq_float!(normalized_data[i], 0.0, 1e-5);
+        for value in normalized_data {
+            assert_eq_float!(value, 0.0, 1e-5);
         }
     }
 
     #[test]
     fn normalize_vector_zero() {
-        let data = [0.0, 0.0, 0.0, 0.0];
+        let data: [f64; 4] = [0.0, 0.0, 0.0, 0.0];
         let mut normalized_data = data;
-        normalize(&mut normalized_data, 0.01);
+        normalize(&mut normalized_data, ChromaNorm::L2, 0.01);
 
-        for i in 0..4 {
-            assert_eq_float!(normalized_data[i], 0.0, 1e-5);
+        for value in normalized_data {
+            assert_eq_float!(value, 0.0, 1e-5);
         }
     }
 }
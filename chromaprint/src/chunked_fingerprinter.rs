@@ -0,0 +1,193 @@
+use crate::audio_processor::{ConsumeError, ResetError};
+use crate::fingerprinter::{duration_to_samples, Configuration, Fingerprinter};
+
+/// A fingerprint of a single chunk of the audio stream.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk {
+    /// Position of the chunk in the stream (in seconds).
+    pub timestamp: f64,
+    /// Duration of the audio covered by the chunk (in seconds).
+    pub duration: f64,
+    /// Fingerprint of the chunk.
+    pub fingerprint: Vec<u32>,
+}
+
+/// Calculates separate fingerprints for consecutive chunks of an audio stream.
+///
+/// With overlap enabled, the internal state is preserved between chunks, so each chunk
+/// (but the first) also covers the audio from the end of the previous one, as long as
+/// the delay of the [Configuration]. Otherwise the chunks are fingerprinted independently.
+pub struct ChunkedFingerprinter {
+    printer: Fingerprinter,
+    chunk_duration: f64,
+    overlap: bool,
+    overlap_duration: f64,
+    sample_rate: u32,
+    channels: u32,
+    chunk_limit: usize,
+    extra_chunk_limit: usize,
+    chunk_size: usize,
+    timestamp: f64,
+}
+
+impl ChunkedFingerprinter {
+    /// Creates a new [ChunkedFingerprinter] splitting the audio into chunks of `chunk_duration` seconds.
+    ///
+    /// A zero duration disables splitting, so the whole stream becomes a single chunk.
+    pub fn new(config: &Configuration, chunk_duration: f64, overlap: bool) -> Self {
+        Self {
+            printer: Fingerprinter::new(config),
+            chunk_duration,
+            overlap,
+            overlap_duration: config.delay() as f64 / f64::from(config.sample_rate()),
+            sample_rate: 0,
+            channels: 0,
+            chunk_limit: 0,
+            extra_chunk_limit: 0,
+            chunk_size: 0,
+            timestamp: 0.0,
+        }
+    }
+
+    /// Duration of the audio shared by two consecutive chunks (in seconds).
+    pub fn overlap_duration(&self) -> f64 {
+        if self.overlap {
+            self.overlap_duration
+        } else {
+            0.0
+        }
+    }
+
+    /// Resets the internal state to allow for a new stream.
+    pub fn start(&mut self, sample_rate: u32, channels: u32) -> Result<(), ResetError> {
+        self.channels = 0;
+        self.printer.start(sample_rate, channels)?;
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.chunk_limit = duration_to_samples(self.chunk_duration, sample_rate, channels);
+        self.extra_chunk_limit = if self.chunk_limit > 0 && self.overlap {
+            duration_to_samples(self.overlap_duration, sample_rate, channels)
+        } else {
+            0
+        };
+        self.chunk_size = 0;
+        self.timestamp = 0.0;
+        Ok(())
+    }
+
+    /// Adds a new portion of samples and returns all the chunks completed by it.
+    pub fn consume(&mut self, data: &[i16]) -> Result<Vec<Chunk>, ConsumeError> {
+        if self.channels == 0 {
+            return Err(ConsumeError::NotStarted);
+        }
+
+        let mut chunks = Vec::new();
+        let mut data = data;
+        while !data.is_empty() {
+            let part = if self.chunk_limit > 0 {
+                data.len()
+                    .min(self.chunk_limit + self.extra_chunk_limit - self.chunk_size)
+            } else {
+                data.len()
+            };
+
+            let (head, tail) = data.split_at(part);
+            self.printer.consume(head)?;
+            self.chunk_size += part;
+            data = tail;
+
+            if self.chunk_limit > 0 && self.chunk_size == self.chunk_limit + self.extra_chunk_limit
+            {
+                chunks.push(self.next_chunk());
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Finishes the last chunk, if it contains any samples.
+    pub fn finish(&mut self) -> Option<Chunk> {
+        if self.chunk_size == 0 {
+            return None;
+        }
+        self.printer.finish();
+        let chunk = self.make_chunk();
+        self.chunk_size = 0;
+        Some(chunk)
+    }
+
+    fn next_chunk(&mut self) -> Chunk {
+        self.printer.finish();
+        let chunk = self.make_chunk();
+        self.timestamp += chunk.duration;
+
+        if self.overlap {
+            self.printer.clear_fingerprint();
+            self.timestamp -= self.overlap_duration;
+        } else {
+            self.printer.reset();
+        }
+
+        // Only the first chunk needs additional samples to cover the delay.
+        self.extra_chunk_limit = 0;
+        self.chunk_size = 0;
+        chunk
+    }
+
+    fn make_chunk(&self) -> Chunk {
+        let frames =
+            self.chunk_size.saturating_sub(self.extra_chunk_limit) / self.channels as usize;
+        Chunk {
+            timestamp: self.timestamp,
+            duration: frames as f64 / f64::from(self.sample_rate) + self.overlap_duration(),
+            fingerprint: self.printer.fingerprint().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert_eq_float;
+    use crate::chunked_fingerprinter::ChunkedFingerprinter;
+    use crate::fingerprinter::Configuration;
+    use crate::io::{read_pcm_file, PcmFormat};
+
+    #[test]
+    fn chunks() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let config = Configuration::preset_test2();
+
+        let mut printer = ChunkedFingerprinter::new(&config, 0.5, false);
+        printer.start(11025, 1).unwrap();
+        let mut chunks = Vec::new();
+        for part in data.chunks(1000) {
+            chunks.extend(printer.consume(part).unwrap());
+        }
+        chunks.extend(printer.finish());
+
+        let total = data.len() / 5513;
+        assert!(chunks.len() >= total);
+        for (i, chunk) in chunks.iter().take(total).enumerate() {
+            assert_eq_float!(i as f64 * 0.5, chunk.timestamp, 1e-3);
+            assert_eq_float!(0.5, chunk.duration, 1e-3);
+        }
+    }
+
+    #[test]
+    fn overlapping_chunks() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let config = Configuration::preset_test2();
+
+        let mut printer = ChunkedFingerprinter::new(&config, 2.0, true);
+        printer.start(11025, 1).unwrap();
+        let overlap = printer.overlap_duration();
+        let mut chunks = printer.consume(&data).unwrap();
+        chunks.extend(printer.finish());
+
+        assert!(chunks.len() >= 2);
+        assert_eq_float!(0.0, chunks[0].timestamp);
+        assert_eq_float!(2.0 + overlap, chunks[0].duration, 1e-3);
+        assert_eq_float!(2.0, chunks[1].timestamp, 1e-3);
+        assert!(chunks.iter().all(|chunk| !chunk.fingerprint.is_empty()));
+    }
+}
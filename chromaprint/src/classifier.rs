@@ -1,7 +1,9 @@
 use crate::filter::{Filter, Image};
 use crate::quantize::Quantizer;
 
+/// Calculates two bits of a fingerprint item by quantizing the response of a [Filter].
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Classifier {
     filter: Filter,
     quantizer: Quantizer,
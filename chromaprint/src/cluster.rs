@@ -0,0 +1,147 @@
+use crate::fingerprint_matcher::{judge_similarity, match_many, MatchError, SimilarityOptions};
+use crate::Configuration;
+
+/// Group of fingerprints found by [cluster], connected by pairs of similar ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cluster {
+    /// Indices of all fingerprints of the cluster in ascending order.
+    pub members: Vec<usize>,
+
+    /// Index of the fingerprint representing the whole cluster.
+    ///
+    /// It's the longest one, which is the most likely to be the complete recording.
+    /// Ties are resolved in favor of the fingerprint with the lowest index.
+    pub representative: usize,
+}
+
+impl Cluster {
+    /// Checks if the cluster contains more than one fingerprint, i.e. any duplicates.
+    pub fn has_duplicates(&self) -> bool {
+        self.members.len() > 1
+    }
+}
+
+/// Groups fingerprints into clusters of near-duplicates.
+///
+/// All pairs of fingerprints are compared with the same rules as [are_similar](crate::are_similar).
+/// Two fingerprints are in the same cluster if they are connected by a chain of similar pairs,
+/// so members of a cluster don't have to be all similar to each other.
+///
+/// Every fingerprint belongs to exactly one cluster, the ones without any duplicates form
+/// clusters of their own. Clusters are ordered by their first member.
+pub fn cluster(
+    fingerprints: &[&[u32]],
+    config: &Configuration,
+    options: &SimilarityOptions,
+) -> Result<Vec<Cluster>, MatchError> {
+    let mut sets = DisjointSets::new(fingerprints.len());
+    for (i, fp) in fingerprints.iter().enumerate() {
+        let results = match_many(fp, &fingerprints[i + 1..], config)?;
+        for (j, result) in (i + 1..).zip(results) {
+            if judge_similarity(&result, options).0 {
+                sets.union(i, j);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut cluster_of_root: Vec<Option<usize>> = vec![None; fingerprints.len()];
+    for i in 0..fingerprints.len() {
+        let root = sets.find(i);
+        match cluster_of_root[root] {
+            Some(index) => {
+                let cluster = &mut clusters[index];
+                cluster.members.push(i);
+                if fingerprints[i].len() > fingerprints[cluster.representative].len() {
+                    cluster.representative = i;
+                }
+            }
+            None => {
+                cluster_of_root[root] = Some(clusters.len());
+                clusters.push(Cluster {
+                    members: vec![i],
+                    representative: i,
+                });
+            }
+        }
+    }
+    Ok(clusters)
+}
+
+/// Union-find structure with path halving and union by size.
+struct DisjointSets {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{cluster, Cluster};
+    use crate::utils::random_fingerprint;
+    use crate::{Configuration, SimilarityOptions};
+
+    #[test]
+    fn near_duplicates() {
+        let a = random_fingerprint(300, 1);
+        let b = random_fingerprint(300, 2);
+        let c = random_fingerprint(300, 3);
+        // Overlapping clips of `a`, the first and the last are connected only through the middle one.
+        let a1 = &a[..150];
+        let a2 = &a[75..225];
+        let a3 = &a[150..];
+        let b1 = &b[50..250];
+
+        let fingerprints = [a1, b1, a2, &c[..], a3, &b[..]];
+        let options = SimilarityOptions::default().with_min_coverage(0.4);
+        let clusters = cluster(&fingerprints, &Configuration::preset_test2(), &options).unwrap();
+        assert_eq!(
+            clusters,
+            vec![
+                Cluster {
+                    members: vec![0, 2, 4],
+                    representative: 0,
+                },
+                Cluster {
+                    members: vec![1, 5],
+                    representative: 5,
+                },
+                Cluster {
+                    members: vec![3],
+                    representative: 3,
+                },
+            ]
+        );
+        assert!(clusters[0].has_duplicates());
+        assert!(!clusters[2].has_duplicates());
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
 use crate::Configuration;
 
 /// Number of "normal" bits.
@@ -44,55 +47,161 @@ impl<'a> FingerprintCompressor<'a> {
 
     /// Compress the fingerprint.
     pub fn compress(&self, fingerprint: &[u32]) -> Vec<u8> {
-        let size = fingerprint.len();
-        let (normal_bits, exceptional_bits) = fingerprint
+        compress(self.0.id(), fingerprint)
+    }
+
+    /// Compress the fingerprint directly into a writer.
+    ///
+    /// Unlike [FingerprintCompressor::compress] it doesn't allocate the compressed data in memory.
+    pub fn compress_to(&self, fingerprint: &[u32], writer: &mut impl Write) -> std::io::Result<()> {
+        compress_to(self.0.id(), fingerprint, writer)
+    }
+
+    /// Compress the fingerprint into a buffer, returning the number of written bytes.
+    ///
+    /// The buffer must be at least [FingerprintCompressor::compressed_len] bytes long.
+    pub fn compress_into(
+        &self,
+        fingerprint: &[u32],
+        buffer: &mut [u8],
+    ) -> Result<usize, BufferTooSmall> {
+        let required = self.compressed_len(fingerprint);
+        if buffer.len() < required {
+            return Err(BufferTooSmall { required });
+        }
+        let mut output = &mut buffer[..required];
+        compress_to(self.0.id(), fingerprint, &mut output).expect("buffer has the required size");
+        Ok(required)
+    }
+
+    /// Returns the size of the compressed fingerprint (in bytes).
+    pub fn compressed_len(&self, fingerprint: &[u32]) -> usize {
+        Self::estimated_size(fingerprint)
+    }
+
+    /// Returns the size of the compressed fingerprint (in bytes) without compressing it.
+    ///
+    /// The size is exact and doesn't depend on the algorithm, so it can be computed before
+    /// a configuration is known, e.g. to pre-allocate storage or enforce quotas.
+    pub fn estimated_size(fingerprint: &[u32]) -> usize {
+        let mut normal_count = 0;
+        let mut exceptional_count = 0;
+        let mut last_subfp = 0;
+        for &subfp in fingerprint {
+            let mut bits = subfp ^ last_subfp;
+            last_subfp = subfp;
+            // Each set bit and the terminating zero take one normal value.
+            normal_count += bits.count_ones() as usize + 1;
+            let mut last_bit_index = 0;
+            while bits != 0 {
+                let bit_index = bits.trailing_zeros() + 1;
+                exceptional_count +=
+                    usize::from(bit_index - last_bit_index >= u32::from(MAX_NORMAL_VALUE));
+                last_bit_index = bit_index;
+                bits &= bits - 1;
+            }
+        }
+        4 + packed_intn_array_len(normal_count, 3) + packed_intn_array_len(exceptional_count, 5)
+    }
+}
+
+impl<'a> From<&'a Configuration> for FingerprintCompressor<'a> {
+    fn from(value: &'a Configuration) -> Self {
+        Self(value)
+    }
+}
+
+/// Compress the fingerprint calculated with the algorithm of a given ID.
+pub(crate) fn compress(algorithm: u8, fingerprint: &[u32]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(4 + fingerprint.len() * 4);
+    compress_to(algorithm, fingerprint, &mut output).expect("writing to a Vec never fails");
+    output
+}
+
+/// Compress the fingerprint calculated with the algorithm of a given ID into a writer.
+pub(crate) fn compress_to(
+    algorithm: u8,
+    fingerprint: &[u32],
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    let size = fingerprint.len();
+
+    #[allow(clippy::cast_possible_truncation)]
+    let header = [
+        algorithm,
+        ((size >> 16) & 0xFF) as u8,
+        ((size >> 8) & 0xFF) as u8,
+        (size & 0xFF) as u8,
+    ];
+    writer.write_all(&header)?;
+
+    let compressed_bits = || {
+        fingerprint
             .iter()
             .scan(0, |last_subfp, current_subfp| {
                 let value = current_subfp ^ *last_subfp;
                 *last_subfp = *current_subfp;
                 Some(value)
             })
-            .flat_map(Self::compress_subfingerprint)
-            .fold(
-                (
-                    Vec::<u8>::with_capacity(size),
-                    Vec::<u8>::with_capacity(size),
-                ),
-                |(mut normal_bits, mut exceptional_bits), (normal_value, exceptional_value)| {
-                    normal_bits.push(normal_value);
-                    if let Some(exceptional_value) = exceptional_value {
-                        exceptional_bits.push(exceptional_value);
-                    }
-                    (normal_bits, exceptional_bits)
-                },
-            );
-
-        let header_size = 4;
-        let normal_size = packed_intn_array_len(normal_bits.len(), 3);
-        let exceptional_size = packed_intn_array_len(exceptional_bits.len(), 5);
-        let expected_size = header_size + normal_size + exceptional_size;
+            .flat_map(FingerprintCompressor::compress_subfingerprint)
+    };
 
-        #[allow(clippy::cast_possible_truncation)]
-        let output = [
-            self.0.id(),
-            ((size >> 16) & 0xFF) as u8,
-            ((size >> 8) & 0xFF) as u8,
-            (size & 0xFF) as u8,
-        ];
+    let mut normal_bits = PackedIntNWriter::<_, 3>::new(writer);
+    for (normal_value, _) in compressed_bits() {
+        normal_bits.push(normal_value)?;
+    }
+    let writer = normal_bits.finish()?;
 
-        let output = output
-            .into_iter()
-            .chain(iter_packed_intn_array::<3>(&normal_bits))
-            .chain(iter_packed_intn_array::<5>(&exceptional_bits))
-            .collect::<Vec<u8>>();
-        debug_assert_eq!(output.len(), expected_size);
-        output
+    let mut exceptional_bits = PackedIntNWriter::<_, 5>::new(writer);
+    for exceptional_value in compressed_bits().filter_map(|(_, exceptional)| exceptional) {
+        exceptional_bits.push(exceptional_value)?;
     }
+    exceptional_bits.finish()?;
+    Ok(())
 }
 
-impl<'a> From<&'a Configuration> for FingerprintCompressor<'a> {
-    fn from(value: &'a Configuration) -> Self {
-        Self(value)
+/// Writes values as packed Int<N> array without buffering the whole array.
+struct PackedIntNWriter<'w, W: Write, const N: usize> {
+    writer: &'w mut W,
+    values: [u8; 8],
+    len: usize,
+}
+
+impl<'w, W: Write, const N: usize> PackedIntNWriter<'w, W, N> {
+    fn new(writer: &'w mut W) -> Self {
+        Self {
+            writer,
+            values: [0; 8],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: u8) -> std::io::Result<()> {
+        self.values[self.len] = value;
+        self.len += 1;
+        if self.len == self.values.len() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut packed = [0u8; N];
+        let mut size = 0;
+        for (dst, byte) in packed
+            .iter_mut()
+            .zip(iter_packed_intn_array::<N>(&self.values[..self.len]))
+        {
+            *dst = byte;
+            size += 1;
+        }
+        self.len = 0;
+        self.writer.write_all(&packed[..size])
+    }
+
+    fn finish(mut self) -> std::io::Result<&'w mut W> {
+        self.flush()?;
+        Ok(self.writer)
     }
 }
 
@@ -126,6 +235,123 @@ fn iter_packed_intn_array<const N: usize>(array: &[u8]) -> impl Iterator<Item =
     })
 }
 
+/// Iterate values of a packed Int<N> array.
+fn iter_unpacked_intn_array<const N: usize>(array: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    let mask = (1u16 << N) - 1;
+    (0..array.len() * 8 / N).map(move |i| {
+        let bit_index = i * N;
+        let byte = bit_index / 8;
+        let mut bits = u16::from(array[byte]);
+        if let Some(&next) = array.get(byte + 1) {
+            bits |= u16::from(next) << 8;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let value = ((bits >> (bit_index % 8)) & mask) as u8;
+        value
+    })
+}
+
+/// Error returned when a buffer cannot fit a compressed fingerprint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooSmall {
+    /// The size of the compressed fingerprint (in bytes).
+    pub required: usize,
+}
+
+impl Display for BufferTooSmall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Buffer is too small, required {} bytes", self.required)
+    }
+}
+
+impl std::error::Error for BufferTooSmall {}
+
+/// Error returned when a compressed fingerprint cannot be decompressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressionError {
+    /// The data is too short to contain a header.
+    MissingHeader,
+    /// The data ends before all the items were decoded.
+    Truncated,
+    /// The data describes a bit outside of a fingerprint item.
+    InvalidBit,
+}
+
+impl Display for DecompressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressionError::MissingHeader => write!(f, "Compressed fingerprint has no header"),
+            DecompressionError::Truncated => write!(f, "Compressed fingerprint is truncated"),
+            DecompressionError::InvalidBit => {
+                write!(f, "Compressed fingerprint contains an invalid bit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecompressionError {}
+
+/// Decompress the fingerprint, returning it with the ID of its algorithm.
+pub(crate) fn decompress(data: &[u8]) -> Result<(u8, Vec<u32>), DecompressionError> {
+    if data.len() < 4 {
+        return Err(DecompressionError::MissingHeader);
+    }
+    let algorithm = data[0];
+    let size = usize::from(data[1]) << 16 | usize::from(data[2]) << 8 | usize::from(data[3]);
+
+    let mut normal_bits = Vec::with_capacity(size);
+    let mut remaining = size;
+    if remaining > 0 {
+        for value in iter_unpacked_intn_array::<3>(&data[4..]) {
+            normal_bits.push(value);
+            if value == 0 {
+                remaining -= 1;
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    if remaining > 0 {
+        return Err(DecompressionError::Truncated);
+    }
+
+    let exceptional_data = &data[4 + packed_intn_array_len(normal_bits.len(), 3)..];
+    let exceptional_count = normal_bits
+        .iter()
+        .filter(|&&value| value == MAX_NORMAL_VALUE)
+        .count();
+    if packed_intn_array_len(exceptional_count, 5) > exceptional_data.len() {
+        return Err(DecompressionError::Truncated);
+    }
+    let mut exceptional_bits = iter_unpacked_intn_array::<5>(exceptional_data);
+
+    let mut fingerprint = Vec::with_capacity(size);
+    let mut last_subfp = 0u32;
+    let mut value = 0u32;
+    let mut last_bit_index = 0u32;
+    for normal_value in normal_bits {
+        if normal_value == 0 {
+            last_subfp ^= value;
+            fingerprint.push(last_subfp);
+            value = 0;
+            last_bit_index = 0;
+            continue;
+        }
+
+        let mut delta = u32::from(normal_value);
+        if normal_value == MAX_NORMAL_VALUE {
+            delta += u32::from(exceptional_bits.next().unwrap_or(0));
+        }
+        last_bit_index += delta;
+        if last_bit_index > u32::BITS {
+            return Err(DecompressionError::InvalidBit);
+        }
+        value |= 1 << (last_bit_index - 1);
+    }
+    Ok((algorithm, fingerprint))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,5 +489,44 @@ mod tests {
         let compressor = FingerprintCompressor::from(&config);
         let output = compressor.compress(&INPUT);
         assert_eq!(output, OUTPUT);
+
+        let mut streamed = Vec::new();
+        compressor.compress_to(&INPUT, &mut streamed).unwrap();
+        assert_eq!(streamed, OUTPUT);
+
+        assert_eq!(compressor.compressed_len(&INPUT), OUTPUT.len());
+        assert_eq!(FingerprintCompressor::estimated_size(&INPUT), OUTPUT.len());
+        let mut buffer = [0; 256];
+        assert_eq!(
+            compressor.compress_into(&INPUT, &mut buffer),
+            Ok(OUTPUT.len())
+        );
+        assert_eq!(buffer[..OUTPUT.len()], OUTPUT);
+        assert_eq!(
+            compressor.compress_into(&INPUT, &mut buffer[..100]),
+            Err(BufferTooSmall {
+                required: OUTPUT.len()
+            })
+        );
+        assert_eq!(decompress(&OUTPUT), Ok((config.id(), INPUT.to_vec())));
+    }
+
+    #[test]
+    fn test_decompression() {
+        let input = [0, u32::MAX, 0x8000_0001, 0x8000_0001, 0x0FCA_F446];
+        let config = Configuration::default();
+        let compressed = FingerprintCompressor::from(&config).compress(&input);
+        assert_eq!(
+            FingerprintCompressor::estimated_size(&input),
+            compressed.len()
+        );
+        assert_eq!(FingerprintCompressor::estimated_size(&[]), 4);
+        assert_eq!(decompress(&compressed), Ok((config.id(), input.to_vec())));
+        assert_eq!(decompress(&[1, 0, 0, 0]), Ok((1, Vec::new())));
+        assert_eq!(
+            decompress(&compressed[..compressed.len() - 2]),
+            Err(DecompressionError::Truncated)
+        );
+        assert_eq!(decompress(&[1, 0]), Err(DecompressionError::MissingHeader));
     }
 }
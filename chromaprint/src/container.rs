@@ -0,0 +1,289 @@
+//! Container format for storing many fingerprints in a single file (usually with `.fpb` extension).
+//!
+//! The file starts with the [MAGIC] bytes and a version byte, followed by entries until the end
+//! of the file. Each entry consists of:
+//! - length of its ID (2 bytes, little-endian) and the ID itself encoded as UTF-8,
+//! - ID of the algorithm used to calculate the fingerprint (1 byte),
+//! - length of the compressed fingerprint (4 bytes, little-endian) and the compressed fingerprint.
+//!
+//! ```
+//! use rusty_chromaprint::container::{ContainerReader, ContainerWriter};
+//! use rusty_chromaprint::Fingerprint;
+//!
+//! let mut writer = ContainerWriter::new(Vec::new()).unwrap();
+//! writer.write("track-1", &Fingerprint::new(1, vec![1, 2, 3])).unwrap();
+//! let data = writer.finish().unwrap();
+//!
+//! let entries = ContainerReader::new(data.as_slice()).unwrap();
+//! for entry in entries {
+//!     let entry = entry.unwrap();
+//!     assert_eq!("track-1", entry.id);
+//!     assert_eq!(&[1, 2, 3], entry.fingerprint.items());
+//! }
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+
+use crate::compression::DecompressionError;
+use crate::fingerprint::Fingerprint;
+
+/// Bytes identifying the container format.
+pub const MAGIC: [u8; 4] = *b"FPB\0";
+
+/// Version of the format written by [ContainerWriter].
+pub const VERSION: u8 = 1;
+
+/// Fingerprint stored in a container together with its ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    /// ID of the fingerprint, e.g. a path or a database key.
+    pub id: String,
+
+    /// The stored fingerprint.
+    pub fingerprint: Fingerprint,
+}
+
+/// Writes fingerprints into a container, one entry at a time.
+pub struct ContainerWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> ContainerWriter<W> {
+    /// Creates a writer, writing the header of the container right away.
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(Self { writer })
+    }
+
+    /// Appends a fingerprint with the given ID.
+    ///
+    /// IDs longer than 65535 bytes are rejected with [ErrorKind::InvalidInput].
+    pub fn write(&mut self, id: &str, fingerprint: &Fingerprint) -> std::io::Result<()> {
+        let id_len = u16::try_from(id.len())
+            .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "ID is too long"))?;
+        let data = fingerprint.compress();
+        self.writer.write_all(&id_len.to_le_bytes())?;
+        self.writer.write_all(id.as_bytes())?;
+        self.writer.write_all(&[fingerprint.algorithm()])?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&data)
+    }
+
+    /// Flushes the written entries and returns the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads entries of a container one by one, without loading the whole file into memory.
+pub struct ContainerReader<R> {
+    reader: R,
+    finished: bool,
+}
+
+impl<R: Read> ContainerReader<R> {
+    /// Creates a reader, checking the header of the container.
+    pub fn new(mut reader: R) -> Result<Self, ContainerError> {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header).map_err(truncated)?;
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(ContainerError::InvalidMagic);
+        }
+        match header[MAGIC.len()] {
+            VERSION => Ok(Self {
+                reader,
+                finished: false,
+            }),
+            version => Err(ContainerError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Reads the next entry, returning `None` at the end of the container.
+    pub fn read_entry(&mut self) -> Result<Option<Entry>, ContainerError> {
+        let mut id_len = [0; 2];
+        if !self.read_first(&mut id_len)? {
+            return Ok(None);
+        }
+        let mut id = vec![0; usize::from(u16::from_le_bytes(id_len))];
+        self.reader.read_exact(&mut id).map_err(truncated)?;
+        let id = String::from_utf8(id).map_err(|_| ContainerError::InvalidId)?;
+
+        let mut header = [0; 5];
+        self.reader.read_exact(&mut header).map_err(truncated)?;
+        let algorithm = header[0];
+        let data_len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(u64::from(data_len))
+            .read_to_end(&mut data)?;
+        if data.len() != data_len as usize {
+            return Err(ContainerError::Truncated);
+        }
+
+        let fingerprint = Fingerprint::decompress(&data)?;
+        if fingerprint.algorithm() != algorithm {
+            return Err(ContainerError::AlgorithmMismatch { id });
+        }
+        Ok(Some(Entry { id, fingerprint }))
+    }
+
+    /// Fills the buffer, returning `false` if the reader is already at its end.
+    fn read_first(&mut self, buffer: &mut [u8]) -> Result<bool, ContainerError> {
+        loop {
+            match self.reader.read(&mut buffer[..1]) {
+                Ok(0) => return Ok(false),
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.reader
+            .read_exact(&mut buffer[1..])
+            .map_err(truncated)?;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for ContainerReader<R> {
+    type Item = Result<Entry, ContainerError>;
+
+    /// Returns the next entry. Stops after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let entry = self.read_entry().transpose();
+        self.finished = !matches!(entry, Some(Ok(_)));
+        entry
+    }
+}
+
+/// Error returned when a container cannot be read.
+#[derive(Debug)]
+pub enum ContainerError {
+    /// The underlying reader failed.
+    Io(std::io::Error),
+    /// The data doesn't start with the [MAGIC] bytes.
+    InvalidMagic,
+    /// The container was written in an unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of the header or an entry.
+    Truncated,
+    /// An ID is not valid UTF-8.
+    InvalidId,
+    /// A compressed fingerprint cannot be decompressed.
+    Decompression(DecompressionError),
+    /// The algorithm of an entry doesn't match the one of its compressed fingerprint.
+    AlgorithmMismatch {
+        /// ID of the invalid entry.
+        id: String,
+    },
+}
+
+impl Display for ContainerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerError::Io(e) => e.fmt(f),
+            ContainerError::InvalidMagic => write!(f, "Not a fingerprint container"),
+            ContainerError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported container version: {version}")
+            }
+            ContainerError::Truncated => write!(f, "Container is truncated"),
+            ContainerError::InvalidId => write!(f, "Entry ID is not valid UTF-8"),
+            ContainerError::Decompression(e) => e.fmt(f),
+            ContainerError::AlgorithmMismatch { id } => {
+                write!(f, "Entry {id} has an inconsistent algorithm")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContainerError::Io(e) => Some(e),
+            ContainerError::Decompression(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ContainerError {
+    fn from(e: std::io::Error) -> Self {
+        ContainerError::Io(e)
+    }
+}
+
+impl From<DecompressionError> for ContainerError {
+    fn from(e: DecompressionError) -> Self {
+        ContainerError::Decompression(e)
+    }
+}
+
+fn truncated(e: std::io::Error) -> ContainerError {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        ContainerError::Truncated
+    } else {
+        ContainerError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::{ContainerError, ContainerReader, ContainerWriter, Entry};
+    use crate::Fingerprint;
+
+    #[test]
+    fn round_trip() {
+        let entries = vec![
+            Entry {
+                id: "a.mp3".to_owned(),
+                fingerprint: Fingerprint::new(1, vec![0x0FCAF446, 0xE3519E89, 0]),
+            },
+            Entry {
+                id: "żółw.flac".to_owned(),
+                fingerprint: Fingerprint::new(2, Vec::new()),
+            },
+        ];
+        let mut writer = ContainerWriter::new(Vec::new()).unwrap();
+        for entry in &entries {
+            writer.write(&entry.id, &entry.fingerprint).unwrap();
+        }
+        let data = writer.finish().unwrap();
+
+        let reader = ContainerReader::new(data.as_slice()).unwrap();
+        let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, read);
+
+        let empty = ContainerWriter::new(Vec::new()).unwrap().finish().unwrap();
+        assert_eq!(0, ContainerReader::new(empty.as_slice()).unwrap().count());
+
+        let mut reader = ContainerReader::new(&data[..data.len() - 1]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(ContainerError::Truncated))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(matches!(
+            ContainerReader::new(&b"RIFF\x01"[..]),
+            Err(ContainerError::InvalidMagic)
+        ));
+        assert!(matches!(
+            ContainerReader::new(&b"FPB\0\x09"[..]),
+            Err(ContainerError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(
+            ContainerReader::new(&b"FPB"[..]),
+            Err(ContainerError::Truncated)
+        ));
+    }
+}
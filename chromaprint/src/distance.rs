@@ -0,0 +1,249 @@
+//! Helpers for measuring differences between fingerprints item by item.
+//!
+//! All the functions compare items at the same indices and ignore the excess items
+//! of the longer fingerprint. Use them on already aligned fingerprints, e.g. after
+//! slicing them with offsets of a [Segment](crate::Segment).
+
+/// Number of bits in a single fingerprint item.
+pub const ITEM_BITS: u32 = u32::BITS;
+
+/// Maximum number of classifiers, each contributing two bits to an item.
+pub const ITEM_CLASSIFIERS: usize = ITEM_BITS as usize / 2;
+
+/// Splits an item into the quantized responses (0 to 3) of its classifiers, undoing the gray coding.
+///
+/// The first response comes from the highest bits, so for configurations with 16 classifiers
+/// (like all the presets) the `i`-th response is the one of the `i`-th classifier.
+pub fn classifier_outputs(item: u32) -> [u8; ITEM_CLASSIFIERS] {
+    let mut outputs = [0; ITEM_CLASSIFIERS];
+    for (i, output) in outputs.iter_mut().enumerate() {
+        let gray = (item >> (ITEM_BITS as usize - 2 * (i + 1))) & 0b11;
+        *output = (gray ^ (gray >> 1)) as u8;
+    }
+    outputs
+}
+
+/// Counts the corresponding items for which each classifier gave a different response.
+pub fn classifier_differences(fp1: &[u32], fp2: &[u32]) -> [u32; ITEM_CLASSIFIERS] {
+    let mut differences = [0; ITEM_CLASSIFIERS];
+    for (&a, &b) in std::iter::zip(fp1, fp2) {
+        let diff = a ^ b;
+        for (i, count) in differences.iter_mut().enumerate() {
+            let bits = diff >> (ITEM_BITS as usize - 2 * (i + 1));
+            *count += u32::from(bits & 0b11 != 0);
+        }
+    }
+    differences
+}
+
+/// Returns the number of different bits between two items.
+#[inline]
+pub fn item_distance(a: u32, b: u32) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Returns the number of set bits in all items of a fingerprint.
+pub fn popcount(fp: &[u32]) -> u32 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    return crate::simd::popcount(fp);
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    return fp.iter().map(|item| item.count_ones()).sum();
+}
+
+/// Returns the number of different bits for each pair of corresponding items.
+pub fn bit_errors<'a>(fp1: &'a [u32], fp2: &'a [u32]) -> impl Iterator<Item = u32> + 'a {
+    std::iter::zip(fp1, fp2).map(|(&a, &b)| item_distance(a, b))
+}
+
+/// Returns the total number of different bits between two fingerprints.
+pub fn hamming(fp1: &[u32], fp2: &[u32]) -> u32 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    return crate::simd::hamming(fp1, fp2);
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    return bit_errors(fp1, fp2).sum();
+}
+
+/// Returns the fraction of different bits between two fingerprints.
+///
+/// The result is in range from 0.0 (identical) to 1.0 (all bits differ). Unrelated
+/// fingerprints are expected to be around 0.5. Returns 0.0 if there is nothing to compare.
+pub fn error_rate(fp1: &[u32], fp2: &[u32]) -> f64 {
+    let len = fp1.len().min(fp2.len());
+    if len == 0 {
+        return 0.0;
+    }
+    f64::from(hamming(fp1, fp2)) / (len as f64 * f64::from(ITEM_BITS))
+}
+
+/// Returns the [error rate](error_rate) of at most `len` items of fingerprints aligned with the given offset.
+///
+/// The offset has the same meaning as in [diff], i.e. `fp1[i + offset]` is compared with `fp2[i]`.
+/// The compared region is shortened to the overlap of both fingerprints.
+pub fn bit_error_rate(fp1: &[u32], fp2: &[u32], offset: isize, len: usize) -> f64 {
+    let fp1 = fp1.get(offset.max(0) as usize..).unwrap_or_default();
+    let fp2 = fp2.get((-offset).max(0) as usize..).unwrap_or_default();
+    error_rate(&fp1[..len.min(fp1.len())], fp2)
+}
+
+/// Returns [error rates](error_rate) of all windows of `window` consecutive items.
+///
+/// The `i`-th value corresponds to items from `i` to `i + window`. The result is empty
+/// if the window is empty or longer than the compared fingerprints.
+pub fn windowed_error_rate(fp1: &[u32], fp2: &[u32], window: usize) -> Vec<f64> {
+    let len = fp1.len().min(fp2.len());
+    if window == 0 || window > len {
+        return Vec::new();
+    }
+
+    let errors = bit_errors(fp1, fp2).collect::<Vec<_>>();
+    let bits = window as f64 * f64::from(ITEM_BITS);
+    let mut sum: u32 = errors[..window].iter().sum();
+    let mut rates = Vec::with_capacity(len - window + 1);
+    rates.push(f64::from(sum) / bits);
+    for i in window..len {
+        sum = sum + errors[i] - errors[i - window];
+        rates.push(f64::from(sum) / bits);
+    }
+    rates
+}
+
+/// Differences between two fingerprints in the region where they overlap, returned by [diff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diff {
+    /// Index of the first compared item of the first fingerprint.
+    pub start1: usize,
+    /// Index of the first compared item of the second fingerprint.
+    pub start2: usize,
+    /// XOR of each pair of corresponding items, with bits set where they differ.
+    pub xor: Vec<u32>,
+    /// Number of different bits of each pair of corresponding items.
+    pub bit_errors: Vec<u32>,
+    /// Number of items for which each classifier gave a different response.
+    pub classifier_mismatches: [u32; ITEM_CLASSIFIERS],
+}
+
+impl Diff {
+    /// Number of compared items.
+    pub fn len(&self) -> usize {
+        self.xor.len()
+    }
+
+    /// Checks if the fingerprints don't overlap at all.
+    pub fn is_empty(&self) -> bool {
+        self.xor.is_empty()
+    }
+
+    /// Fraction of different bits in the compared region, see [error_rate].
+    pub fn error_rate(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let errors: u32 = self.bit_errors.iter().sum();
+        f64::from(errors) / (self.len() as f64 * f64::from(ITEM_BITS))
+    }
+}
+
+/// Compares two fingerprints aligned with the given offset item by item.
+///
+/// The offset has the same meaning as [MatchResult::best_offset](crate::MatchResult::best_offset),
+/// i.e. `fp1[i + offset]` is compared with `fp2[i]`.
+pub fn diff(fp1: &[u32], fp2: &[u32], offset: isize) -> Diff {
+    let start1 = offset.max(0) as usize;
+    let start2 = (-offset).max(0) as usize;
+    let fp1 = fp1.get(start1..).unwrap_or_default();
+    let fp2 = fp2.get(start2..).unwrap_or_default();
+
+    let xor: Vec<u32> = std::iter::zip(fp1, fp2).map(|(&a, &b)| a ^ b).collect();
+    Diff {
+        start1,
+        start2,
+        bit_errors: xor.iter().map(|x| x.count_ones()).collect(),
+        classifier_mismatches: classifier_differences(fp1, fp2),
+        xor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert_eq_float;
+    use crate::distance::{
+        bit_error_rate, bit_errors, classifier_differences, classifier_outputs, diff, error_rate,
+        hamming, item_distance, popcount, windowed_error_rate,
+    };
+
+    #[test]
+    fn items() {
+        assert_eq!(0, item_distance(0xDEADBEEF, 0xDEADBEEF));
+        assert_eq!(32, item_distance(0, u32::MAX));
+        assert_eq!(2, item_distance(0b0110, 0b0101));
+        assert_eq!(33, popcount(&[u32::MAX, 1]));
+    }
+
+    #[test]
+    fn classifiers() {
+        // Gray codes of 0, 1, 2 and 3 in the highest bits, followed by zeros.
+        let item = 0b00_01_11_10 << 24;
+        let mut expected = [0; 16];
+        expected[..4].copy_from_slice(&[0, 1, 2, 3]);
+        assert_eq!(expected, classifier_outputs(item));
+        assert_eq!([2; 16], classifier_outputs(u32::MAX));
+
+        let mut expected = [0; 16];
+        expected[1] = 2;
+        expected[15] = 1;
+        assert_eq!(
+            expected,
+            classifier_differences(&[item, 0, 7], &[item ^ (1 << 28), 0b11 << 28, 4])
+        );
+    }
+
+    #[test]
+    fn aligned_diff() {
+        let fp1 = [9, 9, 1, 2, 3];
+        let fp2 = [1, 2, 7];
+
+        let result = diff(&fp1, &fp2, 2);
+        assert_eq!((2, 0), (result.start1, result.start2));
+        assert_eq!(vec![0, 0, 4], result.xor);
+        assert_eq!(vec![0, 0, 1], result.bit_errors);
+        assert_eq!(1, result.classifier_mismatches[14]);
+        assert_eq!(1, result.classifier_mismatches.iter().sum::<u32>());
+        assert_eq_float!(1.0 / 96.0, result.error_rate());
+
+        let result = diff(&fp2, &fp1, -2);
+        assert_eq!((0, 2), (result.start1, result.start2));
+        assert_eq!(3, result.len());
+
+        assert!(diff(&fp1, &fp2, 10).is_empty());
+        assert!(diff(&fp1, &fp2, -10).is_empty());
+    }
+
+    #[test]
+    fn fingerprints() {
+        let fp1 = [0, 0, u32::MAX, 0xF];
+        let fp2 = [0, 1, 0];
+        assert_eq!(vec![0, 1, 32], bit_errors(&fp1, &fp2).collect::<Vec<_>>());
+        assert_eq!(33, hamming(&fp1, &fp2));
+        assert_eq_float!(33.0 / 96.0, error_rate(&fp1, &fp2));
+        assert_eq_float!(0.0, error_rate(&fp1, &[]));
+
+        assert_eq_float!(31.0 / 64.0, bit_error_rate(&fp1, &fp2, 1, 2));
+        assert_eq_float!(1.0 / 64.0, bit_error_rate(&fp1, &fp2, 0, 2));
+        assert_eq_float!(4.0 / 32.0, bit_error_rate(&fp2, &fp1, -3, 10));
+        assert_eq_float!(0.0, bit_error_rate(&fp1, &fp2, 5, 2));
+    }
+
+    #[test]
+    fn windows() {
+        let fp1 = [0, 0, 0, 0];
+        let fp2 = [u32::MAX, 0, 0xFFFF, 0];
+        let rates = windowed_error_rate(&fp1, &fp2, 2);
+        assert_eq!(3, rates.len());
+        assert_eq_float!(0.5, rates[0]);
+        assert_eq_float!(0.25, rates[1]);
+        assert_eq_float!(0.25, rates[2]);
+        assert!(windowed_error_rate(&fp1, &fp2, 5).is_empty());
+        assert!(windowed_error_rate(&fp1, &fp2, 0).is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::{Display, Formatter};
+
+#[cfg(feature = "acoustid")]
+use crate::acoustid::LookupError;
+use crate::audio_processor::{ConsumeError, ResetError};
+use crate::compression::{BufferTooSmall, DecompressionError};
+use crate::container::ContainerError;
+#[cfg(feature = "symphonia")]
+use crate::file::FileError;
+use crate::fingerprint::{ParseFingerprintError, RawBytesError};
+use crate::fingerprint_matcher::MatchError;
+use crate::mapped_index::MappedIndexError;
+use crate::minhash::MinHashError;
+#[cfg(feature = "cpal")]
+use crate::realtime::CaptureError;
+#[cfg(feature = "hound")]
+use crate::wav::WavError;
+
+/// Any error returned by this crate.
+///
+/// All the specific errors can be converted into it, so it can be used with `?` across the crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Reset(ResetError),
+    Consume(ConsumeError),
+    Match(MatchError),
+    Decompression(DecompressionError),
+    ParseFingerprint(ParseFingerprintError),
+    RawBytes(RawBytesError),
+    BufferTooSmall(BufferTooSmall),
+    Container(ContainerError),
+    MappedIndex(MappedIndexError),
+    MinHash(MinHashError),
+    Io(std::io::Error),
+    #[cfg(feature = "acoustid")]
+    Lookup(LookupError),
+    #[cfg(feature = "symphonia")]
+    File(FileError),
+    #[cfg(feature = "cpal")]
+    Capture(CaptureError),
+    #[cfg(feature = "hound")]
+    Wav(WavError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Reset(e) => e.fmt(f),
+            Error::Consume(e) => e.fmt(f),
+            Error::Match(e) => e.fmt(f),
+            Error::Decompression(e) => e.fmt(f),
+            Error::ParseFingerprint(e) => e.fmt(f),
+            Error::RawBytes(e) => e.fmt(f),
+            Error::BufferTooSmall(e) => e.fmt(f),
+            Error::Container(e) => e.fmt(f),
+            Error::MappedIndex(e) => e.fmt(f),
+            Error::MinHash(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+            #[cfg(feature = "acoustid")]
+            Error::Lookup(e) => e.fmt(f),
+            #[cfg(feature = "symphonia")]
+            Error::File(e) => e.fmt(f),
+            #[cfg(feature = "cpal")]
+            Error::Capture(e) => e.fmt(f),
+            #[cfg(feature = "hound")]
+            Error::Wav(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Reset(e) => Some(e),
+            Error::Consume(e) => Some(e),
+            Error::Match(e) => Some(e),
+            Error::Decompression(e) => Some(e),
+            Error::ParseFingerprint(e) => Some(e),
+            Error::RawBytes(e) => Some(e),
+            Error::BufferTooSmall(e) => Some(e),
+            Error::Container(e) => Some(e),
+            Error::MappedIndex(e) => Some(e),
+            Error::MinHash(e) => Some(e),
+            Error::Io(e) => Some(e),
+            #[cfg(feature = "acoustid")]
+            Error::Lookup(e) => Some(e),
+            #[cfg(feature = "symphonia")]
+            Error::File(e) => Some(e),
+            #[cfg(feature = "cpal")]
+            Error::Capture(e) => Some(e),
+            #[cfg(feature = "hound")]
+            Error::Wav(e) => Some(e),
+        }
+    }
+}
+
+impl From<ResetError> for Error {
+    fn from(e: ResetError) -> Self {
+        Error::Reset(e)
+    }
+}
+
+impl From<ConsumeError> for Error {
+    fn from(e: ConsumeError) -> Self {
+        Error::Consume(e)
+    }
+}
+
+impl From<MatchError> for Error {
+    fn from(e: MatchError) -> Self {
+        Error::Match(e)
+    }
+}
+
+impl From<DecompressionError> for Error {
+    fn from(e: DecompressionError) -> Self {
+        Error::Decompression(e)
+    }
+}
+
+impl From<ParseFingerprintError> for Error {
+    fn from(e: ParseFingerprintError) -> Self {
+        Error::ParseFingerprint(e)
+    }
+}
+
+impl From<RawBytesError> for Error {
+    fn from(e: RawBytesError) -> Self {
+        Error::RawBytes(e)
+    }
+}
+
+impl From<BufferTooSmall> for Error {
+    fn from(e: BufferTooSmall) -> Self {
+        Error::BufferTooSmall(e)
+    }
+}
+
+impl From<ContainerError> for Error {
+    fn from(e: ContainerError) -> Self {
+        Error::Container(e)
+    }
+}
+
+impl From<MappedIndexError> for Error {
+    fn from(e: MappedIndexError) -> Self {
+        Error::MappedIndex(e)
+    }
+}
+
+impl From<MinHashError> for Error {
+    fn from(e: MinHashError) -> Self {
+        Error::MinHash(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(feature = "acoustid")]
+impl From<LookupError> for Error {
+    fn from(e: LookupError) -> Self {
+        Error::Lookup(e)
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl From<FileError> for Error {
+    fn from(e: FileError) -> Self {
+        Error::File(e)
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<CaptureError> for Error {
+    fn from(e: CaptureError) -> Self {
+        Error::Capture(e)
+    }
+}
+
+#[cfg(feature = "hound")]
+impl From<WavError> for Error {
+    fn from(e: WavError) -> Self {
+        Error::Wav(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::fingerprint::Fingerprint;
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+
+    fn fingerprint_of_silence(rate: u32) -> Result<Fingerprint, Error> {
+        let mut printer = Fingerprinter::new(&Configuration::default());
+        printer.start(rate, 1)?;
+        printer.consume(&[0; 1024])?;
+        Ok(printer.finish_into())
+    }
+
+    #[test]
+    fn conversions() {
+        assert!(fingerprint_of_silence(11025).is_ok());
+        assert!(matches!(fingerprint_of_silence(0), Err(Error::Reset(_))));
+        assert!(matches!(
+            "AQ!".parse::<Fingerprint>().map_err(Error::from),
+            Err(Error::ParseFingerprint(_))
+        ));
+    }
+}
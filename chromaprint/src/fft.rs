@@ -1,26 +1,33 @@
-use std::collections::VecDeque;
 use std::sync::Arc;
 
-use rustfft::num_complex::{Complex, Complex64};
+use rustfft::num_complex::Complex;
 use rustfft::num_traits::Zero;
 
-use crate::stages::{AudioConsumer, FeatureVectorConsumer, Stage};
+use crate::fingerprinter::PipelineStats;
+use crate::float::Float;
+use crate::stages::{AudioConsumer, FeatureVectorConsumer, RawAudioConsumer, SpectrumSink, Stage};
 
-pub struct Fft<C: FeatureVectorConsumer> {
+pub struct Fft<C: FeatureVectorConsumer<T>, T: Float = f64> {
     consumer: C,
     frame_size: usize,
     frame_overlap: usize,
 
-    fft_plan: Arc<dyn rustfft::Fft<f64>>,
-    fft_buffer_complex: Box<[Complex64]>,
-    fft_frame: Box<[f64]>,
-    fft_scratch: Box<[Complex64]>,
-
-    window: Box<[f64]>,
-    ring_buf: VecDeque<f64>,
+    fft_plan: Arc<dyn rustfft::Fft<T>>,
+    fft_buffer_complex: Box<[Complex<T>]>,
+    fft_frame: Box<[T]>,
+    fft_scratch: Box<[Complex<T>]>,
+
+    window: Box<[T]>,
+    /// Samples of the current frame, starting with the ones overlapping the previous frame.
+    buffer: Box<[T]>,
+    buffer_offset: usize,
+    consumed_samples: usize,
+    frames: usize,
+    spectrum_sink: Option<SpectrumSink>,
+    spectrum: Vec<f64>,
 }
 
-impl<C: FeatureVectorConsumer> Fft<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Fft<C, T> {
     pub(crate) fn new(frame_size: usize, frame_overlap: usize, consumer: C) -> Self {
         let fft_plan = rustfft::FftPlanner::new().plan_fft_forward(frame_size);
 
@@ -28,75 +35,142 @@ impl<C: FeatureVectorConsumer> Fft<C> {
             consumer,
             frame_size,
             frame_overlap,
-            fft_buffer_complex: vec![Complex64::zero(); frame_size].into_boxed_slice(),
+            fft_buffer_complex: vec![Complex::zero(); frame_size].into_boxed_slice(),
             fft_scratch: vec![Complex::zero(); fft_plan.get_inplace_scratch_len()]
                 .into_boxed_slice(),
-            fft_frame: vec![0.0; 1 + frame_size / 2].into_boxed_slice(),
+            fft_frame: vec![T::zero(); 1 + frame_size / 2].into_boxed_slice(),
             fft_plan,
             window: make_hamming_window(frame_size, 1.0),
-            ring_buf: VecDeque::new(),
+            buffer: vec![T::zero(); frame_size].into_boxed_slice(),
+            buffer_offset: 0,
+            consumed_samples: 0,
+            frames: 0,
+            spectrum_sink: None,
+            spectrum: Vec::new(),
+        }
+    }
+
+    /// Copies the converted samples into the buffer, processing every completed frame.
+    fn consume_samples<S: Copy>(&mut self, data: &[S], convert: impl Fn(S) -> T) {
+        self.consumed_samples += data.len();
+        let mut data = data;
+        while !data.is_empty() {
+            let count = data.len().min(self.frame_size - self.buffer_offset);
+            let (head, tail) = data.split_at(count);
+            for (output, &input) in self.buffer[self.buffer_offset..].iter_mut().zip(head) {
+                *output = convert(input);
+            }
+            self.buffer_offset += count;
+            data = tail;
+
+            if self.buffer_offset == self.frame_size {
+                self.process_frame();
+                self.buffer
+                    .copy_within(self.frame_size - self.frame_overlap.., 0);
+                self.buffer_offset = self.frame_overlap;
+            }
         }
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    fn process_frame(&mut self) {
+        assert_eq!(self.fft_buffer_complex.len(), self.frame_size);
+        assert_eq!(self.window.len(), self.frame_size);
+
+        T::apply_window(&self.buffer, &self.window, &mut self.fft_buffer_complex);
+
+        self.fft_plan
+            .process_with_scratch(&mut self.fft_buffer_complex, &mut self.fft_scratch);
+
+        T::norm_sqr(
+            &self.fft_buffer_complex[..self.frame_size / 2],
+            &mut self.fft_frame,
+        );
+
+        if let Some(sink) = self.spectrum_sink.as_mut() {
+            self.spectrum.clear();
+            self.spectrum
+                .extend(self.fft_frame.iter().map(|&value| value.into_f64()));
+            sink(&self.spectrum);
+        }
+
+        self.consumer.consume(&self.fft_frame);
+        self.frames += 1;
+    }
 }
 
-impl<C: FeatureVectorConsumer> Stage for Fft<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> Stage for Fft<C, T> {
     type Output = C::Output;
 
     fn output(&self) -> &Self::Output {
         self.consumer.output()
     }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
 }
 
-impl<C: FeatureVectorConsumer> AudioConsumer<f64> for Fft<C> {
+impl<C: FeatureVectorConsumer<T>, T: Float> AudioConsumer<f64> for Fft<C, T> {
     fn reset(&mut self) {
+        self.buffer_offset = 0;
+        self.consumed_samples = 0;
+        self.frames = 0;
         self.consumer.reset();
     }
 
     fn consume(&mut self, data: &[f64]) {
-        self.ring_buf.extend(data.iter().copied());
-
-        while self.ring_buf.len() >= self.frame_size {
-            let window = self.ring_buf.iter().copied().take(self.frame_size);
-
-            assert_eq!(self.fft_buffer_complex.len(), self.frame_size);
-            assert_eq!(self.window.len(), self.frame_size);
-
-            for (i, (output, input)) in self.fft_buffer_complex.iter_mut().zip(window).enumerate() {
-                output.re = input * self.window[i];
-                output.im = 0.0;
-            }
-
-            self.fft_plan
-                .process_with_scratch(&mut self.fft_buffer_complex, &mut self.fft_scratch);
-
-            for i in 0..self.frame_size / 2 {
-                self.fft_frame[i] = self.fft_buffer_complex[i].norm_sqr();
-            }
-
-            self.consumer.consume(&self.fft_frame);
-            self.ring_buf.drain(..self.frame_size - self.frame_overlap);
-        }
+        self.consume_samples(data, T::cast);
     }
 
     fn flush(&mut self) {
         // It makes sense to pad the remaining samples with zeros and process the last frame,
         // but the reference implementation doesn't do it.
-        // if !self.ring_buf.is_empty() && self.ring_buf.len() < self.frame_size {
-        //     self.ring_buf.resize(self.frame_size, 0.0);
-        //     self.consume(&[]);
+        // if self.buffer_offset > self.frame_overlap {
+        //     self.buffer[self.buffer_offset..].fill(T::zero());
+        //     self.process_frame();
         // }
     }
 }
 
-fn make_hamming_window(size: usize, scale: f64) -> Box<[f64]> {
+impl<C: FeatureVectorConsumer<T>, T: Float> RawAudioConsumer for Fft<C, T> {
+    /// Converts the samples straight into the internal buffer, without any intermediate copies.
+    fn consume_raw(&mut self, data: &[i16]) {
+        self.consume_samples(data, |sample| {
+            T::cast(f64::from(sample) / f64::from(i16::MAX))
+        });
+    }
+
+    fn set_spectrum_sink(&mut self, sink: Option<SpectrumSink>) {
+        self.spectrum_sink = sink;
+    }
+
+    fn stats(&self, stats: &mut PipelineStats) {
+        stats.resampled_samples = self.consumed_samples;
+        stats.fft_frames = self.frames;
+        // Each frame is turned into a single row of chroma features.
+        stats.chroma_rows = self.frames;
+        stats.dropped_samples = if self.frames > 0 {
+            self.buffer_offset - self.frame_overlap
+        } else {
+            self.buffer_offset
+        };
+    }
+}
+
+fn make_hamming_window<T: Float>(size: usize, scale: f64) -> Box<[T]> {
     let mut window = Vec::with_capacity(size);
     for i in 0..size {
-        window.push(
+        window.push(T::cast(
             scale
                 * (0.54
                     - 0.46
                         * f64::cos(2.0 * std::f64::consts::PI * (i as f64) / (size as f64 - 1.0))),
-        );
+        ));
     }
     window.into_boxed_slice()
 }
@@ -104,7 +178,7 @@ fn make_hamming_window(size: usize, scale: f64) -> Box<[f64]> {
 #[cfg(test)]
 mod tests {
     use crate::fft::Fft;
-    use crate::stages::{AudioConsumer, FeatureVectorConsumer, Stage};
+    use crate::stages::{AudioConsumer, FeatureVectorConsumer, RawAudioConsumer, Stage};
 
     struct Collector {
         frames: Vec<Vec<f64>>,
@@ -122,6 +196,10 @@ mod tests {
         fn output(&self) -> &Self::Output {
             &self.frames
         }
+
+        fn clear_output(&mut self) {
+            self.frames.clear();
+        }
     }
 
     impl FeatureVectorConsumer for Collector {
@@ -144,8 +222,8 @@ mod tests {
         let freq = 7 * (sample_rate / 2) / (frame_size / 2);
 
         let mut input = vec![0.0; frame_size + (nframes - 1) * (frame_size - overlap)];
-        for i in 0..input.len() {
-            input[i] =
+        for (i, sample) in input.iter_mut().enumerate() {
+            *sample =
                 f64::sin(i as f64 * freq as f64 * 2.0 * std::f64::consts::PI / sample_rate as f64);
         }
 
@@ -244,4 +322,23 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn raw_samples() {
+        let input: Vec<i16> = (0..200).map(|i| (i * 331 % 2000 - 1000) as i16).collect();
+
+        let mut expected = Fft::new(32, 8, Collector::new());
+        expected.consume(
+            &input
+                .iter()
+                .map(|&sample| f64::from(sample) / f64::from(i16::MAX))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut fft = Fft::new(32, 8, Collector::new());
+        for chunk in input.chunks(50) {
+            fft.consume_raw(chunk);
+        }
+        assert_eq!(expected.output(), fft.output());
+    }
 }
@@ -0,0 +1,229 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::CODEC_TYPE_NULL;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+
+use crate::audio_processor::ResetError;
+use crate::fingerprint::Fingerprint;
+use crate::fingerprinter::{Configuration, Fingerprinter};
+
+/// Decodes an audio file with [symphonia](https://docs.rs/symphonia) and calculates its fingerprint.
+///
+/// Returns the fingerprint along with the duration of the whole audio track.
+/// Only the formats and codecs enabled in `symphonia` are supported, the default ones can be
+/// extended by enabling more `symphonia` features in the application.
+/// Packets which cannot be decoded are skipped.
+pub fn fingerprint_file(
+    path: impl AsRef<Path>,
+    config: &Configuration,
+) -> Result<(Fingerprint, Duration), FileError> {
+    let path = path.as_ref();
+    let src = File::open(path).map_err(FileError::Io)?;
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &Default::default(),
+        &Default::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(FileError::NoAudioTrack)?;
+    let track_id = track.id;
+    let total_frames = track.codec_params.n_frames;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or(FileError::UnknownFormat)?;
+    let channels = track
+        .codec_params
+        .channels
+        .ok_or(FileError::UnknownFormat)?
+        .count();
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+
+    let mut printer = Fingerprinter::new(config);
+    printer.start(sample_rate, channels as u32)?;
+
+    let mut sample_buf: Option<SampleBuffer<i16>> = None;
+    let mut decoded_frames = 0u64;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let audio_buf = match decoder.decode(&packet) {
+            Ok(audio_buf) => audio_buf,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if audio_buf.spec().channels.count() != channels {
+            return Err(FileError::UnknownFormat);
+        }
+
+        // Packets can differ in size, so the buffer has to fit the largest one.
+        let required = audio_buf.capacity() * channels;
+        if sample_buf
+            .as_ref()
+            .map_or(true, |buf| buf.capacity() < required)
+        {
+            sample_buf = Some(SampleBuffer::new(
+                audio_buf.capacity() as u64,
+                *audio_buf.spec(),
+            ));
+        }
+        let buf = sample_buf.as_mut().expect("buffer is allocated above");
+        decoded_frames += audio_buf.frames() as u64;
+        buf.copy_interleaved_ref(audio_buf);
+        // The fingerprinter has been started, so it cannot fail.
+        let _ = printer.consume(buf.samples());
+
+        // The rest of the audio won't change the fingerprint, so it's only decoded
+        // when it's needed to get the duration.
+        if printer.is_complete() && total_frames.is_some() {
+            break;
+        }
+    }
+
+    let frames = match total_frames {
+        Some(frames) if printer.is_complete() => frames,
+        _ => decoded_frames,
+    };
+    let duration = Duration::from_secs_f64(frames as f64 / f64::from(sample_rate));
+    Ok((printer.finish_into(), duration))
+}
+
+/// Error returned when an audio file cannot be fingerprinted.
+#[derive(Debug)]
+pub enum FileError {
+    /// The file cannot be opened.
+    Io(std::io::Error),
+    /// The file cannot be demuxed or decoded.
+    Decode(SymphoniaError),
+    /// The file contains no audio tracks.
+    NoAudioTrack,
+    /// The sample rate or channels of the audio track are unknown or change within the track.
+    UnknownFormat,
+    /// The fingerprinter cannot process the audio track.
+    Reset(ResetError),
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::Io(e) => write!(f, "Cannot open the file: {e}"),
+            FileError::Decode(e) => write!(f, "Cannot decode the file: {e}"),
+            FileError::NoAudioTrack => write!(f, "The file contains no audio tracks"),
+            FileError::UnknownFormat => write!(f, "Unknown sample rate or channels"),
+            FileError::Reset(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(e) => Some(e),
+            FileError::Decode(e) => Some(e),
+            FileError::Reset(e) => Some(e),
+            FileError::NoAudioTrack | FileError::UnknownFormat => None,
+        }
+    }
+}
+
+impl From<SymphoniaError> for FileError {
+    fn from(e: SymphoniaError) -> Self {
+        FileError::Decode(e)
+    }
+}
+
+impl From<ResetError> for FileError {
+    fn from(e: ResetError) -> Self {
+        FileError::Reset(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::file::{fingerprint_file, FileError};
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+    use crate::io::{read_pcm_file, PcmFormat};
+
+    fn write_wav(path: &std::path::Path, samples: &[i16], sample_rate: u32, channels: u16) {
+        let data_len = (samples.len() * 2) as u32;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * u32::from(channels) * 2).to_le_bytes());
+        wav.extend_from_slice(&(channels * 2).to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn wav() {
+        let data = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
+        let path = std::env::temp_dir().join(format!("chromaprint-{}.wav", std::process::id()));
+        write_wav(&path, &data, 44100, 2);
+
+        for config in [
+            Configuration::preset_test2(),
+            Configuration::preset_test2().with_max_duration(1.0),
+        ] {
+            let mut printer = Fingerprinter::new(&config);
+            printer.start(44100, 2).unwrap();
+            printer.consume(&data).unwrap();
+            printer.finish();
+
+            let (fingerprint, duration) = fingerprint_file(&path, &config).unwrap();
+            assert_eq!(fingerprint.items(), printer.fingerprint());
+            assert_eq!(fingerprint.algorithm(), 1);
+            assert_eq!(
+                duration,
+                Duration::from_secs_f64(data.len() as f64 / 2.0 / 44100.0)
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            fingerprint_file(&path, &Configuration::default()),
+            Err(FileError::Io(_))
+        ));
+    }
+}
@@ -1,4 +1,6 @@
+/// Filter comparing areas of a rectangle of the chroma image, used by a [Classifier](crate::Classifier).
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Filter {
     kind: FilterKind,
     y: usize,
@@ -6,7 +8,15 @@ pub struct Filter {
     width: usize,
 }
 
+/// Function calculating a response of a custom filter.
+///
+/// It takes the image, the first row (`x`) and column (`y`) of the rectangle, and its width
+/// (in rows) and height (in columns). Use [Image::area] to sum parts of the rectangle.
+pub type FilterFn = fn(image: &dyn Image, x: usize, y: usize, w: usize, h: usize) -> f64;
+
+/// Shape of the areas compared by a [Filter].
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FilterKind {
     Filter0,
     Filter1,
@@ -14,10 +24,14 @@ pub enum FilterKind {
     Filter3,
     Filter4,
     Filter5,
+    /// Filter calculated by a user-defined function. It cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(FilterFn),
 }
 
 impl Filter {
-    pub(crate) const fn new(kind: FilterKind, y: usize, height: usize, width: usize) -> Self {
+    /// Creates a filter of the rectangle starting at column `y` of the given height and width.
+    pub const fn new(kind: FilterKind, y: usize, height: usize, width: usize) -> Self {
         Self {
             kind,
             y,
@@ -34,6 +48,7 @@ impl Filter {
             FilterKind::Filter3 => filter3,
             FilterKind::Filter4 => filter4,
             FilterKind::Filter5 => filter5,
+            FilterKind::Custom(filter) => return filter(image, x, self.y, self.width, self.height),
         };
         filter(image, x, self.y, self.width, self.height, subtract_log)
     }
@@ -41,6 +56,11 @@ impl Filter {
     pub(crate) fn width(&self) -> usize {
         self.width
     }
+
+    /// Number of rows of the image (chroma bands) needed to apply the filter.
+    pub(crate) fn height_needed(&self) -> usize {
+        self.y + self.height
+    }
 }
 
 fn subtract_log(a: f64, b: f64) -> f64 {
@@ -49,8 +69,10 @@ fn subtract_log(a: f64, b: f64) -> f64 {
     r
 }
 
+/// Image allowing to sum values over its rectangles.
 pub trait Image {
-    fn area(&self, x: usize, y: usize, w: usize, h: usize) -> f64;
+    /// Returns the sum of cells in rows `r1..r2` and columns `c1..c2`.
+    fn area(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64;
 }
 
 type Comparator = fn(f64, f64) -> f64;
@@ -154,6 +176,7 @@ mod tests {
     use crate::assert_eq_float;
     use crate::filter::{
         filter0, filter1, filter2, filter3, filter4, filter5, subtract_log, Filter, FilterKind,
+        Image,
     };
     use crate::rolling_image::RollingIntegralImage;
 
@@ -172,10 +195,10 @@ mod tests {
     #[test]
     fn test_filter_with_filter0() {
         let data = [0.0, 1.0, 2.0, 3.0];
-        let mut integral_image = RollingIntegralImage::from_data(2, &data);
+        let integral_image = RollingIntegralImage::from_data(2, &data);
         let flt1 = Filter::new(FilterKind::Filter0, 0, 1, 1);
-        assert_eq_float!(0.0, flt1.apply(&mut integral_image, 0));
-        assert_eq_float!(1.0986123, flt1.apply(&mut integral_image, 1));
+        assert_eq_float!(0.0, flt1.apply(&integral_image, 0));
+        assert_eq_float!(1.0986123, flt1.apply(&integral_image, 1));
     }
 
     #[test]
@@ -261,6 +284,25 @@ mod tests {
         assert_eq_float!(-15.0, res); // 3+4+5 - (1+2+3) - (6+7+8)
     }
 
+    #[test]
+    fn test_custom_filter() {
+        fn upper_half(image: &dyn Image, x: usize, y: usize, w: usize, h: usize) -> f64 {
+            subtract_log(
+                image.area(x, y + h / 2, x + w, y + h),
+                image.area(x, y, x + w, y + h / 2),
+            )
+        }
+
+        let data = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let integral_image = RollingIntegralImage::from_data(3, &data);
+        let custom = Filter::new(FilterKind::Custom(upper_half), 0, 2, 2);
+        let reference = Filter::new(FilterKind::Filter1, 0, 2, 2);
+        assert_eq_float!(
+            reference.apply(&integral_image, 1),
+            custom.apply(&integral_image, 1)
+        );
+    }
+
     fn subtract(a: f64, b: f64) -> f64 {
         a - b
     }
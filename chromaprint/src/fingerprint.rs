@@ -0,0 +1,310 @@
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+
+use crate::compression::{compress, decompress, DecompressionError};
+use crate::fingerprinter::Configuration;
+
+/// A fingerprint together with the ID of the algorithm used to calculate it.
+///
+/// It's displayed in the compressed format encoded with URL-safe base64, the same as printed
+/// by `fpcalc`. The alternate form (`{:#}`) gives the raw format with comma-separated items.
+/// Both formats can be parsed back with [FromStr]. The raw format doesn't contain the ID
+/// of the algorithm, so the one of the [default](Configuration::default) configuration is assumed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fingerprint {
+    algorithm: u8,
+    items: Vec<u32>,
+}
+
+impl Fingerprint {
+    /// Creates a new fingerprint from items calculated with the algorithm of a given ID.
+    pub fn new(algorithm: u8, items: Vec<u32>) -> Self {
+        Self { algorithm, items }
+    }
+
+    /// The ID of the algorithm used to calculate the fingerprint.
+    pub fn algorithm(&self) -> u8 {
+        self.algorithm
+    }
+
+    /// Items of the fingerprint.
+    pub fn items(&self) -> &[u32] {
+        &self.items
+    }
+
+    /// Consumes the fingerprint, returning its items.
+    pub fn into_items(self) -> Vec<u32> {
+        self.items
+    }
+
+    /// Creates a new fingerprint from items stored as signed integers, e.g. by `pg_acoustid`.
+    ///
+    /// Items are reinterpreted bit by bit, so it reverses [Fingerprint::to_signed_vec].
+    pub fn from_signed(algorithm: u8, items: &[i32]) -> Self {
+        Self::new(algorithm, items.iter().map(|&item| item as u32).collect())
+    }
+
+    /// Returns the items reinterpreted as signed integers, as stored by `pg_acoustid`.
+    pub fn to_signed_vec(&self) -> Vec<i32> {
+        self.items.iter().map(|&item| item as i32).collect()
+    }
+
+    /// Formats the fingerprint in the raw format with items as signed integers.
+    ///
+    /// It can be parsed back with [FromStr], the same as the unsigned raw format.
+    pub fn to_signed_string(&self) -> String {
+        self.to_signed_vec()
+            .iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Serializes the fingerprint into a portable binary format of raw items.
+    ///
+    /// The data starts with a header of the algorithm ID (1 byte) and the number of items
+    /// (4 bytes), followed by the items. All numbers are little-endian regardless of the platform.
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RAW_HEADER_LEN + 4 * self.items.len());
+        data.push(self.algorithm);
+        data.extend_from_slice(&(self.items.len() as u32).to_le_bytes());
+        for item in &self.items {
+            data.extend_from_slice(&item.to_le_bytes());
+        }
+        data
+    }
+
+    /// Deserializes a fingerprint from the format produced by [Fingerprint::to_le_bytes].
+    pub fn from_le_bytes(data: &[u8]) -> Result<Self, RawBytesError> {
+        if data.len() < RAW_HEADER_LEN {
+            return Err(RawBytesError::MissingHeader);
+        }
+        let (header, items) = data.split_at(RAW_HEADER_LEN);
+        let len = u32::from_le_bytes(header[1..].try_into().unwrap()) as usize;
+        // The expected length may not fit in `usize` on 32-bit platforms.
+        let expected = len
+            .checked_mul(4)
+            .and_then(|items_len| items_len.checked_add(RAW_HEADER_LEN));
+        if expected != Some(data.len()) {
+            return Err(RawBytesError::LengthMismatch {
+                expected: expected.unwrap_or(usize::MAX),
+                actual: data.len(),
+            });
+        }
+        let items = items
+            .chunks_exact(4)
+            .map(|item| u32::from_le_bytes(item.try_into().unwrap()))
+            .collect();
+        Ok(Self::new(header[0], items))
+    }
+
+    /// Compresses the fingerprint into the binary format used by chromaprint.
+    pub fn compress(&self) -> Vec<u8> {
+        compress(self.algorithm, &self.items)
+    }
+
+    /// Decompresses a fingerprint from the binary format used by chromaprint.
+    pub fn decompress(data: &[u8]) -> Result<Self, DecompressionError> {
+        let (algorithm, items) = decompress(data)?;
+        Ok(Self { algorithm, items })
+    }
+}
+
+impl AsRef<[u32]> for Fingerprint {
+    fn as_ref(&self) -> &[u32] {
+        &self.items
+    }
+}
+
+impl From<Fingerprint> for Vec<u32> {
+    fn from(value: Fingerprint) -> Self {
+        value.items
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            for (i, item) in self.items.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{item}")?;
+            }
+            Ok(())
+        } else {
+            f.write_str(&BASE64_URL_SAFE_NO_PAD.encode(self.compress()))
+        }
+    }
+}
+
+/// Size of the header of [Fingerprint::to_le_bytes]: the algorithm ID and the number of items.
+const RAW_HEADER_LEN: usize = 5;
+
+/// Error returned when a fingerprint cannot be read by [Fingerprint::from_le_bytes].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawBytesError {
+    /// The data is too short to contain a header.
+    MissingHeader,
+    /// The size of the data doesn't match the number of items in the header.
+    LengthMismatch {
+        /// Size (in bytes) implied by the header, or `usize::MAX` if it doesn't fit in `usize`.
+        expected: usize,
+        /// Actual size of the data (in bytes).
+        actual: usize,
+    },
+}
+
+impl Display for RawBytesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawBytesError::MissingHeader => write!(f, "Raw fingerprint has no header"),
+            RawBytesError::LengthMismatch { expected, actual } => write!(
+                f,
+                "Raw fingerprint should have {expected} bytes, but it has {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RawBytesError {}
+
+/// Error returned when a fingerprint cannot be parsed from a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseFingerprintError {
+    /// An item of the raw format is not a valid 32-bit integer.
+    InvalidItem(ParseIntError),
+    /// The compressed format is not valid base64.
+    InvalidBase64,
+    /// The compressed fingerprint cannot be decompressed.
+    Decompression(DecompressionError),
+}
+
+impl Display for ParseFingerprintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseFingerprintError::InvalidItem(e) => write!(f, "Invalid fingerprint item: {e}"),
+            ParseFingerprintError::InvalidBase64 => write!(f, "Invalid base64 encoding"),
+            ParseFingerprintError::Decompression(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ParseFingerprintError {}
+
+impl From<DecompressionError> for ParseFingerprintError {
+    fn from(e: DecompressionError) -> Self {
+        ParseFingerprintError::Decompression(e)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = ParseFingerprintError;
+
+    /// Parses a fingerprint in the raw or the compressed format.
+    ///
+    /// Strings consisting only of digits, minus signs and commas are parsed as the raw format.
+    /// Negative items (as stored by `pg_acoustid`) are reinterpreted as unsigned.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let is_raw = s
+            .bytes()
+            .all(|c| c.is_ascii_digit() || c == b',' || c == b'-' || c == b' ');
+        if !is_raw {
+            let data = BASE64_URL_SAFE_NO_PAD
+                .decode(s)
+                .map_err(|_| ParseFingerprintError::InvalidBase64)?;
+            return Ok(Self::decompress(&data)?);
+        }
+
+        let items = if s.is_empty() {
+            Vec::new()
+        } else {
+            s.split(',')
+                .map(|item| {
+                    let item = item.trim();
+                    if item.starts_with('-') {
+                        item.parse::<i32>().map(|item| item as u32)
+                    } else {
+                        item.parse::<u32>()
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(ParseFingerprintError::InvalidItem)?
+        };
+        Ok(Self::new(Configuration::default().id(), items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::{Fingerprint, ParseFingerprintError, RawBytesError};
+
+    #[test]
+    fn raw_format() {
+        let fp = Fingerprint::new(1, vec![1, 2, 3_000_000_000]);
+        assert_eq!("1,2,3000000000", format!("{fp:#}"));
+        assert_eq!(Ok(fp.clone()), "1,2,3000000000".parse());
+        assert_eq!(Ok(fp), "1, 2, -1294967296".parse());
+        assert!(matches!(
+            "1,,2".parse::<Fingerprint>(),
+            Err(ParseFingerprintError::InvalidItem(_))
+        ));
+    }
+
+    #[test]
+    fn signed_format() {
+        let fp = Fingerprint::new(1, vec![1, u32::MAX, 3_000_000_000]);
+        assert_eq!(vec![1, -1, -1294967296], fp.to_signed_vec());
+        assert_eq!("1,-1,-1294967296", fp.to_signed_string());
+        assert_eq!(fp, Fingerprint::from_signed(1, &fp.to_signed_vec()));
+        assert_eq!(Ok(fp.clone()), fp.to_signed_string().parse());
+        assert_eq!("", Fingerprint::new(1, Vec::new()).to_signed_string());
+    }
+
+    #[test]
+    fn binary_format() {
+        let fp = Fingerprint::new(2, vec![1, 0xDEADBEEF]);
+        let data = fp.to_le_bytes();
+        assert_eq!(
+            vec![2, 2, 0, 0, 0, 1, 0, 0, 0, 0xEF, 0xBE, 0xAD, 0xDE],
+            data
+        );
+        assert_eq!(Ok(fp), Fingerprint::from_le_bytes(&data));
+        assert_eq!(
+            Err(RawBytesError::MissingHeader),
+            Fingerprint::from_le_bytes(&data[..4])
+        );
+        assert_eq!(
+            Err(RawBytesError::LengthMismatch {
+                expected: 13,
+                actual: 12
+            }),
+            Fingerprint::from_le_bytes(&data[..12])
+        );
+    }
+
+    #[test]
+    fn compressed_format() {
+        let fp = Fingerprint::new(2, vec![0x0FCAF446, 0xE3519E89, 0xD3494DD6, 0]);
+        let encoded = fp.to_string();
+        assert_eq!(Ok(fp), encoded.parse());
+        assert_eq!(
+            Err(ParseFingerprintError::InvalidBase64),
+            "AQ!".parse::<Fingerprint>()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let fp = Fingerprint::new(1, vec![1, 2, 3]);
+        let json = serde_json::to_string(&fp).unwrap();
+        assert_eq!(r#"{"algorithm":1,"items":[1,2,3]}"#, json);
+        assert_eq!(fp, serde_json::from_str(&json).unwrap());
+    }
+}
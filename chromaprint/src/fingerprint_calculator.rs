@@ -1,11 +1,13 @@
 use crate::classifier::Classifier;
+use crate::float::Float;
 use crate::rolling_image::RollingIntegralImage;
 use crate::stages::{FeatureVectorConsumer, Stage};
 
-pub(crate) struct FingerprintCalculator {
+pub struct FingerprintCalculator {
     classifiers: Vec<Classifier>,
     max_filter_width: usize,
     image: RollingIntegralImage,
+    row: Vec<f64>,
     fingerprint: Vec<u32>,
 }
 
@@ -23,6 +25,7 @@ impl FingerprintCalculator {
             max_filter_width: max_width,
             classifiers,
             image: RollingIntegralImage::new(255),
+            row: Vec::new(),
             fingerprint: vec![],
         }
     }
@@ -42,11 +45,22 @@ impl Stage for FingerprintCalculator {
     fn output(&self) -> &Self::Output {
         self.fingerprint.as_slice()
     }
+
+    fn clear_output(&mut self) {
+        self.fingerprint.clear();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.fingerprint.reserve(additional);
+    }
 }
 
-impl FeatureVectorConsumer for FingerprintCalculator {
-    fn consume(&mut self, features: &[f64]) {
-        self.image.add_row(features);
+/// The features are accumulated in the integral image in double precision regardless of `T`.
+impl<T: Float> FeatureVectorConsumer<T> for FingerprintCalculator {
+    fn consume(&mut self, features: &[T]) {
+        self.row.clear();
+        self.row.extend(features.iter().map(|&x| x.into_f64()));
+        self.image.add_row(&self.row);
         if self.image.rows() >= self.max_filter_width {
             self.fingerprint
                 .push(self.calculate_subfingerprint(self.image.rows() - self.max_filter_width));
@@ -1,13 +1,29 @@
 use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
+use crate::compression::{decompress, DecompressionError};
+use crate::distance::{bit_errors, hamming};
 use crate::fingerprinter::Configuration;
 use crate::gaussian::gaussian_filter;
 use crate::gradient::gradient;
 
 #[derive(Debug)]
 pub enum MatchError {
-    FingerprintTooLong { index: u8 },
+    FingerprintTooLong {
+        index: u8,
+    },
+    InvalidCompressedFingerprint {
+        index: u8,
+        error: DecompressionError,
+    },
+    AlgorithmMismatch {
+        algorithm1: u8,
+        algorithm2: u8,
+    },
+    UnknownAlgorithm {
+        algorithm: u8,
+    },
 }
 
 impl Display for MatchError {
@@ -16,6 +32,21 @@ impl Display for MatchError {
             MatchError::FingerprintTooLong { index } => {
                 write!(f, "Fingerprint #{index} is too long")
             }
+            MatchError::InvalidCompressedFingerprint { index, error } => {
+                write!(f, "Fingerprint #{index} cannot be decompressed: {error}")
+            }
+            MatchError::AlgorithmMismatch {
+                algorithm1,
+                algorithm2,
+            } => {
+                write!(
+                    f,
+                    "Fingerprints were calculated with different algorithms ({algorithm1} and {algorithm2})"
+                )
+            }
+            MatchError::UnknownAlgorithm { algorithm } => {
+                write!(f, "Unknown algorithm {algorithm}")
+            }
         }
     }
 }
@@ -26,88 +57,612 @@ const ALIGN_BITS: u32 = 12;
 const HASH_SHIFT: u32 = 32 - ALIGN_BITS;
 const HASH_MASK: u32 = ((1 << ALIGN_BITS) - 1) << HASH_SHIFT;
 const OFFSET_MASK: u32 = (1 << (32 - ALIGN_BITS - 1)) - 1;
-const SOURCE_MASK: u32 = 1 << (32 - ALIGN_BITS - 1);
 
-fn align_strip(x: u32) -> u32 {
+/// Number of different hashes returned by [align_strip].
+pub(crate) const HASH_COUNT: u32 = 1 << ALIGN_BITS;
+
+pub(crate) fn align_strip(x: u32) -> u32 {
     x >> (32 - ALIGN_BITS)
 }
 
+/// Maximum length of a fingerprint that can be aligned at once.
+const MAX_WINDOW_LEN: usize = OFFSET_MASK as usize - 2;
+
+/// Minimum number of items for which alignment and scoring are split between threads.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LEN: usize = 1 << 14;
+
 /// Returns similar segments of two audio streams using their fingerprints.
+///
+/// Besides the segments, the returned [MatchResult] contains aggregated data about the match
+/// like coverage of both fingerprints and the offset of the best alignment.
+///
+/// Fingerprints of any length are supported. The ones that are too long to be aligned at once
+/// are split into overlapping windows that are matched pairwise. With the `rayon` feature
+/// enabled, long fingerprints are aligned and scored in parallel, giving the same results.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(fp1_len = fp1.len(), fp2_len = fp2.len()))
+)]
 pub fn match_fingerprints(
     fp1: &[u32],
     fp2: &[u32],
-    _config: &Configuration,
-) -> Result<Vec<Segment>, MatchError> {
-    if fp1.len() + 1 >= OFFSET_MASK as usize {
-        return Err(MatchError::FingerprintTooLong { index: 0 });
+    config: &Configuration,
+) -> Result<MatchResult, MatchError> {
+    match_fingerprints_with(fp1, fp2, config, &mut MatcherScratch::new())
+}
+
+/// Same as [match_fingerprints], but reuses the memory of `scratch` for intermediate data.
+///
+/// It avoids allocating the buffers on every call when many fingerprints are matched one by one.
+pub fn match_fingerprints_with(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    scratch: &mut MatcherScratch,
+) -> Result<MatchResult, MatchError> {
+    match_windowed(fp1, fp2, config, MAX_WINDOW_LEN, scratch)
+}
+
+/// Buffers for intermediate data of matching, reusable across calls of [match_fingerprints_with].
+///
+/// The buffers grow to fit the longest matched fingerprints and keep their capacity.
+#[derive(Debug, Default)]
+pub struct MatcherScratch {
+    table1: AlignmentTable,
+    table2: AlignmentTable,
+    histogram: HashMap<usize, u32>,
+    bit_counts: Vec<f64>,
+    orig_bit_counts: Vec<f64>,
+    smoothed_bit_counts: Vec<f64>,
+    gradient: Vec<f64>,
+    gradient_peaks: Vec<usize>,
+}
+
+impl MatcherScratch {
+    /// Creates empty buffers, allocated on the first use.
+    pub fn new() -> Self {
+        Self::default()
     }
+}
 
-    if fp2.len() + 1 >= OFFSET_MASK as usize {
-        return Err(MatchError::FingerprintTooLong { index: 1 });
+/// Returns similar segments of two audio streams using their compressed fingerprints.
+///
+/// Both fingerprints must be calculated with the same algorithm. The [Configuration] is
+/// chosen by the algorithm ID stored in the fingerprints.
+pub fn match_compressed(fp1: &[u8], fp2: &[u8]) -> Result<MatchResult, MatchError> {
+    let (algorithm1, fp1) = decompress(fp1)
+        .map_err(|error| MatchError::InvalidCompressedFingerprint { index: 0, error })?;
+    let (algorithm2, fp2) = decompress(fp2)
+        .map_err(|error| MatchError::InvalidCompressedFingerprint { index: 1, error })?;
+    if algorithm1 != algorithm2 {
+        return Err(MatchError::AlgorithmMismatch {
+            algorithm1,
+            algorithm2,
+        });
     }
+    let config = Configuration::preset(algorithm1).ok_or(MatchError::UnknownAlgorithm {
+        algorithm: algorithm1,
+    })?;
+    match_fingerprints(&fp1, &fp2, &config)
+}
 
-    let mut offsets = Vec::with_capacity(fp1.len() + fp2.len());
-    for (i, &segment) in fp1.iter().enumerate() {
-        offsets.push((align_strip(segment) << HASH_SHIFT) | (i as u32));
+/// Finds the most likely offset between two fingerprints of the same content.
+///
+/// It's much cheaper than [match_fingerprints], because it only aligns both fingerprints
+/// without looking for the matching segments. Returns `None` if no alignment was found.
+pub fn find_best_offset(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+) -> Option<OffsetEstimate> {
+    let (strength, offset) = if fp1.len() <= MAX_WINDOW_LEN && fp2.len() <= MAX_WINDOW_LEN {
+        let table1 = AlignmentTable::new(fp1).ok()?;
+        let table2 = AlignmentTable::with_index(fp2, 1).ok()?;
+        let (count, offset) = histogram_peaks(&table1.histogram(&table2, fp2.len()))
+            .into_iter()
+            .max()?;
+        (count, offset as isize - fp2.len() as isize)
+    } else {
+        let result = match_fingerprints(fp1, fp2, config).ok()?;
+        (result.peak_strength, result.best_offset?)
+    };
+    Some(OffsetEstimate { offset, strength })
+}
+
+/// Relative offset of two fingerprints found by [find_best_offset].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OffsetEstimate {
+    /// Offset in items. See [MatchResult::best_offset] for details.
+    pub offset: isize,
+
+    /// Number of matching hashes supporting this offset.
+    pub strength: u32,
+}
+
+impl OffsetEstimate {
+    /// Offset in seconds.
+    pub fn seconds(&self, config: &Configuration) -> f32 {
+        config.item_duration_in_seconds() * self.offset as f32
     }
+}
 
-    for (i, &segment) in fp2.iter().enumerate() {
-        offsets.push((align_strip(segment) << HASH_SHIFT) | (i as u32) | SOURCE_MASK);
+/// Matches one fingerprint against many others.
+///
+/// It gives the same results as calling [match_fingerprints] for each of the pairs,
+/// but hashes of `fp` are prepared only once and shared by all comparisons.
+/// With the `rayon` feature enabled, the comparisons are run in parallel.
+/// Results are returned in the same order as `others`.
+pub fn match_many(
+    fp: &[u32],
+    others: &[&[u32]],
+    config: &Configuration,
+) -> Result<Vec<MatchResult>, MatchError> {
+    if fp.len() > MAX_WINDOW_LEN {
+        return map_others(others, |scratch, other| {
+            match_fingerprints_with(fp, other, config, scratch)
+        });
     }
-    offsets.sort_unstable();
 
-    let mut histogram = vec![0u32; fp1.len() + fp2.len()];
-    for (offset_idx, item1) in offsets.iter().enumerate() {
-        let hash1 = item1 & HASH_MASK;
-        let offset1 = item1 & OFFSET_MASK;
-        let source1 = item1 & SOURCE_MASK;
-        if source1 != 0 {
-            // if we got hash from fp2, it means there is no hash from fp1,
-            // because if there was, it would be first
-            continue;
+    let table = AlignmentTable::new(fp)?;
+    map_others(others, |scratch, other| {
+        if other.len() > MAX_WINDOW_LEN {
+            match_fingerprints_with(fp, other, config, scratch)
+        } else {
+            match_with_table(&table, fp, other, config, scratch)
         }
+    })
+}
+
+#[cfg(feature = "rayon")]
+fn map_others<F>(others: &[&[u32]], f: F) -> Result<Vec<MatchResult>, MatchError>
+where
+    F: Fn(&mut MatcherScratch, &[u32]) -> Result<MatchResult, MatchError> + Send + Sync,
+{
+    use rayon::prelude::*;
+    others
+        .par_iter()
+        .map_init(MatcherScratch::new, |scratch, other| f(scratch, other))
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_others<F>(others: &[&[u32]], f: F) -> Result<Vec<MatchResult>, MatchError>
+where
+    F: Fn(&mut MatcherScratch, &[u32]) -> Result<MatchResult, MatchError>,
+{
+    let mut scratch = MatcherScratch::new();
+    others.iter().map(|other| f(&mut scratch, other)).collect()
+}
+
+/// Matches a fingerprint against transposed variants of another one.
+///
+/// Each of `transposed` should be calculated from the same audio using a configuration
+/// with a different [chroma rotation](Configuration::with_chroma_rotation), e.g. all
+/// 12 rotations in order. It allows to match pitch-shifted copies of a recording.
+/// Returns the variant giving the highest [similarity](MatchResult::similarity)
+/// or `None` if there are no variants.
+pub fn match_transposed(
+    fp1: &[u32],
+    transposed: &[&[u32]],
+    config: &Configuration,
+) -> Result<Option<TransposedMatch>, MatchError> {
+    let results = match_many(fp1, transposed, config)?;
+    let best = results
+        .into_iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.similarity().total_cmp(&b.similarity()));
+    Ok(best.map(|(rotation, result)| TransposedMatch { rotation, result }))
+}
+
+/// The best matching transposed variant found by [match_transposed].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransposedMatch {
+    /// Index of the best matching variant.
+    ///
+    /// If all 12 rotations were passed in order, it's the number of semitones
+    /// by which the second recording is pitched up relative to the first one.
+    pub rotation: usize,
+
+    /// Result of matching the best variant.
+    pub result: MatchResult,
+}
+
+/// Difference between consecutive stretch factors tried by [match_stretched].
+const STRETCH_STEP: f64 = 0.005;
+
+/// Matches two fingerprints allowing the second one to be slightly faster or slower.
+///
+/// The second fingerprint is resampled with stretch factors from `1.0 - max_stretch` up to
+/// `1.0 + max_stretch` (e.g. `0.05` for ±5%) and the one giving the highest
+/// [similarity](MatchResult::similarity) is returned.
+pub fn match_stretched(
+    fp1: &[u32],
+    fp2: &[u32],
+    max_stretch: f64,
+    config: &Configuration,
+) -> Result<StretchedMatch, MatchError> {
+    let steps = (max_stretch.abs() / STRETCH_STEP).floor() as usize;
+    let factors = std::iter::once(1.0)
+        .chain((1..=steps).flat_map(|i| {
+            let delta = i as f64 * STRETCH_STEP;
+            [1.0 + delta, 1.0 - delta]
+        }))
+        .filter(|&factor| factor > 0.0)
+        .collect::<Vec<_>>();
 
-        for item2 in offsets.iter().skip(offset_idx + 1) {
-            let hash2 = item2 & HASH_MASK;
-            if hash1 != hash2 {
-                break;
+    let stretched = factors
+        .iter()
+        .map(|&factor| stretch(fp2, factor))
+        .collect::<Vec<_>>();
+    let stretched = stretched.iter().map(|fp| fp.as_slice()).collect::<Vec<_>>();
+
+    // Factors closer to 1.0 are tried first, so they win in case of a tie.
+    let mut best: Option<StretchedMatch> = None;
+    for (factor, result) in std::iter::zip(factors, match_many(fp1, &stretched, config)?) {
+        if best
+            .as_ref()
+            .map_or(true, |best| result.similarity() > best.result.similarity())
+        {
+            best = Some(StretchedMatch {
+                stretch: factor,
+                result,
+            });
+        }
+    }
+    Ok(best.expect("at least one stretch factor is always tried"))
+}
+
+/// Resamples the fingerprint to be `factor` times longer.
+fn stretch(fp: &[u32], factor: f64) -> Vec<u32> {
+    let len = (fp.len() as f64 * factor).round() as usize;
+    (0..len)
+        .map(|i| fp[((i as f64 / factor) as usize).min(fp.len() - 1)])
+        .collect()
+}
+
+/// The best match of a stretched fingerprint found by [match_stretched].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StretchedMatch {
+    /// Factor by which the second fingerprint was stretched.
+    ///
+    /// Value above 1.0 means that the second recording is faster than the first one.
+    pub stretch: f64,
+
+    /// Result of matching the first fingerprint with the stretched second one.
+    ///
+    /// Offsets in the second fingerprint refer to the stretched fingerprint.
+    /// Use [StretchedMatch::original_offset2] to map them back.
+    pub result: MatchResult,
+}
+
+impl StretchedMatch {
+    /// Maps an offset in the stretched fingerprint into the original second fingerprint.
+    pub fn original_offset2(&self, offset: usize) -> usize {
+        (offset as f64 / self.stretch).round() as usize
+    }
+}
+
+fn match_windowed(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    window_len: usize,
+    scratch: &mut MatcherScratch,
+) -> Result<MatchResult, MatchError> {
+    if fp1.len() <= window_len && fp2.len() <= window_len {
+        return match_window(fp1, fp2, config, scratch);
+    }
+
+    // Windows overlap by half of their length, so any segment shorter than that
+    // is entirely contained in at least one pair of windows.
+    let step = window_len / 2;
+    let mut result = MatchResult::new(fp1.len(), fp2.len());
+    for start1 in window_starts(fp1.len(), window_len, step) {
+        let window1 = &fp1[start1..fp1.len().min(start1 + window_len)];
+        for start2 in window_starts(fp2.len(), window_len, step) {
+            let window2 = &fp2[start2..fp2.len().min(start2 + window_len)];
+            let partial = match_window(window1, window2, config, scratch)?;
+            if partial.peak_strength > result.peak_strength {
+                result.peak_strength = partial.peak_strength;
+                result.best_offset = partial
+                    .best_offset
+                    .map(|offset| offset + start1 as isize - start2 as isize);
+            }
+            result
+                .segments
+                .extend(partial.segments.into_iter().map(|s| Segment {
+                    offset1: s.offset1 + start1,
+                    offset2: s.offset2 + start2,
+                    ..s
+                }));
+        }
+    }
+    result.segments = merge_overlapping(result.segments, fp1, fp2);
+    Ok(result)
+}
+
+/// Returns start indices of windows covering a fingerprint of the given length.
+fn window_starts(len: usize, window_len: usize, step: usize) -> impl Iterator<Item = usize> {
+    let count = if len <= window_len {
+        1
+    } else {
+        (len - window_len + step - 1) / step + 1
+    };
+    (0..count).map(move |i| i * step)
+}
+
+/// Merges segments found in overlapping windows that lie on the same alignment.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(segments = segments.len())))]
+fn merge_overlapping(mut segments: Vec<Segment>, fp1: &[u32], fp2: &[u32]) -> Vec<Segment> {
+    segments.sort_unstable_by_key(|s| (s.offset1 as isize - s.offset2 as isize, s.offset1));
+
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if let Some(last) = merged.last_mut() {
+            let same_alignment = last.offset1 as isize - last.offset2 as isize
+                == segment.offset1 as isize - segment.offset2 as isize;
+            let last_end = last.offset1 + last.items_count;
+            if same_alignment && segment.offset1 < last_end {
+                let end = last_end.max(segment.offset1 + segment.items_count);
+                last.items_count = end - last.offset1;
+                last.score = average_bit_errors(
+                    &fp1[last.offset1..end],
+                    &fp2[last.offset2..][..last.items_count],
+                );
+                continue;
             }
+        }
+        merged.push(segment);
+    }
+
+    merged.sort_unstable_by_key(|s| (s.offset1, s.offset2));
+    merged
+}
+
+/// Calculates an average number of different bits between corresponding items.
+fn average_bit_errors(fp1: &[u32], fp2: &[u32]) -> f64 {
+    f64::from(hamming(fp1, fp2)) / fp1.len().min(fp2.len()) as f64
+}
+
+fn match_window(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    scratch: &mut MatcherScratch,
+) -> Result<MatchResult, MatchError> {
+    // The table is taken out of the scratch for the time of matching, which needs the rest of it.
+    let mut table = std::mem::take(&mut scratch.table1);
+    let result = table
+        .rebuild(fp1, 0)
+        .and_then(|()| match_with_table(&table, fp1, fp2, config, scratch));
+    scratch.table1 = table;
+    result
+}
+
+/// Hashes of a fingerprint sorted for alignment with other fingerprints.
+///
+/// Each entry contains the hash in its highest bits and the index of the item in the rest.
+#[derive(Debug, Default)]
+struct AlignmentTable {
+    entries: Vec<u32>,
+}
+
+impl AlignmentTable {
+    fn new(fp: &[u32]) -> Result<Self, MatchError> {
+        Self::with_index(fp, 0)
+    }
 
-            let offset2 = item2 & OFFSET_MASK;
-            let source2 = item2 & SOURCE_MASK;
-            if source2 != 0 {
-                let offset_diff = offset1 as usize + fp2.len() - offset2 as usize;
-                histogram[offset_diff] += 1;
+    fn with_index(fp: &[u32], index: u8) -> Result<Self, MatchError> {
+        let mut table = Self::default();
+        table.rebuild(fp, index)?;
+        Ok(table)
+    }
+
+    /// Replaces the entries with the ones of another fingerprint, keeping the allocated memory.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self, fp), fields(len = fp.len())))]
+    fn rebuild(&mut self, fp: &[u32], index: u8) -> Result<(), MatchError> {
+        if fp.len() + 1 >= OFFSET_MASK as usize {
+            return Err(MatchError::FingerprintTooLong { index });
+        }
+
+        self.entries.clear();
+        fill_entries(fp, &mut self.entries);
+        Ok(())
+    }
+
+    /// Counts matching hashes for each possible offset between both fingerprints.
+    ///
+    /// Offsets are shifted by the length of the second fingerprint to keep them non-negative.
+    fn histogram(&self, other: &AlignmentTable, other_len: usize) -> HashMap<usize, u32> {
+        let mut histogram = HashMap::new();
+        self.histogram_into(other, other_len, &mut histogram);
+        histogram
+    }
+
+    /// Same as [AlignmentTable::histogram], but fills an existing histogram.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    fn histogram_into(
+        &self,
+        other: &AlignmentTable,
+        other_len: usize,
+        histogram: &mut HashMap<usize, u32>,
+    ) {
+        histogram.clear();
+        join_hashes(&self.entries, &other.entries, other_len, histogram);
+    }
+}
+
+/// Appends entries of all items of the fingerprint and sorts them.
+#[cfg(feature = "rayon")]
+fn fill_entries(fp: &[u32], entries: &mut Vec<u32>) {
+    use rayon::prelude::*;
+    if fp.len() < PARALLEL_MIN_LEN {
+        entries.extend(
+            fp.iter()
+                .enumerate()
+                .map(|(i, &item)| alignment_entry(i, item)),
+        );
+        entries.sort_unstable();
+        return;
+    }
+    entries.par_extend(
+        fp.par_iter()
+            .enumerate()
+            .map(|(i, &item)| alignment_entry(i, item)),
+    );
+    // All entries are different thanks to the indices, so the order doesn't depend on the sort.
+    entries.par_sort_unstable();
+}
+
+/// Appends entries of all items of the fingerprint and sorts them.
+#[cfg(not(feature = "rayon"))]
+fn fill_entries(fp: &[u32], entries: &mut Vec<u32>) {
+    entries.extend(
+        fp.iter()
+            .enumerate()
+            .map(|(i, &item)| alignment_entry(i, item)),
+    );
+    entries.sort_unstable();
+}
+
+fn alignment_entry(index: usize, item: u32) -> u32 {
+    (align_strip(item) << HASH_SHIFT) | (index as u32)
+}
+
+/// Adds hits of all pairs of entries with equal hashes to the histogram.
+///
+/// Large tables are split into chunks of whole hashes joined in parallel. The counts are summed
+/// up afterwards, so the histogram is the same regardless of the number of threads.
+#[cfg(feature = "rayon")]
+fn join_hashes(
+    entries1: &[u32],
+    entries2: &[u32],
+    other_len: usize,
+    histogram: &mut HashMap<usize, u32>,
+) {
+    use rayon::prelude::*;
+    if entries1.len() < PARALLEL_MIN_LEN {
+        join_hashes_sequential(entries1, entries2, other_len, histogram);
+        return;
+    }
+
+    let chunk_len = entries1.len() / rayon::current_num_threads().max(1) + 1;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < entries1.len() {
+        let mut end = usize::min(start + chunk_len, entries1.len());
+        // Entries with the same hash are never split between chunks.
+        let last = end - 1;
+        end = last + count_hash(&entries1[last..], entries1[last] & HASH_MASK);
+        chunks.push(&entries1[start..end]);
+        start = end;
+    }
+
+    let partial = chunks
+        .into_par_iter()
+        .map(|chunk| {
+            let first_hash = chunk[0] & HASH_MASK;
+            let last_hash = chunk[chunk.len() - 1] & HASH_MASK;
+            let begin = entries2.partition_point(|&entry| entry & HASH_MASK < first_hash);
+            let end = entries2.partition_point(|&entry| entry & HASH_MASK <= last_hash);
+            let mut histogram = HashMap::new();
+            join_hashes_sequential(chunk, &entries2[begin..end], other_len, &mut histogram);
+            histogram
+        })
+        .collect::<Vec<_>>();
+    for part in partial {
+        for (offset, count) in part {
+            *histogram.entry(offset).or_default() += count;
+        }
+    }
+}
+
+/// Adds hits of all pairs of entries with equal hashes to the histogram.
+#[cfg(not(feature = "rayon"))]
+fn join_hashes(
+    entries1: &[u32],
+    entries2: &[u32],
+    other_len: usize,
+    histogram: &mut HashMap<usize, u32>,
+) {
+    join_hashes_sequential(entries1, entries2, other_len, histogram);
+}
+
+fn join_hashes_sequential(
+    entries1: &[u32],
+    entries2: &[u32],
+    other_len: usize,
+    histogram: &mut HashMap<usize, u32>,
+) {
+    // Only a small fraction of all possible offsets gets any hits, so the counts are kept
+    // in a sparse histogram instead of allocating a bucket for each of them.
+    let (mut i, mut j) = (0, 0);
+    while i < entries1.len() && j < entries2.len() {
+        let hash1 = entries1[i] & HASH_MASK;
+        let hash2 = entries2[j] & HASH_MASK;
+        if hash1 < hash2 {
+            i += 1;
+        } else if hash1 > hash2 {
+            j += 1;
+        } else {
+            let end1 = i + count_hash(&entries1[i..], hash1);
+            let end2 = j + count_hash(&entries2[j..], hash2);
+            for item1 in &entries1[i..end1] {
+                let offset1 = (item1 & OFFSET_MASK) as usize;
+                for item2 in &entries2[j..end2] {
+                    let offset2 = (item2 & OFFSET_MASK) as usize;
+                    *histogram.entry(offset1 + other_len - offset2).or_default() += 1;
+                }
             }
+            i = end1;
+            j = end2;
         }
     }
+}
 
-    let mut best_alignments = Vec::new();
-    let histogram_size = histogram.len();
-    for i in 0..histogram_size {
-        let count = histogram[i];
-        if histogram[i] > 1 {
-            let is_peak_left = if i > 0 {
-                histogram[i - 1] <= count
-            } else {
-                true
-            };
-            let is_peak_right = if i < histogram_size - 1 {
-                histogram[i + 1] <= count
-            } else {
-                true
-            };
+/// Returns local maxima of the histogram supported by more than one hash as `(count, offset)`.
+fn histogram_peaks(histogram: &HashMap<usize, u32>) -> Vec<(u32, usize)> {
+    let mut peaks = Vec::new();
+    for (&i, &count) in histogram {
+        if count > 1 {
+            let is_peak_left = i == 0 || histogram.get(&(i - 1)).map_or(true, |&c| c <= count);
+            let is_peak_right = histogram.get(&(i + 1)).map_or(true, |&c| c <= count);
             if is_peak_left && is_peak_right {
-                best_alignments.push((count, i));
+                peaks.push((count, i));
             }
         }
     }
+    peaks
+}
+
+fn count_hash(entries: &[u32], hash: u32) -> usize {
+    entries
+        .iter()
+        .take_while(|&&entry| entry & HASH_MASK == hash)
+        .count()
+}
 
-    best_alignments.sort_unstable_by_key(|it| Reverse(*it));
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(fp1_len = fp1.len(), fp2_len = fp2.len())))]
+fn match_with_table(
+    table: &AlignmentTable,
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    scratch: &mut MatcherScratch,
+) -> Result<MatchResult, MatchError> {
+    scratch.table2.rebuild(fp2, 1)?;
+    table.histogram_into(&scratch.table2, fp2.len(), &mut scratch.histogram);
 
-    let mut segments: Vec<Segment> = Vec::new();
-    if let Some((_count, offset)) = best_alignments.into_iter().next() {
+    let mut result = MatchResult::new(fp1.len(), fp2.len());
+    if let Some((count, offset)) = histogram_peaks(&scratch.histogram).into_iter().max() {
         let offset_diff = offset as isize - fp2.len() as isize;
+        result.best_offset = Some(offset_diff);
+        result.peak_strength = count;
+
         let offset1 = if offset_diff > 0 {
             offset_diff as usize
         } else {
@@ -119,24 +674,37 @@ pub fn match_fingerprints(
             0
         };
 
+        let MatcherScratch {
+            bit_counts,
+            orig_bit_counts,
+            smoothed_bit_counts,
+            gradient: grad,
+            gradient_peaks,
+            ..
+        } = scratch;
+
         let size = usize::min(fp1.len() - offset1, fp2.len() - offset2);
-        let mut bit_counts = Vec::new();
-        for i in 0..size {
-            bit_counts.push((fp1[offset1 + i] ^ fp2[offset2 + i]).count_ones() as f64);
-        }
+        bit_counts.clear();
+        fill_bit_counts(
+            &fp1[offset1..offset1 + size],
+            &fp2[offset2..offset2 + size],
+            bit_counts,
+        );
 
-        let orig_bit_counts = bit_counts.clone();
-        let mut smoothed_bit_counts = vec![0.0; size];
-        gaussian_filter(&mut bit_counts, &mut smoothed_bit_counts, 8.0, 3);
+        orig_bit_counts.clear();
+        orig_bit_counts.extend_from_slice(bit_counts);
+        smoothed_bit_counts.clear();
+        smoothed_bit_counts.resize(size, 0.0);
+        gaussian_filter(bit_counts, smoothed_bit_counts, 8.0, 3);
 
-        let mut grad = Vec::with_capacity(size);
-        gradient(smoothed_bit_counts.iter().copied(), &mut grad);
+        grad.clear();
+        gradient(smoothed_bit_counts.iter().copied(), grad);
 
         for item in grad.iter_mut().take(size) {
             *item = item.abs();
         }
 
-        let mut gradient_peaks = Vec::new();
+        gradient_peaks.clear();
         for i in 0..size {
             let gi = grad[i];
             if i > 0
@@ -150,15 +718,20 @@ pub fn match_fingerprints(
             }
         }
         gradient_peaks.push(size);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            offset = offset_diff,
+            peaks = gradient_peaks.len(),
+            "aligned"
+        );
 
-        let match_threshold = 10.0;
         let max_score_difference = 0.7;
 
         let mut begin = 0;
-        for end in gradient_peaks {
+        for &end in gradient_peaks.iter() {
             let duration = end - begin;
             let score: f64 = orig_bit_counts[begin..end].iter().sum::<f64>() / (duration as f64);
-            if score < match_threshold {
+            if score < config.match_threshold() {
                 let new_segment = Segment {
                     offset1: offset1 + begin,
                     offset2: offset2 + begin,
@@ -167,28 +740,136 @@ pub fn match_fingerprints(
                 };
 
                 let mut added = false;
-                if let Some(s1) = segments.last_mut() {
-                    if (s1.score - score).abs() < max_score_difference {
+                if let Some(s1) = result.segments.last_mut() {
+                    let score_difference = (s1.score - score).abs();
+                    if score_difference < max_score_difference {
                         if let Some(merged) = s1.try_merge(&new_segment) {
                             *s1 = merged;
                             added = true;
                         }
                     }
+
+                    if !added && score_difference < config.max_gap_score_difference() {
+                        // Merge across the gap and include the unmatched items in the score.
+                        let s1_begin = s1.offset1 - offset1;
+                        let gap = begin - (s1_begin + s1.items_count);
+                        if gap <= config.max_gap() {
+                            let items_count = end - s1_begin;
+                            let score = orig_bit_counts[s1_begin..end].iter().sum::<f64>()
+                                / items_count as f64;
+                            s1.items_count = items_count;
+                            s1.score = score;
+                            added = true;
+                        }
+                    }
                 }
 
                 if !added {
-                    segments.push(new_segment);
+                    result.segments.push(new_segment);
                 }
             }
             begin = end;
         }
     }
 
-    Ok(segments)
+    #[cfg(feature = "tracing")]
+    tracing::debug!(segments = result.segments.len(), "matched");
+    Ok(result)
 }
 
-/// Segment of an audio that is similar between two fingerprints.
+/// Appends the number of different bits of each pair of aligned items.
+#[cfg(feature = "rayon")]
+fn fill_bit_counts(fp1: &[u32], fp2: &[u32], bit_counts: &mut Vec<f64>) {
+    use crate::distance::item_distance;
+    use rayon::prelude::*;
+    if fp1.len() < PARALLEL_MIN_LEN {
+        bit_counts.extend(bit_errors(fp1, fp2).map(f64::from));
+        return;
+    }
+    bit_counts.par_extend(
+        fp1.par_iter()
+            .zip(fp2)
+            .map(|(&a, &b)| f64::from(item_distance(a, b))),
+    );
+}
+
+/// Appends the number of different bits of each pair of aligned items.
+#[cfg(not(feature = "rayon"))]
+fn fill_bit_counts(fp1: &[u32], fp2: &[u32], bit_counts: &mut Vec<f64>) {
+    bit_counts.extend(bit_errors(fp1, fp2).map(f64::from));
+}
+
+/// Result of matching two fingerprints.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchResult {
+    /// Similar segments of both fingerprints.
+    pub segments: Vec<Segment>,
+
+    /// Offset of the best alignment of both fingerprints (in items).
+    ///
+    /// Positive value means that the audio in the first fingerprint starts later than in the
+    /// second one, i.e. `fp1[i + offset]` corresponds to `fp2[i]`.
+    /// It's `None` if no alignment was found.
+    pub best_offset: Option<isize>,
+
+    /// Number of matching hashes supporting the best alignment.
+    ///
+    /// The higher this value is, the more reliable is the alignment.
+    pub peak_strength: u32,
+
+    fp1_len: usize,
+    fp2_len: usize,
+}
+
+impl MatchResult {
+    fn new(fp1_len: usize, fp2_len: usize) -> Self {
+        Self {
+            segments: Vec::new(),
+            best_offset: None,
+            peak_strength: 0,
+            fp1_len,
+            fp2_len,
+        }
+    }
+
+    /// Total number of items in all the matched segments.
+    pub fn matched_items(&self) -> usize {
+        self.segments.iter().map(|s| s.items_count).sum()
+    }
+
+    /// Total duration of all the matched segments (in seconds).
+    pub fn matched_duration(&self, config: &Configuration) -> f32 {
+        config.item_duration_in_seconds() * self.matched_items() as f32
+    }
+
+    /// Fraction of the first fingerprint covered by the matched segments.
+    pub fn coverage1(&self) -> f64 {
+        coverage(self.matched_items(), self.fp1_len)
+    }
+
+    /// Fraction of the second fingerprint covered by the matched segments.
+    pub fn coverage2(&self) -> f64 {
+        coverage(self.matched_items(), self.fp2_len)
+    }
+
+    /// Overall similarity of both fingerprints. See [similarity] for details.
+    pub fn similarity(&self) -> f64 {
+        similarity(&self.segments, self.fp1_len, self.fp2_len)
+    }
+}
+
+fn coverage(items: usize, len: usize) -> f64 {
+    if len == 0 {
+        0.0
+    } else {
+        items as f64 / len as f64
+    }
+}
+
+/// Segment of an audio that is similar between two fingerprints.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Segment {
     /// Index of the item in the first fingerprint.
     pub offset1: usize,
@@ -202,11 +883,22 @@ pub struct Segment {
     /// Score that corresponds to similarity of this segment.
     /// The smaller this value is, the stronger similarity.
     ///
-    /// This value can be be 0 up to 32.
+    /// This value can be be 0 up to 32. Use [score_to_similarity] or [Verdict::from_score]
+    /// to interpret it.
     pub score: f64,
 }
 
 impl Segment {
+    /// Creates a new segment.
+    pub fn new(offset1: usize, offset2: usize, items_count: usize, score: f64) -> Self {
+        Self {
+            offset1,
+            offset2,
+            items_count,
+            score,
+        }
+    }
+
     /// A timestamp representing the start of the segment in the first fingerprint.
     pub fn start1(&self, config: &Configuration) -> f32 {
         config.item_duration_in_seconds() * self.offset1 as f32
@@ -234,6 +926,21 @@ impl Segment {
 }
 
 impl Segment {
+    /// Number of different bits for each pair of items in this segment.
+    ///
+    /// The fingerprints must be the same ones that were used to find this segment.
+    /// The [score](Segment::score) is an average of these values.
+    pub fn bit_errors(&self, fp1: &[u32], fp2: &[u32]) -> Vec<u32> {
+        let items1 = &fp1[self.offset1..][..self.items_count];
+        let items2 = &fp2[self.offset2..][..self.items_count];
+        bit_errors(items1, items2).collect()
+    }
+
+    /// Similarity of this segment in range from 0.0 (unrelated audio) up to 1.0 (identical audio).
+    pub fn similarity(&self) -> f64 {
+        score_to_similarity(self.score)
+    }
+
     /// Try to merge two consecutive segments into one.
     fn try_merge(&self, other: &Self) -> Option<Self> {
         // Check if segments are consecutive
@@ -258,11 +965,282 @@ impl Segment {
     }
 }
 
+/// Finds all occurrences of a short query (e.g. a few seconds long clip) in a long recording.
+///
+/// Unlike [match_fingerprints], only the query is indexed, which keeps the memory usage
+/// proportional to its length and allows to find multiple occurrences of the same clip.
+/// The returned occurrences are sorted by their position in the haystack.
+pub fn find_in(query: &[u32], haystack: &[u32], config: &Configuration) -> Vec<Occurrence> {
+    if query.is_empty() || haystack.is_empty() {
+        return Vec::new();
+    }
+
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, &item) in query.iter().enumerate() {
+        index.entry(align_strip(item)).or_default().push(i);
+    }
+
+    // Offsets are shifted by the length of the query to keep them non-negative.
+    let mut histogram: HashMap<usize, u32> = HashMap::new();
+    for (j, &item) in haystack.iter().enumerate() {
+        if let Some(positions) = index.get(&align_strip(item)) {
+            for &i in positions {
+                *histogram.entry(j + query.len() - i).or_default() += 1;
+            }
+        }
+    }
+
+    occurrences_from_histogram(query, haystack, &histogram, config)
+}
+
+/// Picks occurrences of the query from a histogram of its offsets in the haystack,
+/// shifted by the length of the query to keep them non-negative.
+pub(crate) fn occurrences_from_histogram(
+    query: &[u32],
+    haystack: &[u32],
+    histogram: &HashMap<usize, u32>,
+    config: &Configuration,
+) -> Vec<Occurrence> {
+    // Skip alignments where the query would start before the beginning of the haystack.
+    let mut candidates = histogram_peaks(histogram)
+        .into_iter()
+        .filter(|&(_count, i)| i >= query.len())
+        .map(|(count, i)| (count, i - query.len()))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by_key(|it| Reverse(*it));
+
+    let mut occurrences: Vec<Occurrence> = Vec::new();
+    for (_count, offset) in candidates {
+        let items_count = query.len().min(haystack.len() - offset);
+        if items_count < (query.len() + 1) / 2 {
+            continue;
+        }
+
+        // The strongest alignment wins over the ones overlapping with it.
+        let overlaps = occurrences
+            .iter()
+            .any(|o| o.offset.abs_diff(offset) < (query.len() + 1) / 2);
+        if overlaps {
+            continue;
+        }
+
+        let score = average_bit_errors(query, &haystack[offset..][..items_count]);
+        if score < config.match_threshold() {
+            occurrences.push(Occurrence {
+                offset,
+                items_count,
+                score,
+            });
+        }
+    }
+
+    occurrences.sort_unstable_by_key(|o| o.offset);
+    occurrences
+}
+
+/// Occurrence of a query in a longer fingerprint found by [find_in].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Occurrence {
+    /// Index of the item in the haystack where the query starts.
+    pub offset: usize,
+
+    /// Number of items of the query matched against the haystack.
+    ///
+    /// It's shorter than the query only if the occurrence is at the very end of the haystack.
+    pub items_count: usize,
+
+    /// Score that corresponds to similarity of the occurrence. See [Segment::score].
+    pub score: f64,
+}
+
+impl Occurrence {
+    /// A timestamp representing the start of the occurrence in the haystack.
+    pub fn start(&self, config: &Configuration) -> f32 {
+        config.item_duration_in_seconds() * self.offset as f32
+    }
+
+    /// A timestamp representing the end of the occurrence in the haystack.
+    pub fn end(&self, config: &Configuration) -> f32 {
+        config.item_duration_in_seconds() * (self.offset + self.items_count) as f32
+    }
+
+    /// Similarity of the occurrence in range from 0.0 up to 1.0.
+    pub fn similarity(&self) -> f64 {
+        score_to_similarity(self.score)
+    }
+}
+
+/// Average score of two unrelated items (half of their bits differ).
+const UNRELATED_SCORE: f64 = 16.0;
+
+/// Converts a [score](Segment::score) to a similarity in range from 0.0 up to 1.0.
+///
+/// Identical items (score 0) are fully similar, while scores of unrelated audio (16, i.e.
+/// half of the bits differ) and worse have no similarity. Values in between are linear.
+/// This is the conversion used by [Segment::similarity] and [similarity].
+pub fn score_to_similarity(score: f64) -> f64 {
+    (1.0 - score / UNRELATED_SCORE).clamp(0.0, 1.0)
+}
+
+/// Converts a [score](Segment::score) to a similarity percentage, see [score_to_similarity].
+pub fn score_to_percentage(score: f64) -> f64 {
+    100.0 * score_to_similarity(score)
+}
+
+/// Suggested interpretation of a [score](Segment::score).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Verdict {
+    /// The compared items come from the same audio.
+    Match,
+    /// The compared items are unrelated.
+    NoMatch,
+}
+
+impl Verdict {
+    /// Judges a score by the [match threshold](Configuration::match_threshold) of the configuration,
+    /// the same way [match_fingerprints] decides which segments to keep.
+    pub fn from_score(score: f64, config: &Configuration) -> Self {
+        if score < config.match_threshold() {
+            Verdict::Match
+        } else {
+            Verdict::NoMatch
+        }
+    }
+
+    /// Checks if the verdict is a match.
+    pub fn is_match(self) -> bool {
+        self == Verdict::Match
+    }
+}
+
+/// Thresholds used by [are_similar] to decide if two fingerprints come from the same audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimilarityOptions {
+    max_score: f64,
+    min_coverage: f64,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        Self {
+            max_score: 8.0,
+            min_coverage: 0.5,
+        }
+    }
+}
+
+impl SimilarityOptions {
+    /// Sets the maximum [score](Segment::score) of segments taken into account. Defaults to 8.
+    pub fn with_max_score(mut self, score: f64) -> Self {
+        self.max_score = score;
+        self
+    }
+
+    /// Maximum score of segments taken into account.
+    pub fn max_score(&self) -> f64 {
+        self.max_score
+    }
+
+    /// Sets the minimum fraction of the shorter fingerprint that has to be matched. Defaults to 0.5.
+    pub fn with_min_coverage(mut self, coverage: f64) -> Self {
+        self.min_coverage = coverage;
+        self
+    }
+
+    /// Minimum fraction of the shorter fingerprint that has to be matched.
+    pub fn min_coverage(&self) -> f64 {
+        self.min_coverage
+    }
+}
+
+/// Checks if two fingerprints come from the same audio.
+///
+/// Both fingerprints are aligned and matched with [match_fingerprints]. They are similar if
+/// the segments with a low enough score cover a large enough part of the shorter fingerprint.
+/// Use [are_similar_with_confidence] to get a measure of how certain the verdict is.
+pub fn are_similar(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    options: &SimilarityOptions,
+) -> bool {
+    are_similar_with_confidence(fp1, fp2, config, options).0
+}
+
+/// Same as [are_similar], but also returns the confidence in range from 0.0 up to 1.0.
+///
+/// The confidence is the fraction of the shorter fingerprint covered by the accepted segments,
+/// each weighted by its [similarity](Segment::similarity).
+pub fn are_similar_with_confidence(
+    fp1: &[u32],
+    fp2: &[u32],
+    config: &Configuration,
+    options: &SimilarityOptions,
+) -> (bool, f64) {
+    // Raw fingerprints of any length are split into windows, so matching them cannot fail.
+    match match_fingerprints(fp1, fp2, config) {
+        Ok(result) => judge_similarity(&result, options),
+        Err(_) => (false, 0.0),
+    }
+}
+
+/// Decides if the fingerprints of a [MatchResult] are similar, see [are_similar_with_confidence].
+pub(crate) fn judge_similarity(result: &MatchResult, options: &SimilarityOptions) -> (bool, f64) {
+    let shorter = result.fp1_len.min(result.fp2_len);
+    if shorter == 0 || result.best_offset.is_none() {
+        return (false, 0.0);
+    }
+
+    let accepted = || {
+        result
+            .segments
+            .iter()
+            .filter(|s| s.score <= options.max_score)
+    };
+    let covered = accepted().map(|s| s.items_count).sum::<usize>() as f64 / shorter as f64;
+    let confidence = accepted()
+        .map(|s| s.items_count as f64 * s.similarity())
+        .sum::<f64>()
+        / shorter as f64;
+    (covered >= options.min_coverage, confidence.clamp(0.0, 1.0))
+}
+
+/// Calculates a similarity of two fingerprints from their matching segments.
+///
+/// The result is in range from 0.0 to 1.0. Each segment contributes its own similarity weighted
+/// by its length, which is then related to the lengths of both fingerprints. Because of that
+/// a short clip contained in a long recording scores lower than two recordings matching from
+/// the beginning to the end.
+pub fn similarity(segments: &[Segment], fp1_len: usize, fp2_len: usize) -> f64 {
+    if fp1_len == 0 || fp2_len == 0 {
+        return 0.0;
+    }
+
+    let weighted: f64 = segments
+        .iter()
+        .map(|s| s.items_count as f64 * s.similarity())
+        .sum();
+    let coverage = (fp1_len as f64 * fp2_len as f64).sqrt();
+    (weighted / coverage).clamp(0.0, 1.0)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::assert_eq_float;
-    use crate::fingerprint_matcher::match_fingerprints;
+    use crate::compression::compress;
+    use crate::fingerprint_matcher::{alignment_entry, join_hashes_sequential, AlignmentTable};
+    use crate::fingerprint_matcher::{
+        are_similar, are_similar_with_confidence, find_best_offset, find_in, match_compressed,
+        match_fingerprints, match_fingerprints_with, match_many, match_stretched, match_transposed,
+        match_windowed, score_to_percentage, score_to_similarity, similarity, stretch, MatchError,
+        MatcherScratch, Segment, SimilarityOptions, Verdict,
+    };
     use crate::fingerprinter::Configuration;
+    use crate::utils::random_fingerprint;
+    use std::collections::HashMap;
 
     #[test]
     fn simple() {
@@ -725,11 +1703,311 @@ mod tests {
             .collect::<Vec<_>>();
 
         let conf = Configuration::preset_test2();
-        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap();
+        let result = match_fingerprints(&fp1, &fp2, &conf).unwrap();
+        assert_eq!(result.best_offset, Some(5));
+
+        let estimate = find_best_offset(&fp1, &fp2, &conf).unwrap();
+        assert_eq!(estimate.offset, 5);
+        assert_eq!(estimate.strength, result.peak_strength);
+        assert_eq_float!(
+            estimate.seconds(&conf),
+            5.0 * conf.item_duration_in_seconds()
+        );
+        assert_eq!(find_best_offset(&fp2, &fp1, &conf).unwrap().offset, -5);
+        assert_eq_float!(result.coverage1(), 216.0 / 221.0);
+        assert_eq_float!(result.coverage2(), 216.0 / 221.0);
+        assert_eq_float!(result.similarity(), 0.78374, 0.001);
+
+        let segments = result.segments;
         assert_eq!(segments.len(), 1);
         assert_eq!(segments[0].offset1, 5);
         assert_eq!(segments[0].offset2, 0);
         assert_eq!(segments[0].items_count, 216);
         assert_eq_float!(segments[0].score, 3.17183, 0.001);
+        assert_eq!(
+            segments[0],
+            Segment::new(
+                5,
+                0,
+                216,
+                segments[0].bit_errors(&fp1, &fp2).iter().sum::<u32>() as f64 / 216.0
+            )
+        );
+        assert_eq_float!(similarity(&segments, fp1.len(), fp2.len()), 0.78374, 0.001);
+
+        let bit_errors = segments[0].bit_errors(&fp1, &fp2);
+        assert_eq!(bit_errors.len(), segments[0].items_count);
+        assert_eq!(bit_errors[0], (fp1[5] ^ fp2[0]).count_ones());
+        let average = bit_errors.iter().sum::<u32>() as f64 / bit_errors.len() as f64;
+        assert_eq_float!(average, segments[0].score);
+    }
+
+    #[test]
+    fn gap_merging() {
+        let fp1 = random_fingerprint(300, 1);
+        let mut fp2 = fp1.clone();
+        for item in &mut fp2[150..156] {
+            *item = !*item;
+        }
+
+        let conf = Configuration::preset_test2();
+        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap().segments;
+        assert_eq!(segments.len(), 2);
+
+        let conf = Configuration::preset_test2().with_gap_merging(20, 2.0);
+        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap().segments;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].offset1, 0);
+        assert_eq!(segments[0].items_count, 300);
+        assert_eq_float!(segments[0].score, 6.0 * 32.0 / 300.0);
+
+        let conf = Configuration::preset_test2().with_gap_merging(10, 2.0);
+        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap().segments;
+        assert_eq!(segments.len(), 2);
+    }
+
+    #[test]
+    fn match_threshold() {
+        let fp1 = random_fingerprint(300, 1);
+        // Flipping the lowest bits keeps the alignment hashes intact.
+        let fp2: Vec<u32> = fp1.iter().map(|item| item ^ 0b11).collect();
+
+        let conf = Configuration::preset_test2();
+        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap().segments;
+        assert_eq!(segments.len(), 1);
+        assert_eq_float!(segments[0].score, 2.0);
+
+        let conf = Configuration::preset_test2().with_match_threshold(2.0);
+        let segments = match_fingerprints(&fp1, &fp2, &conf).unwrap().segments;
+        assert!(segments.is_empty());
+    }
+
+    #[test]
+    fn long_fingerprints_in_windows() {
+        let fp1 = random_fingerprint(1000, 1);
+        let mut fp2 = random_fingerprint(250, 2);
+        fp2.extend_from_slice(&fp1[300..700]);
+        fp2.extend(random_fingerprint(100, 3));
+
+        let conf = Configuration::preset_test2();
+        let mut scratch = MatcherScratch::new();
+        let whole = match_windowed(&fp1, &fp2, &conf, 2000, &mut scratch).unwrap();
+        let windowed = match_windowed(&fp1, &fp2, &conf, 120, &mut scratch).unwrap();
+        assert_eq!(whole.best_offset, Some(50));
+        assert_eq!(windowed.best_offset, Some(50));
+        assert_eq!(whole.segments.len(), 1);
+        assert_eq!(windowed.segments.len(), 1);
+        assert_eq!(windowed.segments[0].offset1, whole.segments[0].offset1);
+        assert_eq!(windowed.segments[0].offset2, whole.segments[0].offset2);
+        assert_eq!(
+            windowed.segments[0].items_count,
+            whole.segments[0].items_count
+        );
+        assert_eq_float!(windowed.segments[0].score, whole.segments[0].score);
+        assert!(windowed.segments[0].items_count >= 395);
+    }
+
+    #[test]
+    fn query_in_haystack() {
+        let query = random_fingerprint(60, 1);
+        let mut haystack = random_fingerprint(2000, 2);
+        haystack[300..360].copy_from_slice(&query);
+        haystack[1500..1560].copy_from_slice(&query);
+        haystack[1970..].copy_from_slice(&query[..30]);
+        for item in haystack.iter_mut().step_by(7) {
+            *item ^= 0x0101_0101;
+        }
+
+        let conf = Configuration::preset_test2();
+        let occurrences = find_in(&query, &haystack, &conf);
+        assert_eq!(occurrences.len(), 3);
+        assert_eq!(occurrences[0].offset, 300);
+        assert_eq!(occurrences[0].items_count, 60);
+        assert!(occurrences[0].score < 1.0);
+        assert_eq!(occurrences[1].offset, 1500);
+        assert_eq!(occurrences[2].offset, 1970);
+        assert_eq!(occurrences[2].items_count, 30);
+
+        assert!(find_in(&random_fingerprint(60, 3), &haystack, &conf).is_empty());
+    }
+
+    #[test]
+    fn one_vs_many() {
+        let fp = random_fingerprint(500, 1);
+        let mut others = Vec::new();
+        for seed in 2..10 {
+            let mut other = random_fingerprint(200, seed);
+            let start = seed as usize * 30;
+            other[50..150].copy_from_slice(&fp[start..start + 100]);
+            others.push(other);
+        }
+        others.push(random_fingerprint(200, 10));
+
+        let conf = Configuration::preset_test2();
+        let others = others.iter().map(|o| o.as_slice()).collect::<Vec<_>>();
+        let results = match_many(&fp, &others, &conf).unwrap();
+        assert_eq!(results.len(), others.len());
+        for (result, other) in std::iter::zip(&results, &others) {
+            let expected = match_fingerprints(&fp, other, &conf).unwrap();
+            assert_eq!(result.best_offset, expected.best_offset);
+            assert_eq!(result.peak_strength, expected.peak_strength);
+            assert_eq!(result.segments.len(), expected.segments.len());
+        }
+        assert_eq!(results[0].best_offset, Some(10));
+        assert!(results.last().unwrap().segments.is_empty());
+    }
+
+    #[test]
+    fn reused_scratch() {
+        let fp1 = random_fingerprint(400, 1);
+        let mut fp2 = random_fingerprint(250, 2);
+        fp2[20..220].copy_from_slice(&fp1[100..300]);
+        let short = random_fingerprint(30, 3);
+
+        let conf = Configuration::preset_test2();
+        let mut scratch = MatcherScratch::new();
+        for (a, b) in [(&fp1, &fp2), (&short, &fp1), (&fp2, &fp1), (&fp1, &fp2)] {
+            let reused = match_fingerprints_with(a, b, &conf, &mut scratch).unwrap();
+            let fresh = match_fingerprints(a, b, &conf).unwrap();
+            assert_eq!(reused.best_offset, fresh.best_offset);
+            assert_eq!(reused.peak_strength, fresh.peak_strength);
+            assert_eq!(reused.segments, fresh.segments);
+        }
+    }
+
+    #[test]
+    fn long_alignment() {
+        let fp1 = random_fingerprint(60_000, 1);
+        let mut fp2 = random_fingerprint(40_000, 2);
+        fp2[5_000..35_000].copy_from_slice(&fp1[20_000..50_000]);
+
+        let table1 = AlignmentTable::new(&fp1).unwrap();
+        let table2 = AlignmentTable::with_index(&fp2, 1).unwrap();
+        let mut entries = fp2
+            .iter()
+            .enumerate()
+            .map(|(i, &item)| alignment_entry(i, item))
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
+        assert_eq!(table2.entries, entries);
+
+        let mut expected = HashMap::new();
+        join_hashes_sequential(&table1.entries, &table2.entries, fp2.len(), &mut expected);
+        assert_eq!(table1.histogram(&table2, fp2.len()), expected);
+
+        let result = match_fingerprints(&fp1, &fp2, &Configuration::preset_test2()).unwrap();
+        assert_eq!(result.best_offset, Some(15_000));
+        // Boundaries of segments are found on smoothed bit errors, so they can be off by a bit.
+        assert!((29_990..=30_010).contains(&result.matched_items()));
+    }
+
+    #[test]
+    fn transposed() {
+        let fp = random_fingerprint(300, 1);
+        let variants = (2..14)
+            .map(|seed| random_fingerprint(300, seed))
+            .chain(std::iter::once(fp.clone()))
+            .collect::<Vec<_>>();
+        let variants = variants.iter().map(|v| v.as_slice()).collect::<Vec<_>>();
+
+        let conf = Configuration::preset_test2();
+        let best = match_transposed(&fp, &variants, &conf).unwrap().unwrap();
+        assert_eq!(best.rotation, 12);
+        assert_eq_float!(best.result.similarity(), 1.0);
+        assert!(match_transposed(&fp, &[], &conf).unwrap().is_none());
+    }
+
+    #[test]
+    fn stretched() {
+        let fp1 = random_fingerprint(1000, 1);
+        let fp2 = stretch(&fp1[200..], 1.0 / 1.04);
+
+        let conf = Configuration::preset_test2();
+        let plain = match_fingerprints(&fp1, &fp2, &conf).unwrap();
+        let best = match_stretched(&fp1, &fp2, 0.05, &conf).unwrap();
+        assert_eq_float!(best.stretch, 1.04);
+        assert!(best.result.similarity() > 0.8);
+        assert!(best.result.similarity() > 2.0 * plain.similarity());
+        let segment = &best.result.segments[0];
+        assert!(segment.offset1.abs_diff(200) <= 1);
+        assert!(best.original_offset2(segment.offset2) <= 1);
+
+        let same = match_stretched(&fp1, &fp1, 0.05, &conf).unwrap();
+        assert_eq_float!(same.stretch, 1.0);
+    }
+
+    #[test]
+    fn similarity_of_identical() {
+        let fp = (0..100u32)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect::<Vec<_>>();
+        let conf = Configuration::preset_test2();
+        let segments = match_fingerprints(&fp, &fp, &conf).unwrap().segments;
+        assert_eq_float!(similarity(&segments, fp.len(), fp.len()), 1.0);
+        assert_eq_float!(similarity(&segments, fp.len(), 4 * fp.len()), 0.5);
+        assert_eq_float!(similarity(&[], fp.len(), fp.len()), 0.0);
+    }
+
+    #[test]
+    fn similar_fingerprints() {
+        let fp1 = random_fingerprint(300, 1);
+        let conf = Configuration::preset_test2();
+        let options = SimilarityOptions::default();
+
+        let (similar, confidence) = are_similar_with_confidence(&fp1, &fp1, &conf, &options);
+        assert!(similar);
+        assert_eq_float!(confidence, 1.0);
+
+        let clip = &fp1[100..200];
+        assert!(are_similar(&fp1, clip, &conf, &options));
+        assert!(!are_similar(
+            &fp1,
+            clip,
+            &conf,
+            &options.with_max_score(-1.0)
+        ));
+
+        let other = random_fingerprint(300, 2);
+        assert!(!are_similar(&fp1, &other, &conf, &options));
+        assert!(!are_similar(&fp1, &[], &conf, &options));
+    }
+
+    #[test]
+    fn score_conversion() {
+        assert_eq_float!(score_to_similarity(0.0), 1.0);
+        assert_eq_float!(score_to_similarity(4.0), 0.75);
+        assert_eq_float!(score_to_similarity(16.0), 0.0);
+        assert_eq_float!(score_to_similarity(32.0), 0.0);
+        assert_eq_float!(score_to_percentage(8.0), 50.0);
+
+        let conf = Configuration::preset_test2().with_match_threshold(5.0);
+        assert_eq!(Verdict::from_score(4.9, &conf), Verdict::Match);
+        assert_eq!(Verdict::from_score(5.0, &conf), Verdict::NoMatch);
+        assert!(!Verdict::from_score(16.0, &conf).is_match());
+    }
+
+    #[test]
+    fn compressed() {
+        let fp1 = random_fingerprint(300, 1);
+        let fp2 = fp1[100..].to_vec();
+
+        let conf = Configuration::preset_test3();
+        let expected = match_fingerprints(&fp1, &fp2, &conf).unwrap();
+        let result = match_compressed(&compress(conf.id(), &fp1), &compress(conf.id(), &fp2));
+        assert_eq!(expected.segments, result.unwrap().segments);
+
+        let result = match_compressed(&compress(1, &fp1), &compress(2, &fp2));
+        assert!(matches!(
+            result,
+            Err(MatchError::AlgorithmMismatch {
+                algorithm1: 1,
+                algorithm2: 2
+            })
+        ));
+        let result = match_compressed(&compress(1, &fp1), &[1, 0]);
+        assert!(matches!(
+            result,
+            Err(MatchError::InvalidCompressedFingerprint { index: 1, .. })
+        ));
     }
 }
@@ -0,0 +1,82 @@
+use std::ops::{AddAssign, DivAssign};
+
+use rustfft::num_complex::Complex;
+
+/// Precision of the floating point numbers used for processing the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// 64-bit floating point numbers.
+    #[default]
+    Double,
+    /// 32-bit floating point numbers.
+    ///
+    /// Faster and lighter on memory on targets without a native support for doubles
+    /// (e.g. embedded and WASM), at the cost of slightly different fingerprints.
+    Single,
+}
+
+/// Floating point type used by the stages between the audio processor and the fingerprint calculator.
+pub trait Float: rustfft::FftNum + rustfft::num_traits::Float + AddAssign + DivAssign {
+    fn cast(value: f64) -> Self;
+    fn into_f64(self) -> f64;
+
+    /// Multiplies the input by the window, storing the results as real parts of the output.
+    fn apply_window(input: &[Self], window: &[Self], output: &mut [Complex<Self>]) {
+        for ((output, &input), &window) in output.iter_mut().zip(input).zip(window) {
+            output.re = input * window;
+            output.im = Self::zero();
+        }
+    }
+
+    /// Calculates squared magnitudes of the complex numbers.
+    fn norm_sqr(input: &[Complex<Self>], output: &mut [Self]) {
+        for (output, input) in output.iter_mut().zip(input) {
+            *output = input.norm_sqr();
+        }
+    }
+}
+
+impl Float for f32 {
+    #[inline]
+    fn cast(value: f64) -> Self {
+        value as f32
+    }
+
+    #[inline]
+    fn into_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn apply_window(input: &[Self], window: &[Self], output: &mut [Complex<Self>]) {
+        crate::simd::apply_window_f32(input, window, output);
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn norm_sqr(input: &[Complex<Self>], output: &mut [Self]) {
+        crate::simd::norm_sqr_f32(input, output);
+    }
+}
+
+impl Float for f64 {
+    #[inline]
+    fn cast(value: f64) -> Self {
+        value
+    }
+
+    #[inline]
+    fn into_f64(self) -> f64 {
+        self
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn apply_window(input: &[Self], window: &[Self], output: &mut [Complex<Self>]) {
+        crate::simd::apply_window_f64(input, window, output);
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn norm_sqr(input: &[Complex<Self>], output: &mut [Self]) {
+        crate::simd::norm_sqr_f64(input, output);
+    }
+}
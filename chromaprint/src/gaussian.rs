@@ -149,7 +149,7 @@ impl ReflectIterator {
         if self.forward {
             return self.size - self.pos - 1;
         }
-        return 0;
+        0
     }
 }
 
@@ -183,7 +183,7 @@ mod tests {
     #[test]
     fn width1() {
         let input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         box_filter(&input, &mut output, 1);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(1.0, output[0]);
@@ -194,7 +194,7 @@ mod tests {
     #[test]
     fn width2() {
         let input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         box_filter(&input, &mut output, 2);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(1.0, output[0]);
@@ -205,7 +205,7 @@ mod tests {
     #[test]
     fn width3() {
         let input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         box_filter(&input, &mut output, 3);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(1.333333333, output[0]);
@@ -216,7 +216,7 @@ mod tests {
     #[test]
     fn width4() {
         let input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         box_filter(&input, &mut output, 4);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(1.5, output[0]);
@@ -227,7 +227,7 @@ mod tests {
     #[test]
     fn width5() {
         let input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         box_filter(&input, &mut output, 5);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(2.0, output[0]);
@@ -238,7 +238,7 @@ mod tests {
     #[test]
     fn gaussian1() {
         let mut input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         gaussian_filter(&mut input, &mut output, 1.6, 3);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(1.88888889, output[0]);
@@ -249,7 +249,7 @@ mod tests {
     #[test]
     fn gaussian2() {
         let mut input = [1.0, 2.0, 4.0];
-        let mut output = input.clone();
+        let mut output = input;
         gaussian_filter(&mut input, &mut output, 3.6, 4);
         assert_eq!(input.len(), output.len());
         assert_eq_float!(2.3322449, output[0]);
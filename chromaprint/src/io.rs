@@ -0,0 +1,191 @@
+//! Helpers for reading raw PCM audio.
+
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::fingerprint::Fingerprint;
+use crate::fingerprinter::{Configuration, Fingerprinter};
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Encoding of raw PCM samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PcmFormat {
+    /// Signed 16-bit little-endian integers.
+    #[default]
+    S16Le,
+    /// 32-bit little-endian floats in range from -1 to 1.
+    F32Le,
+}
+
+impl PcmFormat {
+    /// Size of a single sample (in bytes).
+    pub fn sample_size(&self) -> usize {
+        match self {
+            PcmFormat::S16Le => 2,
+            PcmFormat::F32Le => 4,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> i16 {
+        match self {
+            PcmFormat::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]),
+            PcmFormat::F32Le => {
+                f32_to_i16(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+        }
+    }
+}
+
+/// Reads raw PCM samples, converting them to 16-bit integers.
+pub struct PcmReader<R> {
+    reader: R,
+    format: PcmFormat,
+    buffer: Vec<u8>,
+    pending: usize,
+}
+
+impl<R: Read> PcmReader<R> {
+    /// Creates a reader of samples in the given format.
+    pub fn new(reader: R, format: PcmFormat) -> Self {
+        Self {
+            reader,
+            format,
+            buffer: Vec::new(),
+            pending: 0,
+        }
+    }
+
+    /// Reads up to `samples.len()` samples, returning the number of samples read.
+    ///
+    /// Waits only until at least one sample is available, so it works with live streams.
+    /// Returns 0 at the end of the stream. Bytes of an incomplete sample at the end are ignored.
+    pub fn read(&mut self, samples: &mut [i16]) -> std::io::Result<usize> {
+        let sample_size = self.format.sample_size();
+        self.buffer.resize(samples.len() * sample_size, 0);
+        while self.pending < sample_size.min(self.buffer.len()) {
+            match self.reader.read(&mut self.buffer[self.pending..]) {
+                Ok(0) => break,
+                Ok(n) => self.pending += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let count = self.pending / sample_size;
+        let decoded = self.buffer[..count * sample_size].chunks_exact(sample_size);
+        for (sample, bytes) in samples.iter_mut().zip(decoded) {
+            *sample = self.format.decode(bytes);
+        }
+        self.buffer
+            .copy_within(count * sample_size..self.pending, 0);
+        self.pending -= count * sample_size;
+        Ok(count)
+    }
+
+    /// Reads all the remaining samples.
+    pub fn read_to_end(mut self) -> std::io::Result<Vec<i16>> {
+        let mut samples = Vec::new();
+        let mut block = [0; BLOCK_SIZE];
+        loop {
+            match self.read(&mut block)? {
+                0 => return Ok(samples),
+                n => samples.extend_from_slice(&block[..n]),
+            }
+        }
+    }
+}
+
+/// Reads all samples from a raw PCM file.
+pub fn read_pcm_file(path: impl AsRef<Path>, format: PcmFormat) -> std::io::Result<Vec<i16>> {
+    let file = std::fs::File::open(path)?;
+    PcmReader::new(std::io::BufReader::new(file), format).read_to_end()
+}
+
+/// Reads raw PCM audio with interleaved channels and calculates its fingerprint.
+///
+/// Reading stops at the end of the stream or when the maximum duration of the
+/// [Configuration] is reached.
+pub fn fingerprint_pcm(
+    reader: impl Read,
+    format: PcmFormat,
+    sample_rate: u32,
+    channels: u32,
+    config: &Configuration,
+) -> Result<Fingerprint, Error> {
+    let mut printer = Fingerprinter::new(config);
+    printer.start(sample_rate, channels)?;
+
+    let mut reader = PcmReader::new(reader, format);
+    let mut block = [0; BLOCK_SIZE];
+    while !printer.is_complete() {
+        match reader.read(&mut block)? {
+            0 => break,
+            n => printer.consume(&block[..n])?,
+        }
+    }
+    Ok(printer.finish_into())
+}
+
+/// Converts a float sample in range from -1 to 1 into a 16-bit integer.
+pub(crate) fn f32_to_i16(sample: f32) -> i16 {
+    (sample * 32768.0).round().clamp(-32768.0, 32767.0) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+    use crate::io::{fingerprint_pcm, read_pcm_file, PcmFormat, PcmReader};
+
+    /// Returns the data in pieces of at most 3 bytes, so samples are split between reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let s16: Vec<u8> = [0i16, 1, -2, i16::MAX, i16::MIN]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let samples = PcmReader::new(Trickle(&s16), PcmFormat::S16Le)
+            .read_to_end()
+            .unwrap();
+        assert_eq!(samples, [0, 1, -2, i16::MAX, i16::MIN]);
+
+        let f32: Vec<u8> = [0.0f32, 0.5, -1.0, 2.0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .chain([0xFF])
+            .collect();
+        let samples = PcmReader::new(Trickle(&f32), PcmFormat::F32Le)
+            .read_to_end()
+            .unwrap();
+        assert_eq!(samples, [0, 16384, -32768, 32767]);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let data = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
+        let config = Configuration::preset_test2();
+        let mut printer = Fingerprinter::new(&config);
+        printer.start(44100, 2).unwrap();
+        printer.consume(&data).unwrap();
+        printer.finish();
+
+        let file = std::fs::File::open("data/test_stereo_44100.raw").unwrap();
+        let fingerprint = fingerprint_pcm(file, PcmFormat::S16Le, 44100, 2, &config).unwrap();
+        assert_eq!(fingerprint.items(), printer.fingerprint());
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use crate::fingerprint_matcher::{
+    align_strip, match_many, occurrences_from_histogram, MatchError, Occurrence,
+};
+use crate::Configuration;
+
+/// Index of single items of many fingerprints, used to find where a clip occurs in any of them.
+///
+/// Items are indexed by their highest bits, the same hashes that [match_fingerprints](crate::match_fingerprints)
+/// uses for alignment, so a search returns both the matching fingerprints and the positions
+/// of the clip in them.
+#[derive(Debug, Default)]
+pub struct ItemIndex {
+    fingerprints: Vec<Vec<u32>>,
+    postings: HashMap<u32, Vec<(u32, u32)>>,
+}
+
+/// Occurrence of a query in one of the fingerprints of an [ItemIndex].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexHit {
+    /// ID of the fingerprint returned by [ItemIndex::insert].
+    pub id: usize,
+
+    /// Position and score of the query in the fingerprint.
+    pub occurrence: Occurrence,
+}
+
+/// Stored fingerprint similar to a query, returned by [ItemIndex::knn].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Neighbor {
+    /// ID of the fingerprint returned by [ItemIndex::insert].
+    pub id: usize,
+
+    /// Similarity of the query and the fingerprint, see [MatchResult::similarity](crate::MatchResult::similarity).
+    pub similarity: f64,
+
+    /// Offset of the best alignment, with the same meaning as [MatchResult::best_offset](crate::MatchResult::best_offset)
+    /// for the query being the first fingerprint.
+    pub offset: isize,
+}
+
+impl ItemIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fingerprint to the index and returns its ID.
+    ///
+    /// IDs are assigned consecutively from 0.
+    pub fn insert(&mut self, fp: &[u32]) -> usize {
+        let id = self.fingerprints.len();
+        for (position, &item) in fp.iter().enumerate() {
+            self.postings
+                .entry(align_strip(item))
+                .or_default()
+                .push((id as u32, position as u32));
+        }
+        self.fingerprints.push(fp.to_vec());
+        id
+    }
+
+    /// Returns the fingerprint with the given ID.
+    pub fn get(&self, id: usize) -> Option<&[u32]> {
+        self.fingerprints.get(id).map(Vec::as_slice)
+    }
+
+    /// Number of indexed fingerprints.
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Checks if there are no indexed fingerprints.
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    /// Finds all occurrences of a short query in the indexed fingerprints.
+    ///
+    /// Occurrences are found and scored like with [find_in](crate::find_in), so a single
+    /// fingerprint can contain multiple ones. Hits are sorted from the best score.
+    pub fn search(&self, query: &[u32], config: &Configuration) -> Vec<IndexHit> {
+        let histograms = query_histograms(query, |hash| self.postings(hash).iter().copied());
+        hits_from_histograms(query, histograms, config, |id| &self.fingerprints[id])
+    }
+
+    /// Returns `(id, position)` of all indexed items with the given hash.
+    pub(crate) fn postings(&self, hash: u32) -> &[(u32, u32)] {
+        self.postings.get(&hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// Finds `k` indexed fingerprints most similar to the query, sorted from the most similar one.
+    ///
+    /// Unlike [ItemIndex::search], whole fingerprints are compared with [match_many](crate::match_many),
+    /// which ranks different versions of a recording instead of looking for exact copies of a clip.
+    /// Fingerprints without any segment matching the query are left out, so fewer than `k`
+    /// neighbors are returned if there are not enough of them.
+    pub fn knn(
+        &self,
+        query: &[u32],
+        k: usize,
+        config: &Configuration,
+    ) -> Result<Vec<Neighbor>, MatchError> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut shared_hashes: HashMap<usize, u32> = HashMap::new();
+        for &item in query {
+            for &(id, _) in self.postings(align_strip(item)) {
+                *shared_hashes.entry(id as usize).or_default() += 1;
+            }
+        }
+        // Alignment needs at least two matching hashes at the same offset.
+        let mut candidates = shared_hashes
+            .into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        let fingerprints = candidates
+            .iter()
+            .map(|&id| self.fingerprints[id].as_slice())
+            .collect::<Vec<_>>();
+        let results = match_many(query, &fingerprints, config)?;
+        let mut neighbors = std::iter::zip(candidates, results)
+            .filter(|(_, result)| !result.segments.is_empty())
+            .filter_map(|(id, result)| {
+                Some(Neighbor {
+                    id,
+                    similarity: result.similarity(),
+                    offset: result.best_offset?,
+                })
+            })
+            .collect::<Vec<_>>();
+        neighbors.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then(a.id.cmp(&b.id)));
+        neighbors.truncate(k);
+        Ok(neighbors)
+    }
+}
+
+/// Counts matching hashes of the query for each fingerprint and offset.
+///
+/// Offsets are shifted by the length of the query to keep them non-negative.
+pub(crate) fn query_histograms<P>(
+    query: &[u32],
+    postings: impl Fn(u32) -> P,
+) -> HashMap<usize, HashMap<usize, u32>>
+where
+    P: IntoIterator<Item = (u32, u32)>,
+{
+    let mut histograms: HashMap<usize, HashMap<usize, u32>> = HashMap::new();
+    for (i, &item) in query.iter().enumerate() {
+        for (id, position) in postings(align_strip(item)) {
+            *histograms
+                .entry(id as usize)
+                .or_default()
+                .entry(position as usize + query.len() - i)
+                .or_default() += 1;
+        }
+    }
+    histograms
+}
+
+/// Finds occurrences of the query in fingerprints with the given histograms, sorted from the best score.
+pub(crate) fn hits_from_histograms<F: AsRef<[u32]>>(
+    query: &[u32],
+    histograms: HashMap<usize, HashMap<usize, u32>>,
+    config: &Configuration,
+    fingerprint: impl Fn(usize) -> F,
+) -> Vec<IndexHit> {
+    let mut hits = Vec::new();
+    for (id, histogram) in histograms {
+        let haystack = fingerprint(id);
+        hits.extend(
+            occurrences_from_histogram(query, haystack.as_ref(), &histogram, config)
+                .into_iter()
+                .map(|occurrence| IndexHit { id, occurrence }),
+        );
+    }
+    hits.sort_by(|a, b| {
+        a.occurrence
+            .score
+            .total_cmp(&b.occurrence.score)
+            .then(a.id.cmp(&b.id))
+            .then(a.occurrence.offset.cmp(&b.occurrence.offset))
+    });
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item_index::ItemIndex;
+    use crate::utils::random_fingerprint;
+    use crate::Configuration;
+
+    #[test]
+    fn clip_positions() {
+        let mut index = ItemIndex::new();
+        let recordings = [1, 2, 3].map(|seed| random_fingerprint(1000, seed));
+        for recording in &recordings {
+            index.insert(recording);
+        }
+        assert_eq!(3, index.len());
+        assert_eq!(Some(&recordings[2][..]), index.get(2));
+
+        // Flip a few low bits, which doesn't affect the hashes.
+        let clip = recordings[1][400..500]
+            .iter()
+            .map(|item| item ^ 0b101)
+            .collect::<Vec<_>>();
+        let conf = Configuration::preset_test2();
+        let hits = index.search(&clip, &conf);
+        assert_eq!(1, hits.len());
+        assert_eq!(1, hits[0].id);
+        assert_eq!(400, hits[0].occurrence.offset);
+        assert_eq!(100, hits[0].occurrence.items_count);
+        assert_eq!(2.0, hits[0].occurrence.score);
+
+        assert!(index.search(&random_fingerprint(100, 4), &conf).is_empty());
+        assert!(ItemIndex::new().search(&clip, &conf).is_empty());
+    }
+
+    #[test]
+    fn nearest_neighbors() {
+        let mut index = ItemIndex::new();
+        let recordings = [1, 2, 3, 4].map(|seed| random_fingerprint(1000, seed));
+        for recording in &recordings {
+            index.insert(recording);
+        }
+        // Another version of the second recording, sharing its first half with the fourth one.
+        let mut query = recordings[1][100..900].to_vec();
+        query[..300].copy_from_slice(&recordings[3][0..300]);
+
+        let conf = Configuration::preset_test2();
+        let neighbors = index.knn(&query, 2, &conf).unwrap();
+        assert_eq!(2, neighbors.len());
+        assert_eq!(1, neighbors[0].id);
+        assert_eq!(-100, neighbors[0].offset);
+        assert_eq!(3, neighbors[1].id);
+        assert_eq!(0, neighbors[1].offset);
+        assert!(neighbors[0].similarity > neighbors[1].similarity);
+
+        assert_eq!(2, index.knn(&query, 10, &conf).unwrap().len());
+        assert!(index.knn(&query, 0, &conf).unwrap().is_empty());
+        assert!(index
+            .knn(&random_fingerprint(500, 5), 3, &conf)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,24 +1,79 @@
 //! Pure Rust port of [chromaprint](https://acoustid.org/chromaprint)
 
-pub use audio_processor::ResetError;
-pub use compression::FingerprintCompressor;
-pub use fingerprint_matcher::{match_fingerprints, MatchError, Segment};
-pub use fingerprinter::{Configuration, Fingerprinter};
+#[cfg(feature = "acoustid")]
+pub use acoustid::{AcoustIdClient, LookupError, LookupResult, Recording, Release};
+pub use audio_processor::{ConsumeError, DownmixMode, ResamplerKind, ResetError};
+pub use chroma_normalizer::ChromaNorm;
+pub use chunked_fingerprinter::{Chunk, ChunkedFingerprinter};
+pub use classifier::Classifier;
+pub use cluster::{cluster, Cluster};
+pub use compression::{BufferTooSmall, DecompressionError, FingerprintCompressor};
+pub use error::Error;
+#[cfg(feature = "symphonia")]
+pub use file::{fingerprint_file, FileError};
+pub use filter::{Filter, FilterFn, FilterKind};
+pub use fingerprint::{Fingerprint, ParseFingerprintError, RawBytesError};
+pub use fingerprint_matcher::{
+    are_similar, are_similar_with_confidence, find_best_offset, find_in, match_compressed,
+    match_fingerprints, match_fingerprints_with, match_many, match_stretched, match_transposed,
+    score_to_percentage, score_to_similarity, similarity, MatchError, MatchResult, MatcherScratch,
+    Occurrence, OffsetEstimate, Segment, SimilarityOptions, StretchedMatch, TransposedMatch,
+    Verdict,
+};
+pub use fingerprinter::{
+    fingerprint_all, Algorithm, Configuration, Delay, DynPipeline, Fingerprinter,
+    ParseConfigurationError, Pipeline, PipelineStats, StaticPipeline,
+};
+pub use float::Precision;
+pub use item_index::{IndexHit, ItemIndex, Neighbor};
+pub use minhash::{MinHashError, MinHasher, Sketch};
+pub use quantize::Quantizer;
+#[cfg(feature = "cpal")]
+pub use realtime::{CaptureError, RealtimeFingerprinter};
+pub use threaded_fingerprinter::ThreadedFingerprinter;
+#[cfg(feature = "hound")]
+pub use wav::{fingerprint_wav, fingerprint_wav_file, WavError};
 
+#[cfg(feature = "acoustid")]
+mod acoustid;
 mod audio_processor;
 mod chroma;
 mod chroma_filter;
 mod chroma_normalizer;
+mod chunked_fingerprinter;
 mod classifier;
+mod cluster;
 mod compression;
+pub mod container;
+pub mod distance;
+mod error;
 mod fft;
+#[cfg(feature = "symphonia")]
+mod file;
 mod filter;
+mod fingerprint;
 mod fingerprint_calculator;
 mod fingerprint_matcher;
 mod fingerprinter;
+mod float;
 mod gaussian;
 mod gradient;
+pub mod io;
+mod item_index;
+pub mod mapped_index;
+mod minhash;
 mod quantize;
-mod rolling_image;
+#[cfg(feature = "cpal")]
+mod realtime;
+mod resampler;
+pub mod rolling_image;
+mod silence_remover;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
 mod stages;
+mod threaded_fingerprinter;
 mod utils;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "hound")]
+mod wav;
@@ -0,0 +1,314 @@
+//! Read-only layout of an [ItemIndex] that can be searched in place, e.g. from a memory-mapped file.
+//!
+//! The layout consists of little-endian 32-bit words:
+//! - the [MAGIC] bytes, the version byte and 3 reserved bytes,
+//! - number of fingerprints, items and postings,
+//! - start of each fingerprint in the items, followed by the total number of items,
+//! - items of all the fingerprints,
+//! - start of the postings of each hash, followed by the total number of postings,
+//! - postings as pairs of a fingerprint ID and a position of the item in the fingerprint.
+//!
+//! Opening the index only checks the tables of starts, the items and postings are read
+//! when a search needs them. Any `AsRef<[u8]>`, like `memmap2::Mmap`, can hold the data.
+//!
+//! ```
+//! use rusty_chromaprint::mapped_index::{self, MappedIndex};
+//! use rusty_chromaprint::{Configuration, ItemIndex};
+//!
+//! let mut index = ItemIndex::new();
+//! index.insert(&[0x0FCAF446, 0xE3519E89, 0xD3494DD6]);
+//!
+//! let mut data = Vec::new();
+//! mapped_index::write(&index, &mut data).unwrap();
+//!
+//! let mapped = MappedIndex::new(data).unwrap();
+//! assert_eq!(1, mapped.len());
+//! assert_eq!(Some(vec![0x0FCAF446, 0xE3519E89, 0xD3494DD6]), mapped.get(0));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::io::{BufWriter, ErrorKind, Write};
+
+use crate::fingerprint_matcher::HASH_COUNT;
+use crate::item_index::{hits_from_histograms, query_histograms, IndexHit, ItemIndex};
+use crate::Configuration;
+
+/// Bytes identifying the layout.
+pub const MAGIC: [u8; 4] = *b"FPI\0";
+
+/// Version of the layout written by [write()].
+pub const VERSION: u8 = 1;
+
+/// Number of words before the tables.
+const HEADER_WORDS: usize = 5;
+
+/// Writes the index in the read-only layout.
+///
+/// The writer is buffered internally. Indexes with more than `u32::MAX` items or postings
+/// are rejected with [ErrorKind::InvalidInput].
+pub fn write<W: Write>(index: &ItemIndex, writer: W) -> std::io::Result<()> {
+    let too_large = || std::io::Error::new(ErrorKind::InvalidInput, "index is too large");
+    let fingerprints = (0..index.len()).filter_map(|id| index.get(id));
+    let postings = (0..HASH_COUNT).map(|hash| index.postings(hash));
+    let item_count = fingerprints.clone().map(<[u32]>::len).sum::<usize>();
+    let posting_count = postings.clone().map(<[(u32, u32)]>::len).sum::<usize>();
+
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(&MAGIC)?;
+    let mut write_word = |word: usize| -> std::io::Result<()> {
+        let word = u32::try_from(word).map_err(|_| too_large())?;
+        writer.write_all(&word.to_le_bytes())
+    };
+
+    write_word(usize::from(VERSION))?;
+    for count in [index.len(), item_count, posting_count] {
+        write_word(count)?;
+    }
+
+    let mut start = 0;
+    for fp in fingerprints.clone() {
+        write_word(start)?;
+        start += fp.len();
+    }
+    write_word(start)?;
+    for &item in fingerprints.flatten() {
+        write_word(item as usize)?;
+    }
+
+    let mut start = 0;
+    for hash_postings in postings.clone() {
+        write_word(start)?;
+        start += hash_postings.len();
+    }
+    write_word(start)?;
+    for &(id, position) in postings.flatten() {
+        write_word(id as usize)?;
+        write_word(position as usize)?;
+    }
+
+    writer.flush()
+}
+
+/// Index in the read-only layout written by [write()].
+pub struct MappedIndex<D> {
+    data: D,
+    len: usize,
+    items: usize,
+    posting_starts: usize,
+    postings: usize,
+}
+
+impl<D: AsRef<[u8]>> MappedIndex<D> {
+    /// Opens the index, checking its header and the tables of starts.
+    pub fn new(data: D) -> Result<Self, MappedIndexError> {
+        let bytes = data.as_ref();
+        if bytes.len() < HEADER_WORDS * 4 {
+            return Err(MappedIndexError::InvalidLayout);
+        }
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Err(MappedIndexError::InvalidMagic);
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(MappedIndexError::UnsupportedVersion(bytes[MAGIC.len()]));
+        }
+
+        let word = |i: usize| u64::from(read_word(bytes, i));
+        let (len, item_count, posting_count) = (word(2), word(3), word(4));
+        // Counts are checked against the size of the data before using them as indices.
+        let words = HEADER_WORDS as u64 + len + 1 + item_count + u64::from(HASH_COUNT) + 1;
+        if bytes.len() as u64 != (words + 2 * posting_count) * 4 {
+            return Err(MappedIndexError::InvalidLayout);
+        }
+        let (len, item_count, posting_count) =
+            (len as usize, item_count as usize, posting_count as usize);
+        let fingerprint_starts = HEADER_WORDS;
+        let items = fingerprint_starts + len + 1;
+        let posting_starts = items + item_count;
+        let postings = posting_starts + HASH_COUNT as usize + 1;
+
+        let index = Self {
+            data,
+            len,
+            items,
+            posting_starts,
+            postings,
+        };
+        let valid = index.starts_are_valid(fingerprint_starts, len, item_count)
+            && index.starts_are_valid(posting_starts, HASH_COUNT as usize, posting_count);
+        if !valid {
+            return Err(MappedIndexError::InvalidLayout);
+        }
+        Ok(index)
+    }
+
+    /// Number of indexed fingerprints.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if there are no indexed fingerprints.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the fingerprint with the given ID.
+    pub fn get(&self, id: usize) -> Option<Vec<u32>> {
+        if id >= self.len {
+            return None;
+        }
+        let (start, end) = self.start_and_end(HEADER_WORDS + id);
+        Some((start..end).map(|i| self.word(self.items + i)).collect())
+    }
+
+    /// Finds all occurrences of a short query in the indexed fingerprints.
+    ///
+    /// Gives the same hits as [ItemIndex::search] of the index that was written.
+    /// Only the postings of the hashes of the query and the fingerprints containing them are read.
+    pub fn search(&self, query: &[u32], config: &Configuration) -> Vec<IndexHit> {
+        let histograms = query_histograms(query, |hash| {
+            let (start, end) = self.start_and_end(self.posting_starts + hash as usize);
+            (start..end)
+                .map(move |i| {
+                    let posting = self.postings + 2 * i;
+                    (self.word(posting), self.word(posting + 1))
+                })
+                .filter(|&(id, position)| self.is_valid_posting(id as usize, position as usize))
+        });
+        hits_from_histograms(query, histograms, config, |id| {
+            self.get(id).unwrap_or_default()
+        })
+    }
+
+    /// Returns the underlying data.
+    pub fn into_inner(self) -> D {
+        self.data
+    }
+
+    fn word(&self, i: usize) -> u32 {
+        read_word(self.data.as_ref(), i)
+    }
+
+    /// Reads a start from a table along with the next one.
+    fn start_and_end(&self, i: usize) -> (usize, usize) {
+        (self.word(i) as usize, self.word(i + 1) as usize)
+    }
+
+    fn starts_are_valid(&self, table: usize, len: usize, total: usize) -> bool {
+        let mut previous = 0;
+        for i in table..=table + len {
+            let start = self.word(i) as usize;
+            if start < previous {
+                return false;
+            }
+            previous = start;
+        }
+        self.word(table) == 0 && previous == total
+    }
+
+    /// Checks if the posting points at an existing item, so a damaged file cannot cause a panic.
+    fn is_valid_posting(&self, id: usize, position: usize) -> bool {
+        if id >= self.len {
+            return false;
+        }
+        let (start, end) = self.start_and_end(HEADER_WORDS + id);
+        position < end - start
+    }
+}
+
+fn read_word(bytes: &[u8], i: usize) -> u32 {
+    let word = &bytes[4 * i..][..4];
+    u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+}
+
+/// Error returned when an index cannot be opened.
+#[derive(Debug)]
+pub enum MappedIndexError {
+    /// The data doesn't start with the [MAGIC] bytes.
+    InvalidMagic,
+    /// The index was written in an unknown version of the layout.
+    UnsupportedVersion(u8),
+    /// Sizes of the tables don't match the data.
+    InvalidLayout,
+}
+
+impl Display for MappedIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MappedIndexError::InvalidMagic => write!(f, "Not a fingerprint index"),
+            MappedIndexError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported index version: {version}")
+            }
+            MappedIndexError::InvalidLayout => write!(f, "Index is truncated or damaged"),
+        }
+    }
+}
+
+impl std::error::Error for MappedIndexError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapped_index::{write, MappedIndex, MappedIndexError};
+    use crate::utils::random_fingerprint;
+    use crate::{Configuration, ItemIndex};
+
+    #[test]
+    fn round_trip() {
+        let mut index = ItemIndex::new();
+        let recordings = [1, 2, 3].map(|seed| random_fingerprint(1000, seed));
+        for recording in &recordings {
+            index.insert(recording);
+        }
+        index.insert(&[]);
+
+        let mut data = Vec::new();
+        write(&index, &mut data).unwrap();
+        let mapped = MappedIndex::new(data.as_slice()).unwrap();
+        assert_eq!(4, mapped.len());
+        assert_eq!(Some(recordings[2].clone()), mapped.get(2));
+        assert_eq!(Some(Vec::new()), mapped.get(3));
+        assert_eq!(None, mapped.get(4));
+
+        let clip = recordings[1][400..500].to_vec();
+        let conf = Configuration::preset_test2();
+        let hits = mapped.search(&clip, &conf);
+        let expected = index.search(&clip, &conf);
+        assert_eq!(1, hits.len());
+        assert_eq!(expected[0].id, hits[0].id);
+        assert_eq!(expected[0].occurrence.offset, hits[0].occurrence.offset);
+        assert_eq!(400, hits[0].occurrence.offset);
+        assert_eq!(expected[0].occurrence.score, hits[0].occurrence.score);
+        assert!(mapped.search(&random_fingerprint(100, 4), &conf).is_empty());
+
+        let mut empty = Vec::new();
+        write(&ItemIndex::new(), &mut empty).unwrap();
+        assert!(MappedIndex::new(empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_data() {
+        let mut data = Vec::new();
+        write(&ItemIndex::new(), &mut data).unwrap();
+        assert!(matches!(
+            MappedIndex::new(&data[..data.len() - 4]),
+            Err(MappedIndexError::InvalidLayout)
+        ));
+        assert!(matches!(
+            MappedIndex::new(&b"FPB\0\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"[..]),
+            Err(MappedIndexError::InvalidMagic)
+        ));
+
+        data[4] = 9;
+        assert!(matches!(
+            MappedIndex::new(&data[..]),
+            Err(MappedIndexError::UnsupportedVersion(9))
+        ));
+
+        // The postings of the first hash don't start at the beginning.
+        data[4] = 1;
+        data[24] = 1;
+        assert!(matches!(
+            MappedIndex::new(&data[..]),
+            Err(MappedIndexError::InvalidLayout)
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt::{Display, Formatter};
+
+/// Calculates MinHash [sketches](Sketch) of fingerprints.
+///
+/// A fingerprint is turned into a set of shingles, each made of the highest bits of a few
+/// consecutive items. Fractions of equal values of two sketches estimate the Jaccard similarity
+/// of both sets, which allows to find candidates for near-duplicates in large collections
+/// without matching every pair. Only sketches calculated by hashers with the same settings
+/// can be compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHasher {
+    seeds: Vec<u64>,
+    shingle_len: usize,
+    item_bits: u32,
+}
+
+impl Default for MinHasher {
+    fn default() -> Self {
+        Self {
+            seeds: (0..128).map(|i| mix(!i)).collect(),
+            shingle_len: 2,
+            item_bits: 12,
+        }
+    }
+}
+
+impl MinHasher {
+    /// Creates a hasher producing sketches of `num_hashes` values, at least one.
+    pub fn new(num_hashes: usize) -> Result<Self, MinHashError> {
+        if num_hashes == 0 {
+            return Err(MinHashError::InvalidParameter(
+                "at least one hash is required",
+            ));
+        }
+        Ok(Self {
+            seeds: (0..num_hashes as u64).map(|i| mix(!i)).collect(),
+            ..Self::default()
+        })
+    }
+
+    /// Sets the number of consecutive items forming a single shingle, at least one. Defaults to 2.
+    pub fn with_shingle_len(mut self, len: usize) -> Result<Self, MinHashError> {
+        if len == 0 {
+            return Err(MinHashError::InvalidParameter(
+                "shingles must contain at least one item",
+            ));
+        }
+        self.shingle_len = len;
+        Ok(self)
+    }
+
+    /// Number of consecutive items forming a single shingle.
+    pub fn shingle_len(&self) -> usize {
+        self.shingle_len
+    }
+
+    /// Sets the number of the highest bits of each item used in shingles. Defaults to 12.
+    ///
+    /// Fewer bits make the sketches more tolerant to noise, but less discriminative.
+    /// It must be between 1 and 32.
+    pub fn with_item_bits(mut self, bits: u32) -> Result<Self, MinHashError> {
+        if !(1..=32).contains(&bits) {
+            return Err(MinHashError::InvalidParameter(
+                "number of item bits must be between 1 and 32",
+            ));
+        }
+        self.item_bits = bits;
+        Ok(self)
+    }
+
+    /// Number of the highest bits of each item used in shingles.
+    pub fn item_bits(&self) -> u32 {
+        self.item_bits
+    }
+
+    /// Number of values in the produced sketches.
+    pub fn num_hashes(&self) -> usize {
+        self.seeds.len()
+    }
+
+    /// Calculates the sketch of a fingerprint.
+    ///
+    /// The sketch is [empty](Sketch::is_empty) if the fingerprint is shorter than a single shingle.
+    pub fn sketch(&self, fp: &[u32]) -> Sketch {
+        let mut values = vec![u64::MAX; self.seeds.len()];
+        for shingle in fp.windows(self.shingle_len) {
+            let shingle = shingle.iter().fold(0u64, |hash, &item| {
+                mix(hash ^ u64::from(item >> (32 - self.item_bits)))
+            });
+            for (value, &seed) in values.iter_mut().zip(&self.seeds) {
+                *value = (*value).min(mix(shingle ^ seed));
+            }
+        }
+        Sketch { values }
+    }
+}
+
+/// MinHash sketch of a fingerprint calculated by a [MinHasher].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sketch {
+    values: Vec<u64>,
+}
+
+impl Sketch {
+    /// Minimum hashes of the shingles, one for each hash function.
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    /// Checks if the sketched fingerprint had no shingles at all.
+    pub fn is_empty(&self) -> bool {
+        self.values.iter().all(|&value| value == u64::MAX)
+    }
+
+    /// Estimates the Jaccard similarity of shingles of both fingerprints, from 0.0 up to 1.0.
+    ///
+    /// Returns 0.0 if any of the sketches is empty or they come from different hashers.
+    pub fn similarity(&self, other: &Sketch) -> f64 {
+        if self.values.len() != other.values.len() || self.is_empty() || other.is_empty() {
+            return 0.0;
+        }
+        let equal = std::iter::zip(&self.values, &other.values)
+            .filter(|(a, b)| a == b)
+            .count();
+        equal as f64 / self.values.len() as f64
+    }
+
+    /// Returns a key for each band of `rows` consecutive values (locality-sensitive hashing).
+    ///
+    /// Sketches sharing any key are candidates for similar fingerprints, so keys can be used
+    /// to bucket a large collection and compare only the sketches within buckets. Fewer rows
+    /// per band find more candidates. The last band may be shorter than `rows`, which must be positive.
+    pub fn band_keys(&self, rows: usize) -> Result<impl Iterator<Item = u64> + '_, MinHashError> {
+        if rows == 0 {
+            return Err(MinHashError::InvalidParameter(
+                "bands must contain at least one row",
+            ));
+        }
+        Ok(self
+            .values
+            .chunks(rows)
+            .enumerate()
+            .map(|(band, values)| values.iter().fold(band as u64, |key, &v| mix(key ^ v))))
+    }
+}
+
+/// Error returned when a [MinHasher] or the bands of a [Sketch] are given invalid parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinHashError {
+    /// A parameter is out of its valid range, for the given reason.
+    InvalidParameter(&'static str),
+}
+
+impl Display for MinHashError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MinHashError::InvalidParameter(reason) => write!(f, "Invalid parameter: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MinHashError {}
+
+/// Finalizer of SplitMix64, a fast hash function with good avalanche.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::minhash::{MinHashError, MinHasher};
+    use crate::utils::random_fingerprint;
+
+    #[test]
+    fn similarity() {
+        let hasher = MinHasher::default();
+        let fp = random_fingerprint(1000, 1);
+        let sketch = hasher.sketch(&fp);
+        assert_eq!(128, sketch.values().len());
+        assert_eq!(1.0, sketch.similarity(&hasher.sketch(&fp)));
+
+        // Half of the shingles are shared, so the Jaccard similarity is about 1/3.
+        let shifted = hasher
+            .sketch(&fp[500..])
+            .similarity(&hasher.sketch(&fp[..750]));
+        assert!((0.2..0.5).contains(&shifted), "{shifted}");
+
+        let other = hasher.sketch(&random_fingerprint(1000, 2));
+        assert!(sketch.similarity(&other) < 0.05);
+
+        // Noise in the lowest bits is ignored.
+        let noisy = fp.iter().map(|item| item ^ 0xFF).collect::<Vec<_>>();
+        assert_eq!(1.0, sketch.similarity(&hasher.sketch(&noisy)));
+
+        let empty = hasher.sketch(&fp[..1]);
+        assert!(empty.is_empty());
+        assert_eq!(0.0, empty.similarity(&empty));
+    }
+
+    #[test]
+    fn bands() {
+        let hasher = MinHasher::new(10).unwrap();
+        let fp = random_fingerprint(100, 1);
+        let keys = hasher.sketch(&fp).band_keys(4).unwrap().collect::<Vec<_>>();
+        assert_eq!(3, keys.len());
+        assert_eq!(
+            keys,
+            hasher.sketch(&fp).band_keys(4).unwrap().collect::<Vec<_>>()
+        );
+
+        let other = hasher.sketch(&random_fingerprint(100, 2));
+        assert!(other.band_keys(4).unwrap().all(|key| !keys.contains(&key)));
+        assert!(matches!(
+            other.band_keys(0),
+            Err(MinHashError::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(MinHasher::new(0).is_err());
+        assert!(MinHasher::default().with_shingle_len(0).is_err());
+        assert!(MinHasher::default().with_item_bits(0).is_err());
+        assert!(MinHasher::default().with_item_bits(33).is_err());
+
+        let hasher = MinHasher::new(16)
+            .and_then(|hasher| hasher.with_shingle_len(3))
+            .and_then(|hasher| hasher.with_item_bits(32))
+            .unwrap();
+        assert_eq!(
+            (16, 3, 32),
+            (
+                hasher.num_hashes(),
+                hasher.shingle_len(),
+                hasher.item_bits()
+            )
+        );
+        assert_eq!(MinHasher::new(128), Ok(MinHasher::default()));
+    }
+}
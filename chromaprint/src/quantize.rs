@@ -1,4 +1,6 @@
+/// Maps a filter response to one of four values using three thresholds.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quantizer {
     t0: f64,
     t1: f64,
@@ -0,0 +1,223 @@
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+
+use crate::audio_processor::ResetError;
+use crate::chunked_fingerprinter::{Chunk, ChunkedFingerprinter};
+use crate::fingerprinter::Configuration;
+
+/// Fingerprints live audio captured from an input device with [cpal](https://docs.rs/cpal).
+///
+/// The audio is split into chunks like in [ChunkedFingerprinter], but timestamps of the chunks
+/// are UNIX timestamps (in seconds) of the moment their audio was captured.
+/// Fingerprinting runs on a separate thread, so the audio callback is never blocked by it.
+/// Dropping the [RealtimeFingerprinter] stops the capture.
+pub struct RealtimeFingerprinter {
+    _stream: cpal::Stream,
+    chunks: Receiver<Chunk>,
+}
+
+impl RealtimeFingerprinter {
+    /// Starts capturing the audio from the default input device.
+    ///
+    /// See [ChunkedFingerprinter::new] for the meaning of the parameters.
+    pub fn new(
+        config: &Configuration,
+        chunk_duration: f64,
+        overlap: bool,
+    ) -> Result<Self, CaptureError> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or(CaptureError::NoDevice)?;
+        Self::with_device(&device, config, chunk_duration, overlap)
+    }
+
+    /// Starts capturing the audio from the given input device.
+    pub fn with_device(
+        device: &cpal::Device,
+        config: &Configuration,
+        chunk_duration: f64,
+        overlap: bool,
+    ) -> Result<Self, CaptureError> {
+        let supported = device
+            .default_input_config()
+            .map_err(CaptureError::Config)?;
+        let stream_config = supported.config();
+
+        let mut printer = ChunkedFingerprinter::new(config, chunk_duration, overlap);
+        printer.start(
+            stream_config.sample_rate.0,
+            u32::from(stream_config.channels),
+        )?;
+
+        let (samples, received_samples) = channel();
+        let (sender, chunks) = channel();
+        let stream = match supported.sample_format() {
+            SampleFormat::I16 => build_stream::<i16>(device, &stream_config, samples),
+            SampleFormat::I32 => build_stream::<i32>(device, &stream_config, samples),
+            SampleFormat::U16 => build_stream::<u16>(device, &stream_config, samples),
+            SampleFormat::F32 => build_stream::<f32>(device, &stream_config, samples),
+            SampleFormat::F64 => build_stream::<f64>(device, &stream_config, samples),
+            format => return Err(CaptureError::UnsupportedFormat(format)),
+        }
+        .map_err(CaptureError::Build)?;
+
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64());
+        std::thread::spawn(move || run(printer, start, received_samples, sender));
+        stream.play().map_err(CaptureError::Play)?;
+
+        Ok(Self {
+            _stream: stream,
+            chunks,
+        })
+    }
+
+    /// Waits for the next completed chunk.
+    ///
+    /// Returns `None` when the capture has stopped.
+    pub fn next_chunk(&self) -> Option<Chunk> {
+        self.chunks.recv().ok()
+    }
+
+    /// Returns the next completed chunk, if there is one.
+    pub fn try_next_chunk(&self) -> Option<Chunk> {
+        self.chunks.try_recv().ok()
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Sender<Vec<i16>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    i16: FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let data = data
+                .iter()
+                .map(|&sample| i16::from_sample(sample))
+                .collect();
+            // The fingerprinting thread stops only after the stream is dropped.
+            let _ = samples.send(data);
+        },
+        // Gaps in the captured audio only make the affected chunk less accurate.
+        |_| {},
+        None,
+    )
+}
+
+/// Feeds the fingerprinter with the captured samples until the stream is closed.
+fn run(
+    mut printer: ChunkedFingerprinter,
+    start: f64,
+    samples: Receiver<Vec<i16>>,
+    chunks: Sender<Chunk>,
+) {
+    let send = |mut chunk: Chunk| {
+        chunk.timestamp += start;
+        chunks.send(chunk).is_ok()
+    };
+    for data in samples {
+        // The fingerprinter has been started, so it cannot fail.
+        for chunk in printer.consume(&data).unwrap_or_default() {
+            if !send(chunk) {
+                return;
+            }
+        }
+    }
+    if let Some(chunk) = printer.finish() {
+        send(chunk);
+    }
+}
+
+/// Error returned when the audio cannot be captured.
+#[derive(Debug)]
+pub enum CaptureError {
+    /// There is no default input device.
+    NoDevice,
+    /// The configuration of the device cannot be read.
+    Config(cpal::DefaultStreamConfigError),
+    /// The device produces samples in an unsupported format.
+    UnsupportedFormat(SampleFormat),
+    /// The input stream cannot be opened.
+    Build(cpal::BuildStreamError),
+    /// The input stream cannot be started.
+    Play(cpal::PlayStreamError),
+    /// The fingerprinter cannot process the captured audio.
+    Reset(ResetError),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::NoDevice => write!(f, "No input device available"),
+            CaptureError::Config(e) => write!(f, "Cannot read the device configuration: {e}"),
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported sample format {format}")
+            }
+            CaptureError::Build(e) => write!(f, "Cannot open the input stream: {e}"),
+            CaptureError::Play(e) => write!(f, "Cannot start the input stream: {e}"),
+            CaptureError::Reset(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Config(e) => Some(e),
+            CaptureError::Build(e) => Some(e),
+            CaptureError::Play(e) => Some(e),
+            CaptureError::Reset(e) => Some(e),
+            CaptureError::NoDevice | CaptureError::UnsupportedFormat(_) => None,
+        }
+    }
+}
+
+impl From<ResetError> for CaptureError {
+    fn from(e: ResetError) -> Self {
+        CaptureError::Reset(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use crate::assert_eq_float;
+    use crate::chunked_fingerprinter::ChunkedFingerprinter;
+    use crate::fingerprinter::Configuration;
+    use crate::io::{read_pcm_file, PcmFormat};
+    use crate::realtime::run;
+
+    #[test]
+    fn timestamps() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+        let mut printer = ChunkedFingerprinter::new(&Configuration::preset_test2(), 3.0, false);
+        printer.start(11025, 1).unwrap();
+
+        let (samples, received_samples) = channel();
+        let (sender, chunks) = channel();
+        for part in data.chunks(1000) {
+            samples.send(part.to_vec()).unwrap();
+        }
+        drop(samples);
+        run(printer, 1000.0, received_samples, sender);
+
+        let chunks: Vec<_> = chunks.iter().collect();
+        assert_eq!(chunks.len(), 3);
+        for (i, chunk) in chunks.iter().take(2).enumerate() {
+            assert_eq_float!(1000.0 + i as f64 * 3.0, chunk.timestamp, 1e-3);
+            assert!(!chunk.fingerprint.is_empty());
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+
+/// Number of fractional bits of the filter coefficients.
+const FILTER_SHIFT: u32 = 15;
+/// Beta parameter of the Kaiser window.
+const KAISER_BETA: f64 = 9.0;
+const FILTER_LENGTH: usize = 16;
+const PHASE_SHIFT: u32 = 10;
+const CUTOFF: f64 = 0.8;
+
+/// Port of the polyphase resampler used by chromaprint (`av_resample` from FFmpeg's `resample2.c`).
+///
+/// It works on integer samples and produces output identical to the original implementation.
+pub(crate) struct ChromaprintResampler {
+    filter_bank: Vec<i16>,
+    filter_length: usize,
+    src_incr: i64,
+    dst_incr: i64,
+    index: i64,
+    frac: i64,
+    max_output: usize,
+    output: Vec<i16>,
+}
+
+impl ChromaprintResampler {
+    /// Creates a resampler producing at most `max_output` samples in a single call to [Self::process].
+    pub(crate) fn new(input_sample_rate: u32, output_sample_rate: u32, max_output: usize) -> Self {
+        let phase_count = 1 << PHASE_SHIFT;
+        let factor =
+            (f64::from(output_sample_rate) * CUTOFF / f64::from(input_sample_rate)).min(1.0);
+        let filter_length = ((FILTER_LENGTH as f64 / factor).ceil() as usize).max(1);
+
+        let mut resampler = Self {
+            filter_bank: build_filter(factor, filter_length, phase_count),
+            filter_length,
+            src_incr: i64::from(output_sample_rate),
+            dst_incr: i64::from(input_sample_rate) * phase_count as i64,
+            index: 0,
+            frac: 0,
+            max_output,
+            output: Vec::with_capacity(max_output),
+        };
+        resampler.reset();
+        resampler
+    }
+
+    /// Restores the initial state, so the next input is treated as a beginning of a new stream.
+    pub(crate) fn reset(&mut self) {
+        self.index = -(1 << PHASE_SHIFT) * ((self.filter_length as i64 - 1) / 2);
+        self.frac = 0;
+    }
+
+    /// Resamples as much of the input as possible, returning the number of consumed samples.
+    ///
+    /// Samples that weren't consumed must be passed again at the beginning of the next input.
+    pub(crate) fn process(&mut self, input: &[i16]) -> usize {
+        self.output.clear();
+        if input.is_empty() {
+            return 0;
+        }
+
+        let phase_mask = (1 << PHASE_SHIFT) - 1;
+        let src_size = input.len() as i64;
+        let dst_incr_frac = self.dst_incr % self.src_incr;
+        let dst_incr = self.dst_incr / self.src_incr;
+        let mut index = self.index;
+        let mut frac = self.frac;
+
+        while self.output.len() < self.max_output {
+            let phase = (index & phase_mask) as usize;
+            let filter = &self.filter_bank[phase * self.filter_length..][..self.filter_length];
+            let sample_index = index >> PHASE_SHIFT;
+
+            let mut val = 0i32;
+            if sample_index < 0 {
+                // The beginning of the stream is mirrored.
+                for (i, &coefficient) in filter.iter().enumerate() {
+                    let sample = input[((sample_index + i as i64).abs() % src_size) as usize];
+                    val = val.wrapping_add(i32::from(sample) * i32::from(coefficient));
+                }
+            } else if sample_index + self.filter_length as i64 > src_size {
+                break;
+            } else {
+                let samples = &input[sample_index as usize..][..self.filter_length];
+                for (&sample, &coefficient) in std::iter::zip(samples, filter) {
+                    val = val.wrapping_add(i32::from(sample) * i32::from(coefficient));
+                }
+            }
+
+            let val = val.wrapping_add(1 << (FILTER_SHIFT - 1)) >> FILTER_SHIFT;
+            self.output
+                .push(val.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16);
+
+            frac += dst_incr_frac;
+            index += dst_incr;
+            if frac >= self.src_incr {
+                frac -= self.src_incr;
+                index += 1;
+            }
+        }
+
+        let consumed = (index.max(0) >> PHASE_SHIFT) as usize;
+        if index >= 0 {
+            index &= phase_mask;
+        }
+        self.index = index;
+        self.frac = frac;
+        consumed
+    }
+
+    /// Samples produced by the last call to [Self::process].
+    pub(crate) fn output(&self) -> &[i16] {
+        &self.output
+    }
+}
+
+/// Builds a bank of Kaiser-windowed sinc filters, one for each phase.
+fn build_filter(factor: f64, tap_count: usize, phase_count: usize) -> Vec<i16> {
+    let scale = f64::from(1 << FILTER_SHIFT);
+    let center = (tap_count as i64 - 1) / 2;
+    let mut filter = Vec::with_capacity(tap_count * phase_count);
+    let mut tab = vec![0.0; tap_count];
+    for ph in 0..phase_count {
+        let mut norm = 0.0;
+        for (i, value) in tab.iter_mut().enumerate() {
+            let x = PI * ((i as i64 - center) as f64 - ph as f64 / phase_count as f64) * factor;
+            let y = if x == 0.0 { 1.0 } else { x.sin() / x };
+            let w = 2.0 * x / (factor * tap_count as f64 * PI);
+            *value = y * bessel(KAISER_BETA * (1.0 - w * w).max(0.0).sqrt());
+            norm += *value;
+        }
+
+        for value in &tab {
+            let coefficient = round_ties_even((value * scale / norm) as f32);
+            filter.push(coefficient.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16);
+        }
+    }
+    filter
+}
+
+/// Modified Bessel function of the first kind of order zero.
+fn bessel(x: f64) -> f64 {
+    let x = x * x / 4.0;
+    let mut v = 1.0;
+    let mut last_v = 0.0;
+    let mut t = 1.0;
+    let mut i = 1;
+    while v != last_v {
+        last_v = v;
+        t *= x / f64::from(i * i);
+        v += t;
+        i += 1;
+    }
+    v
+}
+
+/// Rounds half-way cases to the nearest even integer, like `lrintf`.
+fn round_ties_even(x: f32) -> f32 {
+    let rounded = x.round();
+    if (x - x.trunc()).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - x.signum()
+    } else {
+        rounded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resampler::{round_ties_even, ChromaprintResampler};
+
+    #[test]
+    fn constant_signal() {
+        let mut resampler = ChromaprintResampler::new(44100, 11025, 1024);
+        let consumed = resampler.process(&[1000; 4000]);
+        assert!(consumed > 3800 && consumed <= 4000);
+        assert_eq!(resampler.output().len(), 1000 - 10);
+        assert!(resampler
+            .output()
+            .iter()
+            .all(|&sample| (999..=1001).contains(&sample)));
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round_ties_even(0.5), 0.0);
+        assert_eq!(round_ties_even(1.5), 2.0);
+        assert_eq!(round_ties_even(-2.5), -2.0);
+        assert_eq!(round_ties_even(-2.6), -3.0);
+    }
+}
@@ -1,5 +1,26 @@
-use crate::filter::Image;
-
+//! Integral image of chroma features used by the classifiers of the fingerprint.
+//!
+//! Columns of the image are chroma bands and rows are consecutive frames. The image allows
+//! to sum any rectangle of the last rows in constant time, which is how the filters of
+//! the classifiers compare the energy of the bands over time.
+//!
+//! ```
+//! use rusty_chromaprint::rolling_image::{Image, RollingIntegralImage};
+//!
+//! let mut image = RollingIntegralImage::new(8);
+//! image.add_row(&[1.0, 2.0, 3.0]);
+//! image.add_row(&[4.0, 5.0, 6.0]);
+//!
+//! // Both rows of the last two columns.
+//! assert_eq!(2.0 + 3.0 + 5.0 + 6.0, image.area(0, 1, 2, 3));
+//! ```
+
+pub use crate::filter::Image;
+
+/// Integral image keeping only a limited number of the last rows.
+///
+/// Every cell holds the sum of all the cells above and to the left of it (inclusive),
+/// so the older rows can be dropped without affecting sums over the retained ones.
 pub struct RollingIntegralImage {
     max_rows: usize,
     columns: usize,
@@ -8,6 +29,9 @@ pub struct RollingIntegralImage {
 }
 
 impl RollingIntegralImage {
+    /// Creates an empty image retaining at most `max_rows` last rows.
+    ///
+    /// The number of columns is taken from the first added row.
     pub fn new(max_rows: usize) -> Self {
         Self {
             max_rows: max_rows + 1,
@@ -17,7 +41,7 @@ impl RollingIntegralImage {
         }
     }
 
-    #[cfg(test)]
+    /// Creates an image from all the rows of `data` with the given number of columns.
     pub fn from_data<D>(columns: usize, data: &[D]) -> Self
     where
         D: Copy + Into<f64>,
@@ -36,7 +60,12 @@ impl RollingIntegralImage {
         image
     }
 
-    pub(crate) fn add_row<T>(&mut self, row: &[T])
+    /// Appends a row, dropping the oldest one if the image is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row has a different length than the first one.
+    pub fn add_row<T>(&mut self, row: &[T])
     where
         T: Copy + Into<f64>,
     {
@@ -62,12 +91,13 @@ impl RollingIntegralImage {
         self.rows += 1;
     }
 
-    #[cfg(test)]
-    fn columns(&self) -> usize {
+    /// Number of columns, it's 0 until the first row is added.
+    pub fn columns(&self) -> usize {
         self.columns
     }
 
-    pub(crate) fn rows(&self) -> usize {
+    /// Number of rows added since the image was created or reset, including the dropped ones.
+    pub fn rows(&self) -> usize {
         self.rows
     }
 
@@ -81,7 +111,8 @@ impl RollingIntegralImage {
         &mut self.data[i * self.columns..][..self.columns]
     }
 
-    pub(crate) fn reset(&mut self) {
+    /// Removes all the rows and columns.
+    pub fn reset(&mut self) {
         self.data.clear();
         self.rows = 0;
         self.columns = 0;
@@ -89,6 +120,9 @@ impl RollingIntegralImage {
 }
 
 impl Image for RollingIntegralImage {
+    /// # Panics
+    ///
+    /// Panics if the rectangle is outside the image or includes dropped rows.
     fn area(&self, r1: usize, c1: usize, r2: usize, c2: usize) -> f64 {
         assert!(r1 <= self.rows);
         assert!(r2 <= self.rows);
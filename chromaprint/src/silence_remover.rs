@@ -0,0 +1,182 @@
+use crate::fingerprinter::PipelineStats;
+use crate::stages::{AudioConsumer, RawAudioConsumer, SpectrumSink, Stage};
+
+/// Number of the latest samples whose average amplitude is compared with the threshold.
+const WINDOW: usize = 55;
+
+/// Drops the audio at the start until the average amplitude exceeds a threshold.
+///
+/// Works the same way as the silence remover of libchromaprint: the amplitudes are compared
+/// as 16-bit integers and the first sample above the threshold is kept.
+pub(crate) struct SilenceRemover<C> {
+    consumer: C,
+    threshold: Option<u32>,
+    /// Set once the silence at the start has ended.
+    started: bool,
+    window: [u32; WINDOW],
+    offset: usize,
+    count: u32,
+    sum: u32,
+}
+
+impl<C> SilenceRemover<C> {
+    pub(crate) fn new(consumer: C) -> Self {
+        Self {
+            consumer,
+            threshold: None,
+            started: false,
+            window: [0; WINDOW],
+            offset: 0,
+            count: 0,
+            sum: 0,
+        }
+    }
+
+    /// Removes the silence below `threshold`, passing all the samples through if it's `None`.
+    pub(crate) fn with_threshold(mut self, threshold: Option<u32>) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns the number of samples at the start of the amplitudes belonging to the silence.
+    fn skip_silence(&mut self, amplitudes: impl Iterator<Item = u32>) -> usize {
+        let threshold = match self.threshold {
+            Some(threshold) if !self.started => threshold,
+            _ => return 0,
+        };
+
+        let mut skipped = 0;
+        for amplitude in amplitudes {
+            self.sum = self.sum + amplitude - self.window[self.offset];
+            self.window[self.offset] = amplitude;
+            self.offset = (self.offset + 1) % WINDOW;
+            self.count = (self.count + 1).min(WINDOW as u32);
+            if self.sum / self.count > threshold {
+                self.started = true;
+                break;
+            }
+            skipped += 1;
+        }
+        skipped
+    }
+}
+
+impl<C: Stage> Stage for SilenceRemover<C> {
+    type Output = C::Output;
+
+    fn output(&self) -> &Self::Output {
+        self.consumer.output()
+    }
+
+    fn clear_output(&mut self) {
+        self.consumer.clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        self.consumer.reserve_output(additional);
+    }
+}
+
+impl<C: AudioConsumer<f64>> AudioConsumer<f64> for SilenceRemover<C> {
+    fn reset(&mut self) {
+        self.started = false;
+        self.window = [0; WINDOW];
+        self.offset = 0;
+        self.count = 0;
+        self.sum = 0;
+        self.consumer.reset();
+    }
+
+    fn consume(&mut self, data: &[f64]) {
+        let amplitudes = data
+            .iter()
+            .map(|&sample| (sample * f64::from(i16::MAX)).abs().round() as u32);
+        let skipped = self.skip_silence(amplitudes);
+        if skipped < data.len() {
+            self.consumer.consume(&data[skipped..]);
+        }
+    }
+
+    fn flush(&mut self) {
+        self.consumer.flush();
+    }
+}
+
+impl<C: RawAudioConsumer> RawAudioConsumer for SilenceRemover<C> {
+    fn consume_raw(&mut self, data: &[i16]) {
+        let amplitudes = data.iter().map(|&sample| u32::from(sample.unsigned_abs()));
+        let skipped = self.skip_silence(amplitudes);
+        if skipped < data.len() {
+            self.consumer.consume_raw(&data[skipped..]);
+        }
+    }
+
+    fn stats(&self, stats: &mut PipelineStats) {
+        self.consumer.stats(stats);
+    }
+
+    fn set_spectrum_sink(&mut self, sink: Option<SpectrumSink>) {
+        self.consumer.set_spectrum_sink(sink);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::silence_remover::SilenceRemover;
+    use crate::stages::{AudioConsumer, RawAudioConsumer, Stage};
+
+    struct Samples(Vec<f64>);
+
+    impl Stage for Samples {
+        type Output = [f64];
+
+        fn output(&self) -> &Self::Output {
+            &self.0
+        }
+
+        fn clear_output(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    impl AudioConsumer<f64> for Samples {
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+
+        fn consume(&mut self, data: &[f64]) {
+            self.0.extend_from_slice(data);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    impl RawAudioConsumer for Samples {}
+
+    #[test]
+    fn remove_silence() {
+        let mut data = vec![10i16; 100];
+        data.extend([-5000, 0, 20, 0]);
+        let expected: Vec<f64> = data[100..]
+            .iter()
+            .map(|&sample| f64::from(sample) / f64::from(i16::MAX))
+            .collect();
+
+        let mut remover = SilenceRemover::new(Samples(Vec::new())).with_threshold(Some(50));
+        remover.consume_raw(&data[..50]);
+        remover.consume_raw(&data[50..]);
+        assert_eq!(expected, remover.output());
+
+        remover.reset();
+        let normalized: Vec<f64> = data
+            .iter()
+            .map(|&sample| f64::from(sample) / f64::from(i16::MAX))
+            .collect();
+        remover.consume(&normalized);
+        assert_eq!(expected, remover.output());
+
+        let mut remover = SilenceRemover::new(Samples(Vec::new()));
+        remover.consume(&normalized);
+        assert_eq!(normalized, remover.output());
+    }
+}
@@ -0,0 +1,243 @@
+//! Vectorized versions of the hot loops, selected at runtime depending on the CPU features.
+//!
+//! Every function gives exactly the same results as its scalar counterpart.
+
+use std::arch::x86_64::*;
+use std::ops::Mul;
+
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::{Num, Zero};
+
+/// Multiplies the input by the window, storing the results as real parts of the output.
+pub(crate) fn apply_window_f64(input: &[f64], window: &[f64], output: &mut [Complex<f64>]) {
+    let len = input.len().min(window.len()).min(output.len());
+    if is_x86_feature_detected!("avx") {
+        // SAFETY: AVX is supported and the slices are at least `len` long.
+        unsafe { apply_window_f64_avx(&input[..len], &window[..len], &mut output[..len]) }
+    } else {
+        apply_window_scalar(&input[..len], &window[..len], &mut output[..len]);
+    }
+}
+
+/// Multiplies the input by the window, storing the results as real parts of the output.
+pub(crate) fn apply_window_f32(input: &[f32], window: &[f32], output: &mut [Complex<f32>]) {
+    let len = input.len().min(window.len()).min(output.len());
+    if is_x86_feature_detected!("avx") {
+        // SAFETY: AVX is supported and the slices are at least `len` long.
+        unsafe { apply_window_f32_avx(&input[..len], &window[..len], &mut output[..len]) }
+    } else {
+        apply_window_scalar(&input[..len], &window[..len], &mut output[..len]);
+    }
+}
+
+/// Calculates squared magnitudes of the complex numbers.
+pub(crate) fn norm_sqr_f64(input: &[Complex<f64>], output: &mut [f64]) {
+    let len = input.len().min(output.len());
+    if is_x86_feature_detected!("avx") {
+        // SAFETY: AVX is supported and the slices are at least `len` long.
+        unsafe { norm_sqr_f64_avx(&input[..len], &mut output[..len]) }
+    } else {
+        norm_sqr_scalar(&input[..len], &mut output[..len]);
+    }
+}
+
+/// Calculates squared magnitudes of the complex numbers.
+pub(crate) fn norm_sqr_f32(input: &[Complex<f32>], output: &mut [f32]) {
+    let len = input.len().min(output.len());
+    if is_x86_feature_detected!("avx") {
+        // SAFETY: AVX is supported and the slices are at least `len` long.
+        unsafe { norm_sqr_f32_avx(&input[..len], &mut output[..len]) }
+    } else {
+        norm_sqr_scalar(&input[..len], &mut output[..len]);
+    }
+}
+
+/// Returns the total number of different bits between the corresponding items.
+pub(crate) fn hamming(fp1: &[u32], fp2: &[u32]) -> u32 {
+    if is_x86_feature_detected!("popcnt") {
+        // SAFETY: POPCNT is supported.
+        unsafe { hamming_popcnt(fp1, fp2) }
+    } else {
+        std::iter::zip(fp1, fp2)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+/// Returns the number of set bits in all the items.
+pub(crate) fn popcount(fp: &[u32]) -> u32 {
+    if is_x86_feature_detected!("popcnt") {
+        // SAFETY: POPCNT is supported.
+        unsafe { popcount_popcnt(fp) }
+    } else {
+        fp.iter().map(|item| item.count_ones()).sum()
+    }
+}
+
+fn apply_window_scalar<T: Zero + Mul<Output = T> + Copy>(
+    input: &[T],
+    window: &[T],
+    output: &mut [Complex<T>],
+) {
+    for ((output, &input), &window) in output.iter_mut().zip(input).zip(window) {
+        output.re = input * window;
+        output.im = T::zero();
+    }
+}
+
+fn norm_sqr_scalar<T: Num + Copy>(input: &[Complex<T>], output: &mut [T]) {
+    for (output, input) in output.iter_mut().zip(input) {
+        *output = input.norm_sqr();
+    }
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn apply_window_f64_avx(input: &[f64], window: &[f64], output: &mut [Complex<f64>]) {
+    let chunks = input.len() / 4;
+    let zero = _mm256_setzero_pd();
+    // `Complex` is `repr(C)`, so the output can be treated as interleaved real and imaginary parts.
+    let out = output.as_mut_ptr() as *mut f64;
+    for i in 0..chunks {
+        let x = _mm256_loadu_pd(input.as_ptr().add(i * 4));
+        let w = _mm256_loadu_pd(window.as_ptr().add(i * 4));
+        let p = _mm256_mul_pd(x, w);
+        // [p0, 0, p2, 0] and [p1, 0, p3, 0]
+        let lo = _mm256_unpacklo_pd(p, zero);
+        let hi = _mm256_unpackhi_pd(p, zero);
+        _mm256_storeu_pd(out.add(i * 8), _mm256_permute2f128_pd(lo, hi, 0x20));
+        _mm256_storeu_pd(out.add(i * 8 + 4), _mm256_permute2f128_pd(lo, hi, 0x31));
+    }
+    let done = chunks * 4;
+    apply_window_scalar(&input[done..], &window[done..], &mut output[done..]);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn apply_window_f32_avx(input: &[f32], window: &[f32], output: &mut [Complex<f32>]) {
+    let chunks = input.len() / 8;
+    let zero = _mm256_setzero_ps();
+    let out = output.as_mut_ptr() as *mut f32;
+    for i in 0..chunks {
+        let x = _mm256_loadu_ps(input.as_ptr().add(i * 8));
+        let w = _mm256_loadu_ps(window.as_ptr().add(i * 8));
+        let p = _mm256_mul_ps(x, w);
+        // [p0, 0, p1, 0, p4, 0, p5, 0] and [p2, 0, p3, 0, p6, 0, p7, 0]
+        let lo = _mm256_unpacklo_ps(p, zero);
+        let hi = _mm256_unpackhi_ps(p, zero);
+        _mm256_storeu_ps(out.add(i * 16), _mm256_permute2f128_ps(lo, hi, 0x20));
+        _mm256_storeu_ps(out.add(i * 16 + 8), _mm256_permute2f128_ps(lo, hi, 0x31));
+    }
+    let done = chunks * 8;
+    apply_window_scalar(&input[done..], &window[done..], &mut output[done..]);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn norm_sqr_f64_avx(input: &[Complex<f64>], output: &mut [f64]) {
+    let chunks = input.len() / 4;
+    let inp = input.as_ptr() as *const f64;
+    for i in 0..chunks {
+        let a = _mm256_loadu_pd(inp.add(i * 8));
+        let b = _mm256_loadu_pd(inp.add(i * 8 + 4));
+        // [n0, n2, n1, n3]
+        let sums = _mm256_hadd_pd(_mm256_mul_pd(a, a), _mm256_mul_pd(b, b));
+        let lo = _mm256_castpd256_pd128(sums);
+        let hi = _mm256_extractf128_pd(sums, 1);
+        _mm_storeu_pd(output.as_mut_ptr().add(i * 4), _mm_unpacklo_pd(lo, hi));
+        _mm_storeu_pd(output.as_mut_ptr().add(i * 4 + 2), _mm_unpackhi_pd(lo, hi));
+    }
+    let done = chunks * 4;
+    norm_sqr_scalar(&input[done..], &mut output[done..]);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn norm_sqr_f32_avx(input: &[Complex<f32>], output: &mut [f32]) {
+    let chunks = input.len() / 8;
+    let inp = input.as_ptr() as *const f32;
+    for i in 0..chunks {
+        let a = _mm256_loadu_ps(inp.add(i * 16));
+        let b = _mm256_loadu_ps(inp.add(i * 16 + 8));
+        // [n0, n1, n4, n5, n2, n3, n6, n7]
+        let sums = _mm256_hadd_ps(_mm256_mul_ps(a, a), _mm256_mul_ps(b, b));
+        let lo = _mm256_castps256_ps128(sums);
+        let hi = _mm256_extractf128_ps(sums, 1);
+        _mm_storeu_ps(
+            output.as_mut_ptr().add(i * 8),
+            _mm_shuffle_ps(lo, hi, 0b01_00_01_00),
+        );
+        _mm_storeu_ps(
+            output.as_mut_ptr().add(i * 8 + 4),
+            _mm_shuffle_ps(lo, hi, 0b11_10_11_10),
+        );
+    }
+    let done = chunks * 8;
+    norm_sqr_scalar(&input[done..], &mut output[done..]);
+}
+
+#[target_feature(enable = "popcnt")]
+unsafe fn hamming_popcnt(fp1: &[u32], fp2: &[u32]) -> u32 {
+    std::iter::zip(fp1, fp2)
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum()
+}
+
+#[target_feature(enable = "popcnt")]
+unsafe fn popcount_popcnt(fp: &[u32]) -> u32 {
+    fp.iter().map(|item| item.count_ones()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::num_complex::Complex;
+
+    use crate::simd::{
+        apply_window_f32, apply_window_f64, apply_window_scalar, hamming, norm_sqr_f32,
+        norm_sqr_f64, norm_sqr_scalar, popcount,
+    };
+
+    #[test]
+    fn window() {
+        let input: Vec<f64> = (0..37).map(|i| f64::from(i) * 0.37 - 5.0).collect();
+        let window: Vec<f64> = (0..37).map(|i| f64::from(i).sin()).collect();
+
+        let mut expected = vec![Complex::new(1.0, 1.0); 37];
+        apply_window_scalar(&input, &window, &mut expected);
+        let mut output = vec![Complex::new(1.0, 1.0); 37];
+        apply_window_f64(&input, &window, &mut output);
+        assert_eq!(expected, output);
+
+        let input: Vec<f32> = input.iter().map(|&x| x as f32).collect();
+        let window: Vec<f32> = window.iter().map(|&x| x as f32).collect();
+        let mut expected = vec![Complex::new(1.0, 1.0); 37];
+        apply_window_scalar(&input, &window, &mut expected);
+        let mut output = vec![Complex::new(1.0, 1.0); 37];
+        apply_window_f32(&input, &window, &mut output);
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn magnitude() {
+        let input: Vec<Complex<f64>> = (0..37)
+            .map(|i| Complex::new(f64::from(i).sin(), f64::from(i) * 0.5 - 3.0))
+            .collect();
+        let mut expected = vec![0.0; 37];
+        norm_sqr_scalar(&input, &mut expected);
+        let mut output = vec![0.0; 37];
+        norm_sqr_f64(&input, &mut output);
+        assert_eq!(expected, output);
+
+        let input: Vec<Complex<f32>> = input
+            .iter()
+            .map(|c| Complex::new(c.re as f32, c.im as f32))
+            .collect();
+        let mut expected = vec![0.0; 37];
+        norm_sqr_scalar(&input, &mut expected);
+        let mut output = vec![0.0; 37];
+        norm_sqr_f32(&input, &mut output);
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(33, popcount(&[u32::MAX, 1]));
+        assert_eq!(33, hamming(&[0, 0, u32::MAX, 0xF], &[0, 1, 0]));
+    }
+}
@@ -1,6 +1,14 @@
+use crate::fingerprinter::PipelineStats;
+
 pub trait Stage {
     type Output: ?Sized;
     fn output(&self) -> &Self::Output;
+
+    /// Clears the output without resetting the internal state of the stage.
+    fn clear_output(&mut self);
+
+    /// Reserves capacity for at least `additional` more items of the output.
+    fn reserve_output(&mut self, _additional: usize) {}
 }
 
 impl<C: Stage> Stage for &mut C {
@@ -9,6 +17,14 @@ impl<C: Stage> Stage for &mut C {
     fn output(&self) -> &Self::Output {
         (**self).output()
     }
+
+    fn clear_output(&mut self) {
+        (**self).clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        (**self).reserve_output(additional);
+    }
 }
 
 pub trait AudioConsumer<T = i16>: Stage {
@@ -23,6 +39,14 @@ impl<S: Stage + ?Sized> Stage for Box<S> {
     fn output(&self) -> &Self::Output {
         (**self).output()
     }
+
+    fn clear_output(&mut self) {
+        (**self).clear_output();
+    }
+
+    fn reserve_output(&mut self, additional: usize) {
+        (**self).reserve_output(additional);
+    }
 }
 
 impl<T, C: AudioConsumer<T> + ?Sized> AudioConsumer<T> for Box<C> {
@@ -39,13 +63,48 @@ impl<T, C: AudioConsumer<T> + ?Sized> AudioConsumer<T> for Box<C> {
     }
 }
 
-pub trait FeatureVectorConsumer: Stage {
-    fn consume(&mut self, features: &[f64]);
+/// Callback receiving the power spectrum of each frame.
+pub(crate) type SpectrumSink = Box<dyn FnMut(&[f64]) + Send>;
+
+/// An [AudioConsumer] of normalized samples, which can also take 16-bit samples directly.
+pub trait RawAudioConsumer: AudioConsumer<f64> {
+    /// Consumes 16-bit samples, normalized by [i16::MAX].
+    fn consume_raw(&mut self, data: &[i16]) {
+        let data: Vec<f64> = data
+            .iter()
+            .map(|&sample| f64::from(sample) / f64::from(i16::MAX))
+            .collect();
+        self.consume(&data);
+    }
+
+    /// Fills in the counters of the stages following the resampling.
+    fn stats(&self, _stats: &mut PipelineStats) {}
+
+    /// Sets a callback receiving the power spectrum of each frame, if there is one.
+    fn set_spectrum_sink(&mut self, _sink: Option<SpectrumSink>) {}
+}
+
+impl<C: RawAudioConsumer + ?Sized> RawAudioConsumer for Box<C> {
+    fn consume_raw(&mut self, data: &[i16]) {
+        (**self).consume_raw(data);
+    }
+
+    fn stats(&self, stats: &mut PipelineStats) {
+        (**self).stats(stats);
+    }
+
+    fn set_spectrum_sink(&mut self, sink: Option<SpectrumSink>) {
+        (**self).set_spectrum_sink(sink);
+    }
+}
+
+pub trait FeatureVectorConsumer<T = f64>: Stage {
+    fn consume(&mut self, features: &[T]);
     fn reset(&mut self);
 }
 
-impl<C: FeatureVectorConsumer> FeatureVectorConsumer for &mut C {
-    fn consume(&mut self, features: &[f64]) {
+impl<T, C: FeatureVectorConsumer<T>> FeatureVectorConsumer<T> for &mut C {
+    fn consume(&mut self, features: &[T]) {
         (**self).consume(features);
     }
     fn reset(&mut self) {
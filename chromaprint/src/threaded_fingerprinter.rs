@@ -0,0 +1,150 @@
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread::JoinHandle;
+
+use crate::audio_processor::{ConsumeError, ResetError};
+use crate::fingerprinter::{Configuration, Fingerprinter};
+
+enum Command {
+    Start(u32, u32, Sender<Result<(), ResetError>>),
+    Consume(Vec<i16>),
+    Finish(Sender<Vec<u32>>),
+}
+
+/// Calculates a fingerprint on a separate thread.
+///
+/// [ThreadedFingerprinter::consume] only copies the samples and hands them over to the worker
+/// thread, which does the resampling and all the following stages. This way decoding of the audio
+/// and its fingerprinting can overlap. When the worker falls behind by more than the given number
+/// of buffers, [ThreadedFingerprinter::consume] blocks until it catches up.
+pub struct ThreadedFingerprinter {
+    commands: Option<SyncSender<Command>>,
+    spare_buffers: Receiver<Vec<i16>>,
+    worker: Option<JoinHandle<()>>,
+    started: bool,
+    fingerprint: Vec<u32>,
+}
+
+impl ThreadedFingerprinter {
+    /// Creates a new [ThreadedFingerprinter] with the given [Configuration], spawning its worker thread.
+    ///
+    /// At most `queue_size` buffers can be waiting for the worker.
+    pub fn new(config: &Configuration, queue_size: usize) -> Self {
+        let (commands, receiver) = sync_channel(queue_size);
+        let (recycler, spare_buffers) = channel();
+        let mut printer = Fingerprinter::new(config);
+        let worker = std::thread::spawn(move || run(&mut printer, receiver, recycler));
+        Self {
+            commands: Some(commands),
+            spare_buffers,
+            worker: Some(worker),
+            started: false,
+            fingerprint: Vec::new(),
+        }
+    }
+
+    /// Resets the internal state to allow for a new fingerprint calculation.
+    ///
+    /// Waits until the worker finishes processing all the previously consumed samples.
+    pub fn start(&mut self, sample_rate: u32, channels: u32) -> Result<(), ResetError> {
+        let (sender, receiver) = channel();
+        self.send(Command::Start(sample_rate, channels, sender));
+        let result = receiver.recv().expect("fingerprinting thread has stopped");
+        self.started = result.is_ok();
+        self.fingerprint.clear();
+        result
+    }
+
+    /// Passes a new chunk of samples to the worker thread.
+    pub fn consume(&mut self, data: &[i16]) -> Result<(), ConsumeError> {
+        if !self.started {
+            return Err(ConsumeError::NotStarted);
+        }
+
+        let mut buffer = self.spare_buffers.try_recv().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(data);
+        self.send(Command::Consume(buffer));
+        Ok(())
+    }
+
+    /// Waits until all the consumed samples are processed and finishes the calculation.
+    pub fn finish(&mut self) {
+        let (sender, receiver) = channel();
+        self.send(Command::Finish(sender));
+        self.fingerprint = receiver.recv().expect("fingerprinting thread has stopped");
+    }
+
+    /// Returns the fingerprint calculated up to the last call to [ThreadedFingerprinter::finish].
+    pub fn fingerprint(&self) -> &[u32] {
+        &self.fingerprint
+    }
+
+    fn send(&self, command: Command) {
+        self.commands
+            .as_ref()
+            .expect("commands are available until drop")
+            .send(command)
+            .expect("fingerprinting thread has stopped");
+    }
+}
+
+impl Drop for ThreadedFingerprinter {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker.
+        self.commands = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(printer: &mut Fingerprinter, commands: Receiver<Command>, recycler: Sender<Vec<i16>>) {
+    for command in commands {
+        match command {
+            Command::Start(sample_rate, channels, result) => {
+                let _ = result.send(printer.start(sample_rate, channels));
+            }
+            Command::Consume(buffer) => {
+                // The fingerprinter was started before any samples were sent.
+                let _ = printer.consume(&buffer);
+                let _ = recycler.send(buffer);
+            }
+            Command::Finish(result) => {
+                printer.finish();
+                let _ = result.send(printer.fingerprint().to_vec());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio_processor::ConsumeError;
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+    use crate::io::{read_pcm_file, PcmFormat};
+    use crate::threaded_fingerprinter::ThreadedFingerprinter;
+
+    #[test]
+    fn same_as_fingerprinter() {
+        let data = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
+        let config = Configuration::preset_test2();
+
+        let mut expected = Fingerprinter::new(&config);
+        expected.start(22050, 2).unwrap();
+        expected.consume(&data).unwrap();
+        expected.finish();
+
+        let mut printer = ThreadedFingerprinter::new(&config, 2);
+        assert_eq!(Err(ConsumeError::NotStarted), printer.consume(&data));
+        assert!(printer.start(22050, 0).is_err());
+        for _ in 0..2 {
+            printer.start(22050, 2).unwrap();
+            for chunk in data.chunks(1000) {
+                printer.consume(chunk).unwrap();
+            }
+            printer.finish();
+            assert!(!printer.fingerprint().is_empty());
+            assert_eq!(expected.fingerprint(), printer.fingerprint());
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use std::path::Path;
-
 #[doc(hidden)]
 #[macro_export]
 macro_rules! assert_eq_float {
@@ -28,11 +26,15 @@ macro_rules! assert_eq_float_slice {
     };
 }
 
-#[allow(unused)]
-pub(crate) fn read_s16le(path: impl AsRef<Path>) -> Vec<i16> {
-    std::fs::read(path)
-        .unwrap()
-        .chunks_exact(2)
-        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-        .collect::<Vec<_>>()
+/// Generates a pseudo-random fingerprint with a xorshift generator.
+#[cfg(test)]
+pub(crate) fn random_fingerprint(len: usize, mut seed: u32) -> Vec<u32> {
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        })
+        .collect()
 }
@@ -0,0 +1,118 @@
+//! JavaScript bindings generated with `wasm-bindgen`.
+//!
+//! Memory use is bounded by the size of the buffers passed to [JsFingerprinter::consume]
+//! and by the length of the fingerprint, which can be limited with `maxDuration`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::fingerprint::Fingerprint;
+use crate::fingerprint_matcher::match_fingerprints;
+use crate::fingerprinter::{Configuration, Fingerprinter};
+
+/// Calculates fingerprints of the audio passed as `Int16Array`s.
+#[wasm_bindgen(js_name = Fingerprinter)]
+pub struct JsFingerprinter {
+    inner: Fingerprinter,
+    algorithm: u8,
+}
+
+#[wasm_bindgen(js_class = Fingerprinter)]
+impl JsFingerprinter {
+    /// Creates a fingerprinter using the algorithm with the given ID (1 is the default one of fpcalc).
+    ///
+    /// With `maxDuration` given, only that many seconds of the audio are fingerprinted.
+    #[wasm_bindgen(constructor)]
+    pub fn new(algorithm: u8, max_duration: Option<f64>) -> Result<JsFingerprinter, JsError> {
+        let mut config = preset(algorithm)?;
+        if let Some(secs) = max_duration {
+            config = config.with_max_duration(secs);
+        }
+        Ok(Self {
+            inner: Fingerprinter::new(&config),
+            algorithm,
+        })
+    }
+
+    /// Resets the internal state to allow for a new fingerprint calculation.
+    pub fn start(&mut self, sample_rate: u32, channels: u32) -> Result<(), JsError> {
+        Ok(self.inner.start(sample_rate, channels)?)
+    }
+
+    /// Adds a new chunk of interleaved samples to the current fingerprint calculation.
+    pub fn consume(&mut self, data: &[i16]) -> Result<(), JsError> {
+        Ok(self.inner.consume(data)?)
+    }
+
+    /// Finishes the fingerprint calculation.
+    pub fn finish(&mut self) {
+        self.inner.finish();
+    }
+
+    /// Returns the fingerprint calculated so far.
+    pub fn fingerprint(&self) -> Vec<u32> {
+        self.inner.fingerprint().to_vec()
+    }
+
+    /// Returns the fingerprint calculated so far, compressed and encoded like in fpcalc.
+    #[wasm_bindgen(js_name = compressedFingerprint)]
+    pub fn compressed_fingerprint(&self) -> String {
+        Fingerprint::new(self.algorithm, self.fingerprint()).to_string()
+    }
+}
+
+/// Compresses and encodes the fingerprint like in fpcalc.
+#[wasm_bindgen(js_name = compressFingerprint)]
+pub fn compress_fingerprint(fingerprint: Vec<u32>, algorithm: u8) -> String {
+    Fingerprint::new(algorithm, fingerprint).to_string()
+}
+
+/// Decodes and decompresses the fingerprint encoded like in fpcalc.
+#[wasm_bindgen(js_name = decompressFingerprint)]
+pub fn decompress_fingerprint(fingerprint: &str) -> Result<Vec<u32>, JsError> {
+    let fingerprint: Fingerprint = fingerprint.parse()?;
+    Ok(fingerprint.into_items())
+}
+
+/// Returns similarity of both fingerprints calculated with the algorithm with the given ID.
+#[wasm_bindgen(js_name = matchFingerprints)]
+pub fn match_fingerprints_js(fp1: &[u32], fp2: &[u32], algorithm: u8) -> Result<f64, JsError> {
+    let result = match_fingerprints(fp1, fp2, &preset(algorithm)?)?;
+    Ok(result.similarity())
+}
+
+fn preset(algorithm: u8) -> Result<Configuration, JsError> {
+    Configuration::preset(algorithm).ok_or_else(|| JsError::new("unknown algorithm"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+    use crate::io::{read_pcm_file, PcmFormat};
+    use crate::wasm::{compress_fingerprint, decompress_fingerprint, JsFingerprinter};
+
+    // Only the paths not touching JavaScript values can run outside of wasm32.
+    #[test]
+    fn same_as_fingerprinter() {
+        let data = read_pcm_file("data/test_mono_44100.raw", PcmFormat::S16Le).unwrap();
+
+        let mut expected = Fingerprinter::new(&Configuration::preset_test2());
+        expected.start(11025, 1).unwrap();
+        expected.consume(&data).unwrap();
+        expected.finish();
+
+        let mut printer = JsFingerprinter::new(1, None).ok().unwrap();
+        printer.start(11025, 1).ok().unwrap();
+        for chunk in data.chunks(4096) {
+            printer.consume(chunk).ok().unwrap();
+        }
+        printer.finish();
+        assert_eq!(expected.fingerprint(), &printer.fingerprint()[..]);
+
+        let compressed = printer.compressed_fingerprint();
+        assert_eq!(compressed, compress_fingerprint(printer.fingerprint(), 1));
+        assert_eq!(
+            printer.fingerprint(),
+            decompress_fingerprint(&compressed).ok().unwrap()
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use hound::{SampleFormat, WavReader};
+
+use crate::audio_processor::ResetError;
+use crate::fingerprint::Fingerprint;
+use crate::fingerprinter::{Configuration, Fingerprinter};
+use crate::io::f32_to_i16;
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Reads a WAV stream with [hound](https://docs.rs/hound) and calculates its fingerprint.
+///
+/// Returns the fingerprint along with the duration of the whole audio.
+/// Integer samples of any width and 32-bit float samples are supported.
+pub fn fingerprint_wav(
+    reader: impl Read,
+    config: &Configuration,
+) -> Result<(Fingerprint, Duration), WavError> {
+    let mut reader = WavReader::new(reader)?;
+    let spec = reader.spec();
+
+    let mut printer = Fingerprinter::new(config);
+    printer.start(spec.sample_rate, u32::from(spec.channels))?;
+
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    match spec.sample_format {
+        SampleFormat::Int => {
+            let bits = u32::from(spec.bits_per_sample);
+            let convert = |sample: i32| {
+                if bits > 16 {
+                    (sample >> (bits - 16)) as i16
+                } else {
+                    (sample << (16 - bits)) as i16
+                }
+            };
+            consume_samples(&mut printer, &mut block, reader.samples::<i32>(), convert)?;
+        }
+        SampleFormat::Float => {
+            consume_samples(
+                &mut printer,
+                &mut block,
+                reader.samples::<f32>(),
+                f32_to_i16,
+            )?;
+        }
+    }
+
+    let duration =
+        Duration::from_secs_f64(f64::from(reader.duration()) / f64::from(spec.sample_rate));
+    Ok((printer.finish_into(), duration))
+}
+
+/// Opens a WAV file and calculates its fingerprint like [fingerprint_wav].
+pub fn fingerprint_wav_file(
+    path: impl AsRef<Path>,
+    config: &Configuration,
+) -> Result<(Fingerprint, Duration), WavError> {
+    let file = std::fs::File::open(path).map_err(hound::Error::IoError)?;
+    fingerprint_wav(std::io::BufReader::new(file), config)
+}
+
+fn consume_samples<S>(
+    printer: &mut Fingerprinter,
+    block: &mut Vec<i16>,
+    samples: impl Iterator<Item = hound::Result<S>>,
+    convert: impl Fn(S) -> i16,
+) -> Result<(), WavError> {
+    for sample in samples {
+        block.push(convert(sample?));
+        if block.len() == BLOCK_SIZE {
+            // The fingerprinter has been started, so it cannot fail.
+            let _ = printer.consume(block);
+            block.clear();
+            if printer.is_complete() {
+                return Ok(());
+            }
+        }
+    }
+    let _ = printer.consume(block);
+    Ok(())
+}
+
+/// Error returned when a WAV stream cannot be fingerprinted.
+#[derive(Debug)]
+pub enum WavError {
+    /// The stream cannot be read or is not a valid WAV.
+    Wav(hound::Error),
+    /// The fingerprinter cannot process the audio.
+    Reset(ResetError),
+}
+
+impl Display for WavError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::Wav(e) => write!(f, "Cannot read WAV: {e}"),
+            WavError::Reset(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for WavError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WavError::Wav(e) => Some(e),
+            WavError::Reset(e) => Some(e),
+        }
+    }
+}
+
+impl From<hound::Error> for WavError {
+    fn from(e: hound::Error) -> Self {
+        WavError::Wav(e)
+    }
+}
+
+impl From<ResetError> for WavError {
+    fn from(e: ResetError) -> Self {
+        WavError::Reset(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use crate::fingerprinter::{Configuration, Fingerprinter};
+    use crate::io::{read_pcm_file, PcmFormat};
+    use crate::wav::{fingerprint_wav, WavError};
+
+    fn encode(data: &[i16], bits_per_sample: u16, sample_format: SampleFormat) -> Vec<u8> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample,
+            sample_format,
+        };
+        let mut wav = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut wav, spec).unwrap();
+        for &sample in data {
+            match (sample_format, bits_per_sample) {
+                (SampleFormat::Float, _) => writer.write_sample(f32::from(sample) / 32768.0),
+                (SampleFormat::Int, 16) => writer.write_sample(sample),
+                (SampleFormat::Int, _) => writer.write_sample(i32::from(sample) << 8),
+            }
+            .unwrap();
+        }
+        writer.finalize().unwrap();
+        wav.into_inner()
+    }
+
+    #[test]
+    fn formats() {
+        let data = read_pcm_file("data/test_stereo_44100.raw", PcmFormat::S16Le).unwrap();
+        let config = Configuration::preset_test2();
+        let mut printer = Fingerprinter::new(&config);
+        printer.start(44100, 2).unwrap();
+        printer.consume(&data).unwrap();
+        printer.finish();
+
+        for (bits, format) in [
+            (16, SampleFormat::Int),
+            (24, SampleFormat::Int),
+            (32, SampleFormat::Float),
+        ] {
+            let wav = encode(&data, bits, format);
+            let (fingerprint, duration) = fingerprint_wav(&wav[..], &config).unwrap();
+            assert_eq!(fingerprint.items(), printer.fingerprint());
+            assert_eq!(
+                duration,
+                Duration::from_secs_f64(data.len() as f64 / 2.0 / 44100.0)
+            );
+        }
+
+        assert!(matches!(
+            fingerprint_wav(&b"RIFF"[..], &config),
+            Err(WavError::Wav(_))
+        ));
+    }
+}
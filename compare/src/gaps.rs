@@ -0,0 +1,39 @@
+/// Returns the ranges of items (as start and end) of a fingerprint of `len` items
+/// not covered by any of the segments given by their offset and length.
+pub fn find_gaps(
+    len: usize,
+    segments: impl Iterator<Item = (usize, usize)>,
+) -> Vec<(usize, usize)> {
+    let mut covered: Vec<(usize, usize)> = segments
+        .map(|(offset, count)| (offset, (offset + count).min(len)))
+        .collect();
+    covered.sort_unstable();
+
+    let mut gaps = Vec::new();
+    let mut position = 0;
+    for (start, end) in covered {
+        if start > position {
+            gaps.push((position, start));
+        }
+        position = position.max(end);
+    }
+    if position < len {
+        gaps.push((position, len));
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gaps::find_gaps;
+
+    #[test]
+    fn gaps() {
+        assert_eq!(find_gaps(10, std::iter::empty()), [(0, 10)]);
+        assert_eq!(find_gaps(10, [(0, 10)].into_iter()), []);
+        assert_eq!(
+            find_gaps(20, [(12, 3), (2, 5), (4, 4)].into_iter()),
+            [(0, 2), (8, 12), (15, 20)]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use rusty_chromaprint::{fingerprint_file, Configuration, Fingerprint, ResamplerKind};
+
+/// Source of a fingerprint to compare.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// Audio file that has to be decoded and fingerprinted.
+    Audio(PathBuf),
+    /// Fingerprint in the raw or compressed format, or a file containing one.
+    Fingerprint(String),
+}
+
+impl Input {
+    /// Returns the items of the fingerprint, calculated with the given configuration.
+    pub fn load(&self, config: &Configuration) -> anyhow::Result<Vec<u32>> {
+        match self {
+            Input::Audio(path) => {
+                // Use the same resampler as fpcalc, so audio files can be compared with its fingerprints.
+                let config = config.clone().with_resampler(ResamplerKind::Chromaprint);
+                let (fingerprint, _) = fingerprint_file(path, &config)
+                    .with_context(|| format!("fingerprinting {}", path.display()))?;
+                Ok(fingerprint.into_items())
+            }
+            Input::Fingerprint(value) => {
+                let path = Path::new(value);
+                let text = if path.is_file() {
+                    let content = std::fs::read_to_string(path)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    extract_fingerprint(&content)
+                        .with_context(|| format!("no fingerprint in {}", path.display()))?
+                } else {
+                    value.clone()
+                };
+                parse_fingerprint(&text, config)
+            }
+        }
+    }
+}
+
+/// Finds the fingerprint in the output of fpcalc (text or JSON) or takes the whole content.
+fn extract_fingerprint(content: &str) -> Option<String> {
+    if let Some(line) = content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("FINGERPRINT="))
+    {
+        return Some(line.to_owned());
+    }
+
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(content.lines().next()?) {
+        return match value.get("fingerprint")? {
+            serde_json::Value::String(fingerprint) => Some(fingerprint.clone()),
+            serde_json::Value::Array(items) => Some(
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            _ => None,
+        };
+    }
+
+    Some(content.trim().to_owned()).filter(|content| !content.is_empty())
+}
+
+fn parse_fingerprint(text: &str, config: &Configuration) -> anyhow::Result<Vec<u32>> {
+    let fingerprint: Fingerprint = text.parse().context("parsing fingerprint")?;
+    // Only compressed fingerprints know their algorithm.
+    let compressed = text.trim().bytes().any(|c| c.is_ascii_alphabetic());
+    if compressed && fingerprint.algorithm() != config.id() {
+        anyhow::bail!(
+            "fingerprint was calculated with algorithm {}, but {} is used",
+            fingerprint.algorithm(),
+            config.id()
+        );
+    }
+    Ok(fingerprint.into_items())
+}
+
+/// Fingerprints audio files, reporting and leaving out the ones that cannot be decoded.
+///
+/// Returns the remaining files along with their fingerprints.
+pub fn load_all(paths: &[PathBuf], config: &Configuration) -> (Vec<PathBuf>, Vec<Vec<u32>>) {
+    let mut files = Vec::new();
+    let mut fingerprints = Vec::new();
+    for path in paths {
+        match Input::Audio(path.clone()).load(config) {
+            Ok(fingerprint) => {
+                files.push(path.clone());
+                fingerprints.push(fingerprint);
+            }
+            Err(e) => eprintln!("WARNING: skipping {}: {e:#}", path.display()),
+        }
+    }
+    (files, fingerprints)
+}
+
+/// Replaces directories with the files they contain, sorted by name.
+pub fn expand_directories(paths: &[PathBuf], recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            list_files(path, recursive, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn list_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("reading directory {}", dir.display()))?
+    {
+        let path = entry
+            .with_context(|| format!("reading directory {}", dir.display()))?
+            .path();
+        entries.push(path);
+    }
+    entries.sort();
+
+    for path in entries {
+        if path.is_file() {
+            files.push(path);
+        } else if recursive && path.is_dir() {
+            list_files(&path, recursive, files)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::extract_fingerprint;
+
+    #[test]
+    fn fpcalc_output() {
+        let text = "FILE=a.wav\nDURATION=20\nFINGERPRINT=AQAAAA\n";
+        assert_eq!(extract_fingerprint(text).as_deref(), Some("AQAAAA"));
+
+        let json = r#"{"file":"a.wav","duration":20.0,"fingerprint":[1,2,3]}"#;
+        assert_eq!(extract_fingerprint(json).as_deref(), Some("1,2,3"));
+
+        let json = r#"{"duration":20.0,"fingerprint":"AQAAAA"}"#;
+        assert_eq!(extract_fingerprint(json).as_deref(), Some("AQAAAA"));
+
+        assert_eq!(extract_fingerprint(" 1,2,3\n").as_deref(), Some("1,2,3"));
+        assert_eq!(extract_fingerprint("\n"), None);
+    }
+}
@@ -1,127 +1,313 @@
+mod gaps;
+mod input;
+mod matrix;
+mod search;
+mod timeline;
 mod utils;
+mod visualize;
 
-use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-use anyhow::Context;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
-use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+use clap::Parser;
+use serde::Serialize;
 
+use crate::gaps::find_gaps;
+use crate::input::Input;
+use crate::matrix::Matrix;
+use crate::search::Search;
+use crate::timeline::print_timeline;
 use crate::utils::DurationExt;
-use rusty_chromaprint::{match_fingerprints, Configuration, Fingerprinter};
+use crate::visualize::print_visualization;
+use rusty_chromaprint::{match_fingerprints, Configuration, MatchResult};
 
-fn calc_fingerprint(path: impl AsRef<Path>, config: &Configuration) -> anyhow::Result<Vec<u32>> {
-    let path = path.as_ref();
-    let src = std::fs::File::open(path).context("failed to open file")?;
-    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+/// Find similar segments of two audio files, or compare many of them at once.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Set the algorithm method.
+    #[arg(short, long, value_parser = |s: &str| s.parse::<Configuration>(), default_value = "1")]
+    algorithm: Configuration,
 
-    let mut hint = Hint::new();
-    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
-    }
+    /// Maximum score (average number of differing bits per item) of a matching segment
+    #[arg(short = 't', long)]
+    match_threshold: Option<f64>,
+
+    /// Merge segments separated by at most this many unmatched items
+    #[arg(short = 'g', long, default_value_t = 0)]
+    max_gap: usize,
+
+    /// Merge segments across a gap only if their scores differ by less than this
+    #[arg(short = 'd', long, default_value_t = 0.0)]
+    max_gap_score_difference: f64,
+
+    /// Exit with 0 only if the similarity is at least this (with --matrix, if any pair is)
+    #[arg(long)]
+    threshold: Option<f64>,
+
+    /// Search for the first input among all files in this directory and its subdirectories
+    #[arg(long, conflicts_with_all = ["matrix", "fp2"])]
+    against: Option<PathBuf>,
+
+    /// Maximum number of files reported by --against
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+
+    /// Show the matched regions of both files on a timeline (text format only)
+    #[arg(long, conflicts_with = "matrix")]
+    timeline: bool,
+
+    /// Show both fingerprints and their differences as heatmaps, aligned at the best offset (text format only)
+    #[arg(long, conflicts_with = "matrix")]
+    visualize: bool,
 
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = Default::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .context("unsupported format")?;
-
-    let mut format = probed.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .context("no supported audio tracks")?;
-
-    let dec_opts: DecoderOptions = Default::default();
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &dec_opts)
-        .context("unsupported codec")?;
-
-    let track_id = track.id;
-
-    let mut printer = Fingerprinter::new(config);
-    let sample_rate = track
-        .codec_params
-        .sample_rate
-        .context("missing sample rate")?;
-    let channels = track
-        .codec_params
-        .channels
-        .context("missing audio channels")?
-        .count() as u32;
-    printer
-        .start(sample_rate, channels)
-        .context("initializing fingerprinter")?;
-
-    let mut sample_buf = None;
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(_) => break,
+    /// Print the output in a certain format (text or json)
+    #[arg(short, long, value_parser = |s: &str| OutputFormat::try_from(s), default_value = "text")]
+    format: OutputFormat,
+
+    /// Use this fingerprint (raw or compressed) or file with fpcalc output instead of the first audio file
+    #[arg(long)]
+    fp1: Option<String>,
+
+    /// Use this fingerprint (raw or compressed) or file with fpcalc output instead of the second audio file
+    #[arg(long)]
+    fp2: Option<String>,
+
+    /// Compare all pairs of the given files and files in the given directories
+    #[arg(short, long, conflicts_with_all = ["fp1", "fp2"])]
+    matrix: bool,
+
+    /// Audio files to compare
+    files: Vec<PathBuf>,
+}
+
+impl Args {
+    /// Returns both inputs, taking audio files for the fingerprints not given explicitly.
+    fn inputs(&self) -> anyhow::Result<(Input, Input)> {
+        let mut files = self.files.iter().cloned().map(Input::Audio);
+        let mut input = |fingerprint: &Option<String>| match fingerprint {
+            Some(fingerprint) => Some(Input::Fingerprint(fingerprint.clone())),
+            None => files.next(),
         };
+        let (Some(input1), Some(input2)) = (input(&self.fp1), input(&self.fp2)) else {
+            anyhow::bail!("two inputs are required, as files or with --fp1 and --fp2");
+        };
+        if files.next().is_some() {
+            anyhow::bail!("too many inputs, only two can be compared");
+        }
+        Ok((input1, input2))
+    }
 
-        if packet.track_id() != track_id {
-            continue;
+    /// Returns the only input used for searching.
+    fn query(&self) -> anyhow::Result<Input> {
+        match (&self.fp1, self.files.as_slice()) {
+            (Some(fingerprint), []) => Ok(Input::Fingerprint(fingerprint.clone())),
+            (None, [file]) => Ok(Input::Audio(file.clone())),
+            _ => anyhow::bail!("exactly one query is required, as a file or with --fp1"),
         }
+    }
 
-        match decoder.decode(&packet) {
-            Ok(audio_buf) => {
-                if sample_buf.is_none() {
-                    let spec = *audio_buf.spec();
-                    let duration = audio_buf.capacity() as u64;
-                    sample_buf = Some(SampleBuffer::<i16>::new(duration, spec));
-                }
+    fn to_config(&self) -> Configuration {
+        let mut config = self
+            .algorithm
+            .clone()
+            .with_gap_merging(self.max_gap, self.max_gap_score_difference);
+        if let Some(threshold) = self.match_threshold {
+            config = config.with_match_threshold(threshold);
+        }
+        config
+    }
+}
 
-                if let Some(buf) = &mut sample_buf {
-                    buf.copy_interleaved_ref(audio_buf);
-                    printer.consume(buf.samples());
-                }
-            }
-            Err(Error::DecodeError(_)) => (),
-            Err(_) => break,
+#[derive(Debug, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl TryFrom<&str> for OutputFormat {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<OutputFormat, Self::Error> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err("invalid output format"),
         }
     }
+}
 
-    printer.finish();
-    Ok(printer.fingerprint().to_vec())
+#[derive(Serialize)]
+struct JsonOutput {
+    similarity: f64,
+    segments: Vec<JsonSegment>,
+    gaps1: Vec<Gap>,
+    gaps2: Vec<Gap>,
 }
 
-pub fn main() -> anyhow::Result<()> {
-    let args: Vec<_> = std::env::args_os().collect();
-    if args.len() != 3 {
-        eprintln!("missing paths to audio files");
-        return Ok(());
+/// Matched segment with all positions in seconds.
+#[derive(Serialize)]
+struct JsonSegment {
+    start1: f32,
+    end1: f32,
+    start2: f32,
+    end2: f32,
+    duration: f32,
+    score: f64,
+}
+
+/// Time range of a file (in seconds) not matched in the other one.
+#[derive(Serialize)]
+struct Gap {
+    start: f32,
+    end: f32,
+    duration: f32,
+}
+
+impl Gap {
+    /// Returns the unmatched ranges of a fingerprint of `len` items.
+    fn find(
+        len: usize,
+        segments: impl Iterator<Item = (usize, usize)>,
+        config: &Configuration,
+    ) -> Vec<Gap> {
+        let item_duration = config.item_duration_in_seconds();
+        find_gaps(len, segments)
+            .into_iter()
+            .map(|(start, end)| Gap {
+                start: start as f32 * item_duration,
+                end: end as f32 * item_duration,
+                duration: (end - start) as f32 * item_duration,
+            })
+            .collect()
+    }
+}
+
+pub fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            // 1 is reserved for files that don't match.
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+
+    let config = args.to_config();
+    if args.matrix {
+        let mut matrix = Matrix::calculate(&args.files, &config)?;
+        if let Some(threshold) = args.threshold {
+            matrix.retain_pairs(threshold);
+        }
+        matrix.print(args.format)?;
+        return Ok(exit_code(args.threshold, matrix.max_similarity()));
+    }
+
+    if let Some(dir) = &args.against {
+        let query = args.query()?.load(&config)?;
+        let threshold = args.threshold.unwrap_or(0.0);
+        let search = Search::run(&query, dir, &config, threshold, args.limit)?;
+        search.print(args.format)?;
+        return Ok(exit_code(args.threshold, search.best_similarity()));
+    }
+
+    let (input1, input2) = args.inputs()?;
+    let fp1 = input1.load(&config)?;
+    let fp2 = input2.load(&config)?;
+
+    let result = match_fingerprints(&fp1, &fp2, &config)?;
+    let segments1 = result.segments.iter().map(|s| (s.offset1, s.items_count));
+    let segments2 = result.segments.iter().map(|s| (s.offset2, s.items_count));
+    let gaps1 = Gap::find(fp1.len(), segments1, &config);
+    let gaps2 = Gap::find(fp2.len(), segments2, &config);
+
+    match args.format {
+        OutputFormat::Text => {
+            print_table(&result, &config);
+            print_gaps("File 1", &gaps1);
+            print_gaps("File 2", &gaps2);
+            if args.timeline {
+                println!();
+                print_timeline(&result, fp1.len(), fp2.len(), &config);
+            }
+            if args.visualize {
+                println!();
+                match result.best_offset {
+                    Some(offset) => print_visualization(&fp1, &fp2, offset, &config),
+                    None => println!("No alignment found, nothing to visualize."),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let output = JsonOutput {
+                similarity: result.similarity(),
+                segments: result
+                    .segments
+                    .iter()
+                    .map(|segment| JsonSegment {
+                        start1: segment.start1(&config),
+                        end1: segment.end1(&config),
+                        start2: segment.start2(&config),
+                        end2: segment.end2(&config),
+                        duration: segment.duration(&config),
+                        score: segment.score,
+                    })
+                    .collect(),
+                gaps1,
+                gaps2,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
     }
 
-    let config = Configuration::preset_test1();
-    let fp1 = calc_fingerprint(&args[1], &config)?;
-    let fp2 = calc_fingerprint(&args[2], &config)?;
+    Ok(exit_code(args.threshold, Some(result.similarity())))
+}
 
-    let segments = match_fingerprints(&fp1, &fp2, &config)?;
+/// Returns success if there is no threshold or the similarity reaches it.
+fn exit_code(threshold: Option<f64>, similarity: Option<f64>) -> ExitCode {
+    match (threshold, similarity) {
+        (None, _) => ExitCode::SUCCESS,
+        (Some(threshold), Some(similarity)) if similarity >= threshold => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
 
+fn print_table(result: &MatchResult, config: &Configuration) {
     println!("  #  |          File 1          |          File 2          |  Duration  |  Score  ");
     println!("-----+--------------------------+--------------------------+------------+---------");
-    for (idx, segment) in segments.iter().enumerate() {
+    for (idx, segment) in result.segments.iter().enumerate() {
         println!(
             "{:>4} | {} -- {} | {} -- {} | {} | {:>6.02}",
             idx + 1,
-            segment.start1(&config).display_duration(),
-            segment.end1(&config).display_duration(),
-            segment.start2(&config).display_duration(),
-            segment.end2(&config).display_duration(),
-            segment.duration(&config).display_duration(),
+            segment.start1(config).display_duration(),
+            segment.end1(config).display_duration(),
+            segment.start2(config).display_duration(),
+            segment.end2(config).display_duration(),
+            segment.duration(config).display_duration(),
             segment.score,
         );
     }
+    println!();
+    println!("Similarity: {:.02}", result.similarity());
+}
+
+fn print_gaps(name: &str, gaps: &[Gap]) {
+    if gaps.is_empty() {
+        return;
+    }
 
-    Ok(())
+    println!();
+    println!("Unmatched in {name}:");
+    for gap in gaps {
+        println!(
+            "  {} -- {} | {}",
+            gap.start.display_duration(),
+            gap.end.display_duration(),
+            gap.duration.display_duration()
+        );
+    }
 }
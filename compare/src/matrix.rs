@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use rusty_chromaprint::{match_many, Configuration};
+use serde::Serialize;
+
+use crate::input::{expand_directories, load_all};
+use crate::OutputFormat;
+
+/// Similarities of all pairs of fingerprinted files.
+#[derive(Serialize)]
+pub struct Matrix {
+    files: Vec<PathBuf>,
+    similarities: Vec<Vec<f64>>,
+    pairs: Vec<Pair>,
+}
+
+/// Pair of different files, listed from the most similar one.
+#[derive(Serialize)]
+struct Pair {
+    file1: PathBuf,
+    file2: PathBuf,
+    similarity: f64,
+}
+
+impl Matrix {
+    /// Fingerprints each file once and matches all the pairs.
+    ///
+    /// Files that cannot be fingerprinted are reported and left out.
+    pub fn calculate(paths: &[PathBuf], config: &Configuration) -> anyhow::Result<Self> {
+        let (files, fingerprints) = load_all(&expand_directories(paths, false)?, config);
+
+        let count = files.len();
+        let mut similarities = vec![vec![1.0; count]; count];
+        for i in 0..count {
+            let others: Vec<&[u32]> = fingerprints[i + 1..].iter().map(Vec::as_slice).collect();
+            let results = match_many(&fingerprints[i], &others, config)?;
+            for (j, result) in (i + 1..count).zip(results) {
+                similarities[i][j] = result.similarity();
+                similarities[j][i] = result.similarity();
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for i in 0..count {
+            for j in i + 1..count {
+                pairs.push(Pair {
+                    file1: files[i].clone(),
+                    file2: files[j].clone(),
+                    similarity: similarities[i][j],
+                });
+            }
+        }
+        pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+
+        Ok(Self {
+            files,
+            similarities,
+            pairs,
+        })
+    }
+
+    /// Keeps only the pairs with similarity of at least `threshold`.
+    pub fn retain_pairs(&mut self, threshold: f64) {
+        self.pairs.retain(|pair| pair.similarity >= threshold);
+    }
+
+    /// Similarity of the most similar pair of the listed ones.
+    pub fn max_similarity(&self) -> Option<f64> {
+        self.pairs.first().map(|pair| pair.similarity)
+    }
+
+    pub fn print(&self, format: OutputFormat) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Text => self.print_text(),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+
+    fn print_text(&self) {
+        for (i, file) in self.files.iter().enumerate() {
+            println!("{:>4}: {}", i + 1, file.display());
+        }
+        println!();
+
+        print!("     ");
+        for i in 0..self.files.len() {
+            print!(" {:>5}", i + 1);
+        }
+        println!();
+        for (i, row) in self.similarities.iter().enumerate() {
+            print!("{:>4} ", i + 1);
+            for similarity in row {
+                print!(" {similarity:>5.02}");
+            }
+            println!();
+        }
+        println!();
+
+        println!("Pairs by similarity:");
+        for pair in &self.pairs {
+            println!(
+                "{:>6.02}  {} -- {}",
+                pair.similarity,
+                pair.file1.display(),
+                pair.file2.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use rusty_chromaprint::{match_many, Configuration};
+use serde::Serialize;
+
+use crate::input::{expand_directories, load_all};
+use crate::OutputFormat;
+
+/// Files of a library that are the most similar to a query.
+#[derive(Serialize)]
+pub struct Search {
+    matches: Vec<SearchMatch>,
+}
+
+#[derive(Serialize)]
+struct SearchMatch {
+    file: PathBuf,
+    similarity: f64,
+    /// Position of the query in the file (in seconds), negative if the file starts later.
+    offset: Option<f32>,
+}
+
+impl Search {
+    /// Matches the query against all files in the directory and its subdirectories.
+    ///
+    /// Returns at most `limit` files with similarity of at least `threshold`, the best first.
+    pub fn run(
+        query: &[u32],
+        dir: &PathBuf,
+        config: &Configuration,
+        threshold: f64,
+        limit: usize,
+    ) -> anyhow::Result<Self> {
+        let (files, fingerprints) = load_all(
+            &expand_directories(std::slice::from_ref(dir), true)?,
+            config,
+        );
+        let others: Vec<&[u32]> = fingerprints.iter().map(Vec::as_slice).collect();
+        let results = match_many(query, &others, config)?;
+
+        let mut matches: Vec<SearchMatch> = files
+            .into_iter()
+            .zip(results)
+            .map(|(file, result)| SearchMatch {
+                file,
+                similarity: result.similarity(),
+                offset: result
+                    .best_offset
+                    .map(|offset| -offset as f32 * config.item_duration_in_seconds()),
+            })
+            .filter(|m| m.similarity >= threshold)
+            .collect();
+        matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        matches.truncate(limit);
+        Ok(Self { matches })
+    }
+
+    /// Similarity of the best match, if there is any.
+    pub fn best_similarity(&self) -> Option<f64> {
+        self.matches.first().map(|m| m.similarity)
+    }
+
+    pub fn print(&self, format: OutputFormat) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Text => {
+                println!(" Similarity |  Offset  | File");
+                println!("------------+----------+-----");
+                for m in &self.matches {
+                    let offset = m
+                        .offset
+                        .map_or_else(|| "-".to_owned(), |offset| format!("{offset:.02}"));
+                    println!(
+                        "   {:>6.02}   | {:>8} | {}",
+                        m.similarity,
+                        offset,
+                        m.file.display()
+                    );
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+}
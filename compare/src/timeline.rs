@@ -0,0 +1,90 @@
+use rusty_chromaprint::{Configuration, MatchResult};
+
+use crate::utils::DurationExt;
+
+/// Width of the longer track (in characters).
+const WIDTH: usize = 64;
+
+/// Labels of the segments, the ones past the last are shown as `*`.
+const LABELS: &[u8] = b"123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Prints both fingerprints as tracks on a common time scale, with matched segments labeled
+/// by their number and unmatched regions shown as `-`.
+pub fn print_timeline(
+    result: &MatchResult,
+    fp1_len: usize,
+    fp2_len: usize,
+    config: &Configuration,
+) {
+    let items_per_column = fp1_len.max(fp2_len).div_ceil(WIDTH).max(1);
+    let track1 = render_track(
+        fp1_len,
+        items_per_column,
+        result.segments.iter().map(|s| (s.offset1, s.items_count)),
+    );
+    let track2 = render_track(
+        fp2_len,
+        items_per_column,
+        result.segments.iter().map(|s| (s.offset2, s.items_count)),
+    );
+
+    let column_duration = items_per_column as f32 * config.item_duration_in_seconds();
+    println!("Timeline (each column is {column_duration:.02} s):");
+    println!(
+        "File 1 |{track1}| {}",
+        (fp1_len as f32 * config.item_duration_in_seconds()).display_duration()
+    );
+    println!(
+        "File 2 |{track2}| {}",
+        (fp2_len as f32 * config.item_duration_in_seconds()).display_duration()
+    );
+}
+
+/// Renders a track of `len` items, where each of `segments` is given by its offset and length.
+///
+/// A column shows the segment covering most of its items.
+fn render_track(
+    len: usize,
+    items_per_column: usize,
+    segments: impl Iterator<Item = (usize, usize)>,
+) -> String {
+    let columns = len.div_ceil(items_per_column);
+    let mut coverage = vec![(0, b'-'); columns];
+    for (index, (offset, count)) in segments.enumerate() {
+        let label = LABELS.get(index).copied().unwrap_or(b'*');
+        let end = (offset + count).min(len);
+        let columns = offset / items_per_column..end.div_ceil(items_per_column);
+        for (column, best) in coverage
+            .iter_mut()
+            .enumerate()
+            .take(columns.end)
+            .skip(columns.start)
+        {
+            let start = (column * items_per_column).max(offset);
+            let stop = ((column + 1) * items_per_column).min(end);
+            let covered = stop.saturating_sub(start);
+            if covered > best.0 {
+                *best = (covered, label);
+            }
+        }
+    }
+    coverage
+        .into_iter()
+        .map(|(_, label)| label as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timeline::render_track;
+
+    #[test]
+    fn track() {
+        assert_eq!(
+            render_track(10, 1, [(2, 3), (7, 2)].into_iter()),
+            "--111--22-"
+        );
+        assert_eq!(render_track(10, 2, [(2, 3), (5, 5)].into_iter()), "-1122");
+        assert_eq!(render_track(5, 2, std::iter::empty()), "---");
+    }
+}
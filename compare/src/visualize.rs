@@ -0,0 +1,92 @@
+use rusty_chromaprint::distance::{diff, ITEM_BITS};
+use rusty_chromaprint::Configuration;
+
+use crate::utils::DurationExt;
+
+/// Width of the heatmaps (in characters).
+const WIDTH: usize = 64;
+
+/// Number of neighbouring bits shown in a single row.
+const BITS_PER_ROW: u32 = 2;
+
+/// Shades from no set bits to all of them.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Prints the overlapping parts of both fingerprints and their XOR as heatmaps,
+/// with fingerprints aligned at `offset` (see [diff]).
+pub fn print_visualization(fp1: &[u32], fp2: &[u32], offset: isize, config: &Configuration) {
+    let diff = diff(fp1, fp2, offset);
+    if diff.is_empty() {
+        println!("Fingerprints don't overlap at offset {offset}.");
+        return;
+    }
+
+    let items_per_column = diff.len().div_ceil(WIDTH).max(1);
+    let item_duration = config.item_duration_in_seconds();
+    let column_duration = items_per_column as f32 * item_duration;
+    println!(
+        "Aligned at offset {offset} (each column is {column_duration:.02} s, darker means more set bits):"
+    );
+
+    let fp1 = &fp1[diff.start1..][..diff.len()];
+    let fp2 = &fp2[diff.start2..][..diff.len()];
+    let start1 = diff.start1 as f32 * item_duration;
+    let start2 = diff.start2 as f32 * item_duration;
+    print_heatmap(
+        &format!("File 1 from {}", start1.display_duration()),
+        fp1,
+        items_per_column,
+    );
+    print_heatmap(
+        &format!("File 2 from {}", start2.display_duration()),
+        fp2,
+        items_per_column,
+    );
+    print_heatmap(
+        &format!("Difference ({:.02}% bits)", diff.error_rate() * 100.0),
+        &diff.xor,
+        items_per_column,
+    );
+}
+
+fn print_heatmap(title: &str, items: &[u32], items_per_column: usize) {
+    println!("{title}:");
+    for row in render_heatmap(items, items_per_column) {
+        println!("|{row}|");
+    }
+}
+
+/// Renders items as columns of shaded cells, the highest bits on top.
+///
+/// Each cell shows the fraction of set bits among `BITS_PER_ROW` bits of `items_per_column` items.
+fn render_heatmap(items: &[u32], items_per_column: usize) -> Vec<String> {
+    (0..ITEM_BITS / BITS_PER_ROW)
+        .map(|row| {
+            let shift = ITEM_BITS - BITS_PER_ROW * (row + 1);
+            let mask = ((1 << BITS_PER_ROW) - 1) << shift;
+            items
+                .chunks(items_per_column)
+                .map(|column| {
+                    let set: u32 = column.iter().map(|item| (item & mask).count_ones()).sum();
+                    let total = column.len() as u32 * BITS_PER_ROW;
+                    let max_shade = SHADES.len() as u32 - 1;
+                    SHADES[((set * max_shade + total / 2) / total) as usize]
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::visualize::render_heatmap;
+
+    #[test]
+    fn heatmap() {
+        let rows = render_heatmap(&[u32::MAX, 0, 0b11 << 30, 0b10 << 30, 1], 2);
+        assert_eq!(16, rows.len());
+        assert_eq!("▒▓ ", rows[0]);
+        assert_eq!("▒  ", rows[1]);
+        assert_eq!("▒ ▒", rows[15]);
+    }
+}
@@ -0,0 +1,201 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::{AtomicFile, ChunkResult};
+
+/// Stores the results of fingerprinted files, so unchanged files can be skipped.
+///
+/// Entries are keyed by the canonical path of the file and the options affecting the results,
+/// and are valid as long as the size and modification time of the file stay the same.
+pub(crate) struct Cache {
+    storage: Storage,
+    options: String,
+}
+
+enum Storage {
+    /// A JSON file per entry in a directory.
+    Dir(PathBuf),
+    /// A table in an SQLite database, shared by the threads processing files.
+    Sqlite(Mutex<Connection>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    options: String,
+    size: u64,
+    modified: (u64, u32),
+    results: Vec<ChunkResult>,
+}
+
+/// Identity of a file at the time it has been fingerprinted.
+struct FileState {
+    path: PathBuf,
+    size: u64,
+    modified: (u64, u32),
+}
+
+impl Cache {
+    /// Opens the cache at `path`, which is an SQLite database if it has a `.sqlite` or `.db`
+    /// extension and a directory otherwise.
+    pub(crate) fn open(path: &Path, options: String) -> io::Result<Self> {
+        let is_database = path
+            .extension()
+            .is_some_and(|ext| ext == "sqlite" || ext == "db");
+        let storage = if is_database {
+            let connection = Connection::open(path).map_err(sqlite_error)?;
+            connection
+                .execute(
+                    "CREATE TABLE IF NOT EXISTS entries (
+                        path TEXT NOT NULL,
+                        options TEXT NOT NULL,
+                        size INTEGER NOT NULL,
+                        modified_secs INTEGER NOT NULL,
+                        modified_nanos INTEGER NOT NULL,
+                        results TEXT NOT NULL,
+                        PRIMARY KEY (path, options)
+                    )",
+                    (),
+                )
+                .map_err(sqlite_error)?;
+            Storage::Sqlite(Mutex::new(connection))
+        } else {
+            std::fs::create_dir_all(path)?;
+            Storage::Dir(path.to_owned())
+        };
+        Ok(Self { storage, options })
+    }
+
+    /// Returns the stored results if the file hasn't changed since it was fingerprinted.
+    pub(crate) fn get(&self, file: &Path) -> Option<Vec<ChunkResult>> {
+        let state = FileState::of(file).ok()?;
+        let dir = match &self.storage {
+            Storage::Dir(dir) => dir,
+            Storage::Sqlite(connection) => return self.get_row(connection, &state),
+        };
+        let data = std::fs::read(self.entry_path(dir, &state)).ok()?;
+        let entry: Entry = serde_json::from_slice(&data).ok()?;
+        let valid = entry.path == state.path
+            && entry.options == self.options
+            && entry.size == state.size
+            && entry.modified == state.modified;
+        valid.then_some(entry.results)
+    }
+
+    pub(crate) fn put(&self, file: &Path, results: Vec<ChunkResult>) -> io::Result<()> {
+        let state = FileState::of(file)?;
+        let dir = match &self.storage {
+            Storage::Dir(dir) => dir,
+            Storage::Sqlite(connection) => return self.put_row(connection, &state, &results),
+        };
+        let mut out = AtomicFile::create(&self.entry_path(dir, &state))?;
+        let entry = Entry {
+            path: state.path,
+            options: self.options.clone(),
+            size: state.size,
+            modified: state.modified,
+            results,
+        };
+        serde_json::to_writer(&mut out, &entry)?;
+        out.commit()
+    }
+
+    fn entry_path(&self, dir: &Path, state: &FileState) -> PathBuf {
+        let key = format!("{}\n{}", state.path.display(), self.options);
+        dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+
+    fn get_row(
+        &self,
+        connection: &Mutex<Connection>,
+        state: &FileState,
+    ) -> Option<Vec<ChunkResult>> {
+        let connection = connection.lock().ok()?;
+        let (size, secs, nanos, results) = connection
+            .query_row(
+                "SELECT size, modified_secs, modified_nanos, results FROM entries
+                 WHERE path = ?1 AND options = ?2",
+                (state.path.to_str()?, &self.options),
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, u32>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .ok()??;
+        let valid = size == state.size as i64
+            && (secs, nanos) == (state.modified.0 as i64, state.modified.1);
+        if !valid {
+            return None;
+        }
+        serde_json::from_str(&results).ok()
+    }
+
+    fn put_row(
+        &self,
+        connection: &Mutex<Connection>,
+        state: &FileState,
+        results: &[ChunkResult],
+    ) -> io::Result<()> {
+        let path = state
+            .path
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "path is not valid UTF-8"))?;
+        let results = serde_json::to_string(results)?;
+        let connection = connection
+            .lock()
+            .map_err(|_| io::Error::other("cache is poisoned"))?;
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO entries
+                 (path, options, size, modified_secs, modified_nanos, results)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (
+                    path,
+                    &self.options,
+                    state.size as i64,
+                    state.modified.0 as i64,
+                    state.modified.1,
+                    results,
+                ),
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+}
+
+fn sqlite_error(error: rusqlite::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+impl FileState {
+    fn of(file: &Path) -> io::Result<Self> {
+        let path = file.canonicalize()?;
+        let metadata = std::fs::metadata(&path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self {
+            path,
+            size: metadata.len(),
+            modified: (modified.as_secs(), modified.subsec_nanos()),
+        })
+    }
+}
+
+/// 64-bit FNV-1a, used because the names of the entries must be stable between runs.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
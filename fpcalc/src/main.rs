@@ -1,11 +1,24 @@
-use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+mod cache;
+mod tags;
+
 use chrono::Local;
 use clap::Parser;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 
 use anyhow::Context;
-use rusty_chromaprint::{Configuration, FingerprintCompressor, Fingerprinter};
+use rusty_chromaprint::io::{PcmFormat, PcmReader};
+use rusty_chromaprint::{
+    Chunk, ChunkedFingerprinter, Configuration, Fingerprint, ParseConfigurationError, ResamplerKind,
+};
+use serde::{Deserialize, Serialize};
 use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
 use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
@@ -14,35 +27,33 @@ use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
-#[derive(Default, Debug, Clone)]
+use crate::cache::Cache;
+
+#[derive(Debug, Clone)]
 struct Algorithm(Configuration);
 
 impl Algorithm {
+    fn new(configuration: Configuration) -> Self {
+        // Use the same resampler as the original fpcalc to get identical fingerprints.
+        Self(configuration.with_resampler(ResamplerKind::Chromaprint))
+    }
+
     fn as_config(&self) -> &Configuration {
         &self.0
     }
 }
 
-impl TryFrom<&str> for Algorithm {
-    type Error = &'static str;
+impl Default for Algorithm {
+    fn default() -> Self {
+        Self::new(Configuration::default())
+    }
+}
 
-    fn try_from(value: &str) -> Result<Algorithm, Self::Error> {
-        let algorithm_id = value
-            .parse::<u8>()
-            .map_err(|_| "value must be between an integer between 0 and 4")?;
-        let configuration = match algorithm_id {
-            0 => Configuration::preset_test1(),
-            1 => Configuration::preset_test2(),
-            2 => Configuration::preset_test3(),
-            3 => Configuration::preset_test4(),
-            4 => Configuration::preset_test5(),
-            _ => {
-                return Err("unknown algorithm ID");
-            }
-        };
-        debug_assert_eq!(configuration.id(), algorithm_id);
-        let algorithm = Algorithm(configuration);
-        Ok(algorithm)
+impl FromStr for Algorithm {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Algorithm, Self::Err> {
+        s.parse().map(Algorithm::new)
     }
 }
 
@@ -52,11 +63,67 @@ impl fmt::Display for Algorithm {
     }
 }
 
+const EXIT_CODES: &str = "Exit codes:
+  1  other error
+  3  unsupported format or codec
+  4  no matching audio track
+  5  error while decoding audio
+When files fail for different reasons, the exit code is 1.";
+
+/// Category of a failure, reported in error records and through the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailureKind {
+    UnsupportedFormat,
+    NoAudioTrack,
+    Decode,
+    Other,
+}
+
+impl FailureKind {
+    /// Finds the kind attached as context to the error.
+    fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<FailureKind>()
+            .copied()
+            .unwrap_or(FailureKind::Other)
+    }
+
+    /// Identifier used in machine-readable error records.
+    fn name(&self) -> &'static str {
+        match self {
+            FailureKind::UnsupportedFormat => "unsupported_format",
+            FailureKind::NoAudioTrack => "no_audio_track",
+            FailureKind::Decode => "decode",
+            FailureKind::Other => "other",
+        }
+    }
+
+    fn exit_code(&self) -> u8 {
+        match self {
+            FailureKind::UnsupportedFormat => 3,
+            FailureKind::NoAudioTrack => 4,
+            FailureKind::Decode => 5,
+            FailureKind::Other => 1,
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureKind::UnsupportedFormat => "unsupported format".fmt(f),
+            FailureKind::NoAudioTrack => "no audio track".fmt(f),
+            FailureKind::Decode => "decoding audio".fmt(f),
+            FailureKind::Other => "error".fmt(f),
+        }
+    }
+}
+
 /// Generate fingerprints from audio files/streams.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES)]
 struct Args {
-    /// Set the input format name
+    /// Set the input format name to read raw PCM audio (s16le or f32le), use "-" as the file to read stdin
     #[arg(short, long)]
     format: Option<String>,
 
@@ -68,6 +135,18 @@ struct Args {
     #[arg(short, long)]
     channels: Option<usize>,
 
+    /// Fingerprint the audio track with this index (counting from 0) instead of the first one
+    #[arg(long, conflicts_with = "language")]
+    track: Option<usize>,
+
+    /// Fingerprint the first audio track in this language (e.g. eng)
+    #[arg(long)]
+    language: Option<String>,
+
+    /// Report the duration of the whole track from the container metadata instead of the analyzed duration
+    #[arg(long)]
+    container_duration: bool,
+
     /// Restrict the duration of the processed input audio
     #[arg(short, long, default_value_t = 120)]
     length: usize,
@@ -77,7 +156,7 @@ struct Args {
     chunk: Option<usize>,
 
     /// Set the algorithm method.
-    #[arg(short, long, value_parser = |s: &str| Algorithm::try_from(s), default_value_t)]
+    #[arg(short, long, value_parser = |s: &str| s.parse::<Algorithm>(), default_value_t)]
     algorithm: Algorithm,
 
     /// Overlap the chunks slightly to make sure audio on the edges is fingerprinted
@@ -96,21 +175,68 @@ struct Args {
     #[arg(short, long)]
     signed: bool,
 
-    /// Print the output in a certain format
+    /// Print the output in a certain format (text, json, ndjson, xml or plain)
     #[arg(short='F', long, value_parser = |s: &str| OutputFormat::try_from(s), default_value = "text")]
     output_format: OutputFormat,
 
-    /// File to analyze
-    file: PathBuf,
+    /// Descend into subdirectories of the given directories
+    #[arg(long)]
+    recursive: bool,
+
+    /// Write the results to this file instead of the standard output
+    #[arg(short = 'O', long, conflicts_with = "sidecar")]
+    output: Option<PathBuf>,
+
+    /// Write the results of each file next to it, into a file with an added .fp extension
+    #[arg(long)]
+    sidecar: bool,
+
+    /// Keep the results in this directory, or in an SQLite database if the path ends with .sqlite or .db,
+    /// and skip files that haven't changed since
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Store the fingerprint and the duration in the ACOUSTID_FINGERPRINT and ACOUSTID_DURATION
+    /// tags of the files (only FLAC files are supported)
+    #[arg(long, conflicts_with_all = ["chunk", "format", "ts"])]
+    write_tags: bool,
+
+    /// Process this many files in parallel, 0 uses all available CPUs
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Files or directories to analyze
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
 }
 
 impl Args {
+    /// Describes all the options affecting the results, so cached results are not reused with
+    /// different ones.
+    fn cache_options(&self) -> String {
+        format!(
+            "algorithm={} length={} chunk={} overlap={} format={:?} rate={:?} channels={:?} \
+             track={:?} language={:?} container_duration={}",
+            self.algorithm,
+            self.length,
+            self.max_chunk_duration(),
+            self.overlap,
+            self.format,
+            self.rate,
+            self.channels,
+            self.track,
+            self.language,
+            self.container_duration,
+        )
+    }
+
     fn max_chunk_duration(&self) -> usize {
         self.chunk.unwrap_or(0)
     }
 
-    fn to_result_printer(&self) -> ResultPrinter<'_> {
+    fn to_result_printer(&self, show_file: bool) -> ResultPrinter<'_> {
         ResultPrinter {
+            show_file,
             config: self.algorithm.as_config(),
             abs_ts: self.ts,
             raw: self.raw,
@@ -125,6 +251,8 @@ impl Args {
 enum OutputFormat {
     Text,
     Json,
+    Ndjson,
+    Xml,
     Plain,
 }
 
@@ -135,6 +263,8 @@ impl TryFrom<&str> for OutputFormat {
         match value {
             "text" => Ok(OutputFormat::Text),
             "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "xml" => Ok(OutputFormat::Xml),
             "plain" => Ok(OutputFormat::Plain),
             _ => Err("invalid result format"),
         }
@@ -146,21 +276,32 @@ impl fmt::Display for OutputFormat {
         match &self {
             Self::Text => "text".fmt(f),
             Self::Json => "json".fmt(f),
+            Self::Ndjson => "ndjson".fmt(f),
+            Self::Xml => "xml".fmt(f),
             Self::Plain => "plain".fmt(f),
         }
     }
 }
 
+/// Which of the audio tracks in a container is fingerprinted.
+enum TrackSelection {
+    First,
+    Index(usize),
+    Language(String),
+}
+
 struct AudioReader {
     format: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     track_id: u32,
     sample_rate: u32,
     channel_count: usize,
+    track_duration: Option<f64>,
+    sample_buf: Option<SampleBuffer<i16>>,
 }
 
 impl AudioReader {
-    fn new(path: &impl AsRef<Path>) -> anyhow::Result<Self> {
+    fn new(path: &impl AsRef<Path>, selection: &TrackSelection) -> anyhow::Result<Self> {
         let path = path.as_ref();
         let src = std::fs::File::open(path).context("failed to open file")?;
         let mss = MediaSourceStream::new(Box::new(src), Default::default());
@@ -175,15 +316,25 @@ impl AudioReader {
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &fmt_opts, &meta_opts)
-            .context("unsupported format")?;
+            .context(FailureKind::UnsupportedFormat)?;
 
         let format = probed.format;
 
-        let track = format
+        let mut tracks = format
             .tracks()
             .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .context("no supported audio tracks")?;
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL);
+        let track = match selection {
+            TrackSelection::First => tracks.next().context(FailureKind::NoAudioTrack)?,
+            TrackSelection::Index(index) => tracks
+                .nth(*index)
+                .with_context(|| format!("index {index} is out of range"))
+                .context(FailureKind::NoAudioTrack)?,
+            TrackSelection::Language(language) => tracks
+                .find(|t| t.language.as_deref() == Some(language.as_str()))
+                .with_context(|| format!("language {language} not found"))
+                .context(FailureKind::NoAudioTrack)?,
+        };
 
         let track_id = track.id;
 
@@ -191,7 +342,8 @@ impl AudioReader {
 
         let decoder = symphonia::default::get_codecs()
             .make(&track.codec_params, &dec_opts)
-            .context("unsupported codec")?;
+            .context("unsupported codec")
+            .context(FailureKind::UnsupportedFormat)?;
 
         let sample_rate = track
             .codec_params
@@ -203,29 +355,168 @@ impl AudioReader {
             .context("missing audio channels")?
             .count();
 
+        let params = &track.codec_params;
+        let track_duration = params.n_frames.map(|frames| match params.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(frames);
+                time.seconds as f64 + time.frac
+            }
+            None => frames as f64 / f64::from(sample_rate),
+        });
+
         Ok(Self {
             format,
             decoder,
             track_id,
             sample_rate,
             channel_count,
+            track_duration,
+            sample_buf: None,
         })
     }
 
-    fn next_buffer(&mut self) -> Result<AudioBufferRef<'_>, Error> {
-        let packet = loop {
-            let packet = match self.format.next_packet() {
-                Ok(packet) => packet,
-                err => break err,
-            };
-
-            if packet.track_id() != self.track_id {
-                continue;
+    fn next_samples(&mut self) -> anyhow::Result<Option<&[i16]>> {
+        let audio_buf = match next_buffer(&mut *self.format, &mut *self.decoder, self.track_id) {
+            Ok(buffer) => buffer,
+            Err(Error::DecodeError(err)) => {
+                return Err(Error::DecodeError(err)).context(FailureKind::Decode)
             }
+            Err(_) => return Ok(None),
+        };
+
+        let required = audio_buf.capacity() * self.channel_count;
+        if !matches!(&self.sample_buf, Some(buf) if buf.capacity() >= required) {
+            let spec = *audio_buf.spec();
+            let duration = audio_buf.capacity() as u64;
+            self.sample_buf = Some(SampleBuffer::<i16>::new(duration, spec));
+        }
+
+        let buf = self.sample_buf.as_mut().expect("buffer is allocated above");
+        buf.copy_interleaved_ref(audio_buf);
+        Ok(Some(buf.samples()))
+    }
+}
+
+fn next_buffer<'a>(
+    format: &mut dyn FormatReader,
+    decoder: &'a mut dyn Decoder,
+    track_id: u32,
+) -> Result<AudioBufferRef<'a>, Error> {
+    let packet = loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            err => break err,
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        break Ok(packet);
+    };
+    packet.and_then(|pkt| decoder.decode(&pkt))
+}
 
-            break Ok(packet);
+struct RawReader {
+    reader: PcmReader<Box<dyn Read>>,
+    sample_rate: u32,
+    channel_count: usize,
+    block: Vec<i16>,
+}
+
+impl RawReader {
+    fn new(
+        path: &Path,
+        format: &str,
+        rate: Option<usize>,
+        channels: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let format = match format {
+            "s16le" => PcmFormat::S16Le,
+            "f32le" => PcmFormat::F32Le,
+            _ => anyhow::bail!("unsupported raw format {format}, expected s16le or f32le"),
         };
-        packet.and_then(|pkt| self.decoder.decode(&pkt))
+        let sample_rate = rate.context("raw input requires the sample rate")?;
+        let channel_count = channels.context("raw input requires the number of channels")?;
+        let source: Box<dyn Read> = if path == Path::new("-") {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(std::fs::File::open(path).context("failed to open file")?)
+        };
+
+        Ok(Self {
+            reader: PcmReader::new(source, format),
+            sample_rate: sample_rate.try_into().context("invalid sample rate")?,
+            channel_count,
+            block: vec![0; 4096 * channel_count],
+        })
+    }
+
+    fn next_samples(&mut self) -> anyhow::Result<Option<&[i16]>> {
+        match self
+            .reader
+            .read(&mut self.block)
+            .context("reading raw audio")?
+        {
+            0 => Ok(None),
+            n => Ok(Some(&self.block[..n])),
+        }
+    }
+}
+
+/// Source of interleaved samples, either decoded from a media file or read as raw PCM.
+enum Input {
+    Decoded(AudioReader),
+    Raw(RawReader),
+}
+
+impl Input {
+    fn new(args: &Args, path: &Path) -> anyhow::Result<Self> {
+        match &args.format {
+            Some(format) => Ok(Input::Raw(RawReader::new(
+                path,
+                format,
+                args.rate,
+                args.channels,
+            )?)),
+            None => {
+                let selection = match (args.track, &args.language) {
+                    (Some(index), _) => TrackSelection::Index(index),
+                    (None, Some(language)) => TrackSelection::Language(language.clone()),
+                    (None, None) => TrackSelection::First,
+                };
+                Ok(Input::Decoded(AudioReader::new(&path, &selection)?))
+            }
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Input::Decoded(reader) => reader.sample_rate,
+            Input::Raw(reader) => reader.sample_rate,
+        }
+    }
+
+    /// Duration of the whole track (in seconds) if it's known upfront.
+    fn track_duration(&self) -> Option<f64> {
+        match self {
+            Input::Decoded(reader) => reader.track_duration,
+            Input::Raw(_) => None,
+        }
+    }
+
+    fn channel_count(&self) -> usize {
+        match self {
+            Input::Decoded(reader) => reader.channel_count,
+            Input::Raw(reader) => reader.channel_count,
+        }
+    }
+
+    fn next_samples(&mut self) -> anyhow::Result<Option<&[i16]>> {
+        match self {
+            Input::Decoded(reader) => reader.next_samples(),
+            Input::Raw(reader) => reader.next_samples(),
+        }
     }
 }
 
@@ -235,152 +526,448 @@ fn get_current_timestamp() -> f64 {
     (usec as f64) / 1000000.0
 }
 
-pub fn main() -> anyhow::Result<()> {
+/// Expands directories into the files they contain, sorted by name.
+fn collect_files(paths: &[PathBuf], recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
+    fn visit(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+        let mut entries = std::fs::read_dir(dir)
+            .with_context(|| format!("reading directory {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("reading directory {}", dir.display()))?;
+        entries.sort();
+
+        for path in entries {
+            if path.is_dir() {
+                if recursive {
+                    visit(&path, recursive, files)?;
+                }
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            visit(path, recursive, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+pub fn main() -> anyhow::Result<ExitCode> {
     let args = Args::parse();
-    let result_printer = args.to_result_printer();
+    let files = collect_files(&args.files, args.recursive)?;
+    let show_file = files.len() > 1 || args.files.iter().any(|path| path.is_dir());
+    if args.sidecar && files.iter().any(|file| file == Path::new("-")) {
+        anyhow::bail!("cannot write a sidecar file for the standard input");
+    }
+    if args.write_tags && files.iter().any(|file| file == Path::new("-")) {
+        anyhow::bail!("cannot write tags to the standard input");
+    }
 
-    let mut reader = AudioReader::new(&args.file).context("initializing audio reader")?;
+    let mut output_file = match &args.output {
+        Some(path) => Some(AtomicFile::create(path).context("creating output file")?),
+        None => None,
+    };
+    let out: Box<dyn Write> = match &mut output_file {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut reporter = Reporter::new(args.to_result_printer(show_file), out, args.sidecar)?;
+
+    // Timestamps of real-time streams can't be reused.
+    let cache = match &args.cache {
+        Some(path) if !args.ts => {
+            Some(Cache::open(path, args.cache_options()).context("opening cache")?)
+        }
+        _ => None,
+    };
+    let cache = cache.as_ref();
+
+    let jobs = match args.jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    if jobs == 1 {
+        for file in &files {
+            let result =
+                process_file_tagged(&args, cache, file, &mut |chunk| reporter.chunk(file, chunk));
+            reporter.finish_file(file, result)?;
+        }
+    } else {
+        let mut output = Ok(());
+        process_files_parallel(&args, cache, &files, jobs, |file, chunks, result| {
+            for chunk in chunks {
+                reporter.chunk(file, chunk);
+            }
+            if output.is_ok() {
+                output = reporter.finish_file(file, result);
+            }
+        });
+        output?;
+    }
+    let failures = reporter.finish()?;
+    if let Some(file) = output_file {
+        file.commit().context("writing output file")?;
+    }
 
-    let config = args.algorithm.as_config();
-    let mut printer = Fingerprinter::new(config);
+    let Some(&kind) = failures.first() else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    if show_file {
+        eprintln!(
+            "failed to process {} of {} files",
+            failures.len(),
+            files.len()
+        );
+    }
+    if failures.iter().all(|&other| other == kind) {
+        Ok(ExitCode::from(kind.exit_code()))
+    } else {
+        Ok(ExitCode::from(FailureKind::Other.exit_code()))
+    }
+}
 
-    let channel_count: u32 = reader
-        .channel_count
-        .try_into()
-        .context("converting sample rate")?;
-    printer
-        .start(reader.sample_rate, channel_count)
-        .context("initializing fingerprinter")?;
+/// Writes the results of all files, either into a single output or into sidecar files.
+struct Reporter<'a> {
+    printer: ResultPrinter<'a>,
+    out: Box<dyn Write + 'a>,
+    first: bool,
+    /// Results of the current file, kept until it's done when writing sidecar files.
+    sidecar: Option<Vec<ChunkResult>>,
+    /// The first error of writing a chunk, reported once the file is done.
+    error: Option<io::Error>,
+    failures: Vec<FailureKind>,
+}
 
-    let mut sample_buf = None;
+impl<'a> Reporter<'a> {
+    fn new(
+        printer: ResultPrinter<'a>,
+        mut out: Box<dyn Write + 'a>,
+        sidecar: bool,
+    ) -> io::Result<Self> {
+        if !sidecar {
+            printer.begin(&mut out)?;
+        }
+        Ok(Self {
+            printer,
+            out,
+            first: true,
+            sidecar: sidecar.then(Vec::new),
+            error: None,
+            failures: Vec::new(),
+        })
+    }
 
-    let mut ts: f64 = 0.0;
-    if args.ts {
-        ts = get_current_timestamp();
+    fn chunk(&mut self, file: &Path, result: ChunkResult) {
+        if let Some(pending) = &mut self.sidecar {
+            pending.push(result);
+        } else if self.error.is_none() {
+            self.error = self
+                .printer
+                .print_result(&mut self.out, &result, file, self.first)
+                .err();
+            self.first = false;
+        }
     }
 
-    let sample_rate = usize::try_from(reader.sample_rate).context("invalid sample rate")?;
+    fn finish_file(&mut self, file: &Path, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e).context("writing results");
+        }
 
-    let mut stream_size = 0;
-    let stream_limit = args.length * sample_rate;
+        match result {
+            Ok(()) => {
+                if let Some(pending) = &mut self.sidecar {
+                    let results = std::mem::take(pending);
+                    self.write_sidecar(file, &results)
+                        .with_context(|| format!("writing sidecar file for {}", file.display()))?;
+                }
+            }
+            Err(e) => {
+                if let Some(pending) = &mut self.sidecar {
+                    pending.clear();
+                }
+                self.printer
+                    .print_error(&mut self.out, file, &e)
+                    .context("writing results")?;
+                self.failures.push(FailureKind::of(&e));
+            }
+        }
+        Ok(())
+    }
 
-    let mut chunk_size = 0;
-    let chunk_limit = args.max_chunk_duration() * sample_rate;
+    fn write_sidecar(&self, file: &Path, results: &[ChunkResult]) -> io::Result<()> {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".fp");
+        let mut out = AtomicFile::create(Path::new(&path))?;
+        self.printer.begin(&mut out)?;
+        for (i, result) in results.iter().enumerate() {
+            self.printer.print_result(&mut out, result, file, i == 0)?;
+        }
+        self.printer.end(&mut out)?;
+        out.commit()
+    }
+
+    /// Finishes the output and returns the kinds of all failures.
+    fn finish(mut self) -> anyhow::Result<Vec<FailureKind>> {
+        if self.sidecar.is_none() {
+            self.printer.end(&mut self.out).context("writing results")?;
+        }
+        self.out.flush().context("writing results")?;
+        Ok(self.failures)
+    }
+}
 
-    let mut extra_chunk_limit = 0;
-    let mut overlap: f64 = 0.0;
+/// A file that replaces the target path only once it's completely written.
+struct AtomicFile {
+    file: io::BufWriter<std::fs::File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
 
-    if chunk_limit > 0 && args.overlap {
-        extra_chunk_limit = config.delay();
-        overlap = (config.delay() as f64) * 1.0 / (sample_rate as f64) / 1000.0;
+impl AtomicFile {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+        let file = std::fs::File::create(&temp_path)?;
+        Ok(Self {
+            file: io::BufWriter::new(file),
+            temp_path,
+            path: path.to_owned(),
+        })
     }
 
-    let mut first_chunk = true;
+    fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        std::fs::rename(&self.temp_path, &self.path)
+    }
+}
 
-    loop {
-        let audio_buf = match reader.next_buffer() {
-            Ok(buffer) => buffer,
-            Err(Error::DecodeError(err)) => Err(Error::DecodeError(err))?,
-            Err(_) => break,
-        };
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
 
-        if sample_buf.is_none() {
-            let spec = *audio_buf.spec();
-            let duration = audio_buf.capacity() as u64;
-            sample_buf = Some(SampleBuffer::<i16>::new(duration, spec));
-        }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
 
-        if let Some(buf) = &mut sample_buf {
-            let (stream_done, mut frame_size) = if stream_limit > 0 {
-                let remaining = stream_limit - stream_size;
-                let frame_size = audio_buf.frames();
-                (frame_size > remaining, frame_size.min(remaining))
-            } else {
-                (false, audio_buf.frames())
-            };
-            stream_size += frame_size;
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Does nothing after a successful commit, since the file has been renamed.
+        let _ = std::fs::remove_file(&self.temp_path);
+    }
+}
 
-            if frame_size == 0 {
-                if stream_done {
+/// Processes files on `jobs` threads, handing the results to `output` in the original order.
+fn process_files_parallel(
+    args: &Args,
+    cache: Option<&Cache>,
+    files: &[PathBuf],
+    jobs: usize,
+    mut output: impl FnMut(&Path, Vec<ChunkResult>, anyhow::Result<()>),
+) {
+    let next_file = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            let sender = sender.clone();
+            let next_file = &next_file;
+            scope.spawn(move || loop {
+                let index = next_file.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let mut chunks = Vec::new();
+                let result =
+                    process_file_tagged(args, cache, file, &mut |chunk| chunks.push(chunk));
+                if sender.send((index, chunks, result)).is_err() {
                     break;
-                } else {
-                    continue;
                 }
+            });
+        }
+        drop(sender);
+
+        // Results arrive out of order, so keep them until all the preceding files are done.
+        let mut pending = BTreeMap::new();
+        let mut next_output = 0;
+        for (index, chunks, result) in receiver {
+            pending.insert(index, (chunks, result));
+            while let Some((chunks, result)) = pending.remove(&next_output) {
+                output(&files[next_output], chunks, result);
+                next_output += 1;
             }
+        }
+    });
+}
 
-            let first_part_size = frame_size;
-            let (chunk_done, first_part_size) = if chunk_limit > 0 {
-                let remaining = chunk_limit + extra_chunk_limit - chunk_size;
-                (first_part_size > remaining, first_part_size.min(remaining))
-            } else {
-                (false, first_part_size)
-            };
+/// Fingerprint of a chunk along with the properties of the audio it was calculated from.
+#[derive(Clone, Serialize, Deserialize)]
+struct ChunkResult {
+    chunk: Chunk,
+    sample_rate: u32,
+    channels: usize,
+    track_duration: Option<f64>,
+}
 
-            buf.copy_interleaved_ref(audio_buf);
-            let frame_data = buf.samples();
-            printer.consume(&frame_data[..first_part_size * reader.channel_count]);
+/// Fingerprints a single file like [process_file_cached], storing the fingerprint in its tags if requested.
+fn process_file_tagged(
+    args: &Args,
+    cache: Option<&Cache>,
+    path: &Path,
+    emit: &mut dyn FnMut(ChunkResult),
+) -> anyhow::Result<()> {
+    if !args.write_tags {
+        return process_file_cached(args, cache, path, emit);
+    }
 
-            chunk_size += first_part_size;
+    // Without chunking there is a single result for the whole file.
+    let mut last = None;
+    process_file_cached(args, cache, path, &mut |result| {
+        last = Some(result.chunk.clone());
+        emit(result);
+    })?;
+    if let Some(chunk) = last {
+        let config = args.algorithm.as_config();
+        let fingerprint = Fingerprint::new(config.id(), chunk.fingerprint).to_string();
+        tags::write_fingerprint(path, &fingerprint, chunk.duration).context("writing tags")?;
+    }
+    Ok(())
+}
 
-            if chunk_done {
-                printer.finish();
+/// Fingerprints a single file like [process_file], reusing the results stored in the cache.
+fn process_file_cached(
+    args: &Args,
+    cache: Option<&Cache>,
+    path: &Path,
+    emit: &mut dyn FnMut(ChunkResult),
+) -> anyhow::Result<()> {
+    let cache = match cache {
+        Some(cache) if path != Path::new("-") => cache,
+        _ => return process_file(args, path, emit),
+    };
+
+    if let Some(results) = cache.get(path) {
+        results.into_iter().for_each(emit);
+        return Ok(());
+    }
 
-                let chunk_duration = (chunk_size - extra_chunk_limit) as f64 * 1.0
-                    / f64::from(reader.sample_rate)
-                    + overlap;
-                result_printer.print_result(&printer, first_chunk, ts, chunk_duration);
+    let mut results = Vec::new();
+    process_file(args, path, &mut |result| {
+        results.push(result.clone());
+        emit(result);
+    })?;
+    if let Err(e) = cache.put(path, results) {
+        eprintln!(
+            "WARNING: {}: cannot store results in cache: {e}",
+            path.display()
+        );
+    }
+    Ok(())
+}
 
-                if args.ts {
-                    ts = get_current_timestamp();
-                } else {
-                    ts += chunk_duration;
-                }
+/// Fingerprints a single file, passing the results to `emit` as soon as they are ready.
+fn process_file(args: &Args, path: &Path, emit: &mut dyn FnMut(ChunkResult)) -> anyhow::Result<()> {
+    let mut input = Input::new(args, path).context("initializing audio reader")?;
 
-                if args.overlap {
-                    printer = Fingerprinter::new(config);
-                    ts -= overlap;
-                } else {
-                    printer
-                        .start(reader.sample_rate, channel_count)
-                        .context("initializing fingerprinter")?;
-                }
+    let config = args.algorithm.as_config();
+    let mut printer =
+        ChunkedFingerprinter::new(config, args.max_chunk_duration() as f64, args.overlap);
 
-                if first_chunk {
-                    extra_chunk_limit = 0;
-                    first_chunk = false;
-                }
+    let channel_count = input.channel_count();
+    printer
+        .start(
+            input.sample_rate(),
+            channel_count
+                .try_into()
+                .context("converting channel count")?,
+        )
+        .context("initializing fingerprinter")?;
 
-                chunk_size = 0;
-            }
+    let mut ts: f64 = 0.0;
+    if args.ts {
+        ts = get_current_timestamp();
+    }
 
-            frame_size -= first_part_size;
-            if frame_size > 0 {
-                printer.consume(
-                    &frame_data[(first_part_size * reader.channel_count)
-                        ..(frame_size * reader.channel_count)],
-                );
+    let sample_rate = usize::try_from(input.sample_rate()).context("invalid sample rate")?;
+
+    let mut stream_size = 0;
+    let stream_limit = args.length * sample_rate;
+
+    let overlap = printer.overlap_duration();
+    let input_sample_rate = input.sample_rate();
+    let track_duration = input.track_duration();
+    let mut print_chunk = |mut chunk: Chunk| {
+        if args.ts {
+            chunk.timestamp = ts;
+        }
+        if args.container_duration && args.max_chunk_duration() == 0 {
+            if let Some(track_duration) = track_duration {
+                chunk.duration = track_duration;
             }
+        }
+        emit(ChunkResult {
+            chunk,
+            sample_rate: input_sample_rate,
+            channels: channel_count,
+            track_duration,
+        });
+        if args.ts {
+            ts = get_current_timestamp() - overlap;
+        }
+    };
 
-            chunk_size += frame_size;
+    while let Some(samples) = input.next_samples()? {
+        let (stream_done, frame_size) = if stream_limit > 0 {
+            let remaining = stream_limit - stream_size;
+            let frame_size = samples.len() / channel_count;
+            (frame_size > remaining, frame_size.min(remaining))
+        } else {
+            (false, samples.len() / channel_count)
+        };
+        stream_size += frame_size;
 
+        if frame_size == 0 {
             if stream_done {
                 break;
+            } else {
+                continue;
             }
         }
-    }
 
-    printer.finish();
+        let chunks = printer
+            .consume(&samples[..frame_size * channel_count])
+            .context("processing audio")?;
+        for chunk in chunks {
+            print_chunk(chunk);
+        }
+
+        if stream_done {
+            break;
+        }
+    }
 
-    if chunk_size > 0 {
-        let chunk_duration =
-            (chunk_size - extra_chunk_limit) as f64 * 1.0 / f64::from(reader.sample_rate) + overlap;
-        result_printer.print_result(&printer, first_chunk, ts, chunk_duration);
+    if let Some(chunk) = printer.finish() {
+        print_chunk(chunk);
     }
 
     Ok(())
 }
 
 struct ResultPrinter<'a> {
+    show_file: bool,
     config: &'a Configuration,
     abs_ts: bool,
     raw: bool,
@@ -390,58 +977,218 @@ struct ResultPrinter<'a> {
 }
 
 impl<'a> ResultPrinter<'a> {
-    fn print_result(&self, printer: &Fingerprinter, first: bool, timestamp: f64, duration: f64) {
-        let raw_fingerprint = printer.fingerprint();
-        let fp = if self.raw {
-            if self.signed {
-                // FIXME: Use `u32.case_signed()` once it becomes stable.
-                raw_fingerprint
-                    .iter()
-                    .map(|x| *x as i32)
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>()
-                    .join(",")
-            } else {
-                raw_fingerprint
-                    .iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>()
-                    .join(",")
-            }
-        } else {
-            let compressed_fingerprint =
-                FingerprintCompressor::from(self.config).compress(raw_fingerprint);
-            BASE64_URL_SAFE_NO_PAD.encode(&compressed_fingerprint)
-        };
+    /// Prints whatever has to precede the results.
+    fn begin(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let OutputFormat::Xml = self.format {
+            writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(out, "<results>")?;
+        }
+        Ok(())
+    }
+
+    /// Prints whatever has to follow the results.
+    fn end(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let OutputFormat::Xml = self.format {
+            writeln!(out, "</results>")?;
+        }
+        Ok(())
+    }
+
+    fn print_result(
+        &self,
+        out: &mut dyn Write,
+        result: &ChunkResult,
+        file: &Path,
+        first: bool,
+    ) -> io::Result<()> {
+        let raw_fingerprint = &result.chunk.fingerprint;
+        let timestamp = result.chunk.timestamp;
+        let duration = result.chunk.duration;
 
         match self.format {
             OutputFormat::Text => {
                 if !first {
-                    println!();
+                    writeln!(out)?;
                 }
 
+                if self.show_file {
+                    writeln!(out, "FILE={}", file.display())?;
+                }
                 if self.abs_ts {
-                    println!("TIMESTAMP={timestamp:.2}");
+                    writeln!(out, "TIMESTAMP={timestamp:.2}")?;
+                }
+                writeln!(out, "DURATION={duration}")?;
+                if let Some(track_duration) = result.track_duration {
+                    writeln!(
+                        out,
+                        "TRACK_DURATION={}",
+                        round_to_hundredths(track_duration)
+                    )?;
                 }
-                println!("DURATION={duration}");
-                println!("FINGERPRINT={fp}");
+                writeln!(
+                    out,
+                    "FINGERPRINT={}",
+                    self.format_fingerprint(raw_fingerprint)
+                )?;
             }
-            OutputFormat::Json => {
-                if self.max_chunk_duration != 0 {
-                    if self.raw {
-                        println!("{{\"timestamp\": {timestamp:.2}, \"duration\": {duration:.2}, \"fingerprint\": [{fp}]}}");
-                    } else {
-                        println!("{{\"timestamp\": {timestamp:.2}, \"duration\": {duration:.2}, \"fingerprint\": \"{fp}\"}}");
-                    }
-                } else if self.raw {
-                    println!("{{\"duration\": {duration:.2}, \"fingerprint\": [{fp}]}}");
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                // Plain JSON keeps the timestamp only for chunked results, as before.
+                let with_timestamp =
+                    matches!(self.format, OutputFormat::Ndjson) || self.max_chunk_duration != 0;
+                let fingerprint = if !self.raw {
+                    JsonFingerprint::Compressed(self.format_fingerprint(raw_fingerprint))
+                } else if self.signed {
+                    JsonFingerprint::Signed(
+                        Fingerprint::new(self.config.id(), raw_fingerprint.to_vec())
+                            .to_signed_vec(),
+                    )
                 } else {
-                    println!("{{\"duration\": {duration:.2}, \"fingerprint\": \"{fp}\"}}");
+                    JsonFingerprint::Raw(raw_fingerprint)
+                };
+                let output = JsonResult {
+                    file: file.to_string_lossy(),
+                    timestamp: with_timestamp.then(|| round_to_hundredths(timestamp)),
+                    duration: round_to_hundredths(duration),
+                    track_duration: result.track_duration.map(round_to_hundredths),
+                    sample_rate: result.sample_rate,
+                    channels: result.channels,
+                    algorithm: self.config.id(),
+                    fingerprint,
+                };
+                writeln!(
+                    out,
+                    "{}",
+                    serde_json::to_string(&output).expect("serializing result")
+                )?;
+            }
+            OutputFormat::Xml => {
+                writeln!(out, "  <result>")?;
+                writeln!(
+                    out,
+                    "    <file>{}</file>",
+                    xml_escape(&file.to_string_lossy())
+                )?;
+                if self.abs_ts || self.max_chunk_duration != 0 {
+                    writeln!(out, "    <timestamp>{timestamp:.2}</timestamp>")?;
+                }
+                writeln!(out, "    <duration>{duration}</duration>")?;
+                if let Some(track_duration) = result.track_duration {
+                    writeln!(
+                        out,
+                        "    <track_duration>{}</track_duration>",
+                        round_to_hundredths(track_duration)
+                    )?;
                 }
+                writeln!(out, "    <algorithm>{}</algorithm>", self.config.id())?;
+                writeln!(
+                    out,
+                    "    <fingerprint>{}</fingerprint>",
+                    self.format_fingerprint(raw_fingerprint)
+                )?;
+                writeln!(out, "  </result>")?;
             }
             OutputFormat::Plain => {
-                println!("{fp}");
+                writeln!(out, "{}", self.format_fingerprint(raw_fingerprint))?;
             }
         }
+        Ok(())
     }
+
+    fn print_error(
+        &self,
+        out: &mut dyn Write,
+        file: &Path,
+        error: &anyhow::Error,
+    ) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                let output = JsonError {
+                    file: file.to_string_lossy(),
+                    kind: FailureKind::of(error).name(),
+                    error: format!("{error:#}"),
+                };
+                writeln!(
+                    out,
+                    "{}",
+                    serde_json::to_string(&output).expect("serializing error")
+                )?;
+            }
+            OutputFormat::Xml => {
+                writeln!(out, "  <result>")?;
+                writeln!(
+                    out,
+                    "    <file>{}</file>",
+                    xml_escape(&file.to_string_lossy())
+                )?;
+                writeln!(
+                    out,
+                    "    <error kind=\"{}\">{}</error>",
+                    FailureKind::of(error).name(),
+                    xml_escape(&format!("{error:#}"))
+                )?;
+                writeln!(out, "  </result>")?;
+            }
+            OutputFormat::Text | OutputFormat::Plain => {
+                eprintln!("ERROR: {}: {error:#}", file.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn format_fingerprint(&self, raw_fingerprint: &[u32]) -> String {
+        let fingerprint = Fingerprint::new(self.config.id(), raw_fingerprint.to_vec());
+        match (self.raw, self.signed) {
+            (true, true) => fingerprint.to_signed_string(),
+            (true, false) => format!("{fingerprint:#}"),
+            (false, _) => fingerprint.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    file: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<f64>,
+    duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track_duration: Option<f64>,
+    sample_rate: u32,
+    channels: usize,
+    algorithm: u8,
+    fingerprint: JsonFingerprint<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonFingerprint<'a> {
+    Compressed(String),
+    Raw(&'a [u32]),
+    Signed(Vec<i32>),
+}
+
+#[derive(Serialize)]
+struct JsonError<'a> {
+    file: Cow<'a, str>,
+    kind: &'static str,
+    error: String,
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn round_to_hundredths(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
 }
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::AtomicFile;
+
+/// Field storing the compressed fingerprint, as read by taggers like MusicBrainz Picard.
+const FINGERPRINT_FIELD: &str = "ACOUSTID_FINGERPRINT";
+/// Field storing the duration (in whole seconds) of the fingerprinted audio.
+const DURATION_FIELD: &str = "ACOUSTID_DURATION";
+
+const FLAC_MARKER: &[u8; 4] = b"fLaC";
+const VORBIS_COMMENT: u8 = 4;
+const LAST_BLOCK: u8 = 0x80;
+const MAX_BLOCK_SIZE: usize = (1 << 24) - 1;
+
+/// Stores the fingerprint and the duration in the Vorbis comment of a FLAC file.
+///
+/// Fields with the same names are replaced, the rest of the metadata and the audio are copied as is.
+/// The file is rewritten into a temporary file, which replaces the original one once it's complete.
+pub(crate) fn write_fingerprint(
+    path: &Path,
+    fingerprint: &str,
+    duration: f64,
+) -> anyhow::Result<()> {
+    let file = File::open(path).context("failed to open file")?;
+    let permissions = file.metadata().context("reading file")?.permissions();
+    let mut input = BufReader::new(file);
+    let mut marker = [0; 4];
+    input.read_exact(&mut marker).context("reading file")?;
+    if &marker != FLAC_MARKER {
+        anyhow::bail!("writing tags is only supported for FLAC files");
+    }
+
+    let mut blocks = read_metadata_blocks(&mut input).context("reading FLAC metadata")?;
+    let fields = [
+        (FINGERPRINT_FIELD, fingerprint.to_owned()),
+        (DURATION_FIELD, (duration.round() as u64).to_string()),
+    ];
+    match blocks.iter_mut().find(|(kind, _)| *kind == VORBIS_COMMENT) {
+        Some((_, data)) => {
+            *data = update_comment(data, &fields).context("invalid Vorbis comment")?;
+        }
+        None => {
+            // STREAMINFO must stay the first block.
+            let data = update_comment(&empty_comment(), &fields).expect("valid comment");
+            blocks.insert(1.min(blocks.len()), (VORBIS_COMMENT, data));
+        }
+    }
+
+    let mut output = AtomicFile::create(path).context("creating temporary file")?;
+    std::fs::set_permissions(&output.temp_path, permissions).context("creating temporary file")?;
+    output.write_all(FLAC_MARKER)?;
+    for (i, (kind, data)) in blocks.iter().enumerate() {
+        if data.len() > MAX_BLOCK_SIZE {
+            anyhow::bail!("metadata block is too large");
+        }
+        let last = if i + 1 == blocks.len() { LAST_BLOCK } else { 0 };
+        output.write_all(&[kind | last])?;
+        output.write_all(&(data.len() as u32).to_be_bytes()[1..])?;
+        output.write_all(data)?;
+    }
+    io::copy(&mut input, &mut output).context("copying audio")?;
+    output.commit().context("replacing file")
+}
+
+/// Reads the types and contents of all metadata blocks following the FLAC marker.
+fn read_metadata_blocks(input: &mut impl Read) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let mut blocks = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        input.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut data = vec![0; len];
+        input.read_exact(&mut data)?;
+        blocks.push((header[0] & !LAST_BLOCK, data));
+        if header[0] & LAST_BLOCK != 0 {
+            return Ok(blocks);
+        }
+    }
+}
+
+fn empty_comment() -> Vec<u8> {
+    let vendor = concat!("rusty-chromaprint ", env!("CARGO_PKG_VERSION"));
+    let mut data = Vec::new();
+    data.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    data.extend_from_slice(vendor.as_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data
+}
+
+/// Rebuilds a Vorbis comment with the given fields replacing the existing ones of the same name.
+fn update_comment(data: &[u8], fields: &[(&str, String)]) -> Option<Vec<u8>> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if data.len() < len {
+            return None;
+        }
+        let (head, tail) = data.split_at(len);
+        *data = tail;
+        Some(head)
+    }
+    fn take_len(data: &mut &[u8]) -> Option<usize> {
+        let bytes = take(data, 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    let mut data = data;
+    let vendor_len = take_len(&mut data)?;
+    let vendor = take(&mut data, vendor_len)?;
+    let count = take_len(&mut data)?;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let len = take_len(&mut data)?;
+        comments.push(take(&mut data, len)?);
+    }
+
+    // Field names are case-insensitive.
+    let replaced = |comment: &[u8]| {
+        fields.iter().any(|(name, _)| {
+            comment.len() > name.len()
+                && comment[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+                && comment[name.len()] == b'='
+        })
+    };
+    let new_comments = fields
+        .iter()
+        .map(|(name, value)| format!("{name}={value}").into_bytes())
+        .collect::<Vec<_>>();
+    let comments = comments
+        .into_iter()
+        .filter(|comment| !replaced(comment))
+        .chain(new_comments.iter().map(Vec::as_slice))
+        .collect::<Vec<_>>();
+
+    let mut output = Vec::new();
+    output.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    output.extend_from_slice(vendor);
+    output.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        output.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        output.extend_from_slice(comment);
+    }
+    // Framing bit or other data following the comments.
+    output.extend_from_slice(data);
+    Some(output)
+}